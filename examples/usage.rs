@@ -45,7 +45,7 @@ async fn example_simple() -> anyhow::Result<()> {
             col!("Employees.CountryId").eq(col!("C.CountryId")),
         )
         .Where(condition!(Employees::country_id == "Mex"))
-        .OrderBy("Employees.HireDate DESC")
+        .OrderByDesc(Employees::cols().hire_date)
         .Top(10)
         .to_list_async()
         .await?;
@@ -6,10 +6,12 @@ use syn::{parse_macro_input, Data, DeriveInput, Fields, Lit, Meta, NestedMeta};
 pub fn entity(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
     let struct_name = input.ident;
+    let vis = input.vis;
 
     // table attributes
     let mut table_name = struct_name.to_string();
     let mut table_schema: Option<String> = None;
+    let mut table_soft_delete: Option<String> = None;
     for attr in &input.attrs {
         if attr.path.is_ident("table") {
             if let Ok(Meta::List(list)) = attr.parse_meta() {
@@ -25,6 +27,13 @@ pub fn entity(input: TokenStream) -> TokenStream {
                                 table_schema = Some(s.value());
                             }
                         }
+                        NestedMeta::Meta(Meta::NameValue(nv))
+                            if nv.path.is_ident("soft_delete") =>
+                        {
+                            if let Lit::Str(s) = &nv.lit {
+                                table_soft_delete = Some(s.value());
+                            }
+                        }
                         _ => {}
                     }
                 }
@@ -42,12 +51,28 @@ pub fn entity(input: TokenStream) -> TokenStream {
     let mut assoc_consts = Vec::new();
     let mut insert_stmts = Vec::new();
     let mut update_set_stmts = Vec::new();
+    // whether an update SET stamps a value from the repository's AuditValues
+    let mut update_stamps = false;
     let mut update_where_stmts = Vec::new();
     let mut delete_where_stmts = Vec::new();
     let mut validate_stmts = Vec::new();
-    let mut first_key_col = String::new();
+    let mut key_cols = Vec::new();
+    let mut key_fields = Vec::new();
     let mut has_identity = false;
     let mut key_trait_impls = Vec::new();
+    let mut column_value_arms = Vec::new();
+    let mut relation_fields = Vec::new();
+    let mut relation_inits = Vec::new();
+    let mut column_ref_fields = Vec::new();
+    let mut column_ref_inits = Vec::new();
+    // (column, is_timestamp) of every mapped field and of the soft delete one
+    let mut column_kinds: Vec<(String, bool)> = Vec::new();
+    let mut soft_delete_column: Option<(String, bool)> = None;
+    // assignments copying the concurrency token out of an updated row
+    // column and SQL Server type of the concurrency token
+    let mut token_column: Option<(String, &str)> = None;
+    let mut token_refresh_ms = Vec::new();
+    let mut token_refresh_pg = Vec::new();
 
     if let Data::Struct(ds) = input.data {
         if let Fields::Named(fields_named) = ds.fields {
@@ -75,6 +100,19 @@ pub fn entity(input: TokenStream) -> TokenStream {
                         is_string = true;
                     }
                 }
+                // Vec<T> other than Vec<u8> is a PostgreSQL array column
+                let is_array = match &inner_ty {
+                    syn::Type::Path(tp)
+                        if tp.path.segments.len() == 1 && tp.path.segments[0].ident == "Vec" =>
+                    {
+                        !matches!(
+                            &tp.path.segments[0].arguments,
+                            syn::PathArguments::AngleBracketed(args)
+                                if matches!(args.args.first(), Some(syn::GenericArgument::Type(syn::Type::Path(e))) if e.path.is_ident("u8"))
+                        )
+                    }
+                    _ => false,
+                };
 
                 // relation handling
                 let mut is_relation = false;
@@ -83,6 +121,9 @@ pub fn entity(input: TokenStream) -> TokenStream {
                 let mut rel_table_number: Option<u32> = None;
                 let mut rel_ignore_in_update = false;
                 let mut rel_ignore_in_insert = false;
+                let mut rel_through: Option<String> = None;
+                let mut rel_local_key: Option<String> = None;
+                let mut rel_remote_key: Option<String> = None;
 
                 for attr in field.attrs.iter() {
                     if attr.path.is_ident("relation") {
@@ -100,6 +141,27 @@ pub fn entity(input: TokenStream) -> TokenStream {
                                             rel_table = s.value();
                                         }
                                     }
+                                    NestedMeta::Meta(Meta::NameValue(nv))
+                                        if nv.path.is_ident("through") =>
+                                    {
+                                        if let Lit::Str(s) = &nv.lit {
+                                            rel_through = Some(s.value());
+                                        }
+                                    }
+                                    NestedMeta::Meta(Meta::NameValue(nv))
+                                        if nv.path.is_ident("local_key") =>
+                                    {
+                                        if let Lit::Str(s) = &nv.lit {
+                                            rel_local_key = Some(s.value());
+                                        }
+                                    }
+                                    NestedMeta::Meta(Meta::NameValue(nv))
+                                        if nv.path.is_ident("remote_key") =>
+                                    {
+                                        if let Lit::Str(s) = &nv.lit {
+                                            rel_remote_key = Some(s.value());
+                                        }
+                                    }
                                     NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("table_number") => {
                                         if let Lit::Int(i) = &nv.lit {
                                             rel_table_number = i.base10_parse().ok();
@@ -123,22 +185,70 @@ pub fn entity(input: TokenStream) -> TokenStream {
                         Some(v) => quote! { Some(#v) },
                         None => quote! { None },
                     };
+                    let through_tokens = match rel_through.as_ref() {
+                        Some(s) => quote! { Some(#s) },
+                        None => quote! { None },
+                    };
+                    let local_key_tokens = match rel_local_key.as_ref() {
+                        Some(s) => quote! { Some(#s) },
+                        None => quote! { None },
+                    };
+                    let remote_key_tokens = match rel_remote_key.as_ref() {
+                        Some(s) => quote! { Some(#s) },
+                        None => quote! { None },
+                    };
                     relations.push(quote! {
                         ::rquery_orm::mapping::RelationMeta {
                             name: stringify!(#ident),
                             foreign_key: #rel_foreign_key,
                             table: #rel_table,
                             table_number: #table_num_tokens,
+                            through: #through_tokens,
+                            local_key: #local_key_tokens,
+                            remote_key: #remote_key_tokens,
                             ignore_in_update: #rel_ignore_in_update,
                             ignore_in_insert: #rel_ignore_in_insert,
                         }
                     });
+                    // Vec<U> loads as one-to-many (many-to-many with `through`),
+                    // Option<U> as many-to-one
+                    let rel_idx = relations.len() - 1;
+                    if let syn::Type::Path(tp) = &ty {
+                        let seg = tp.path.segments.last().unwrap();
+                        if let syn::PathArguments::AngleBracketed(args) = &seg.arguments {
+                            if let Some(syn::GenericArgument::Type(target)) = args.args.first() {
+                                let descriptor = if seg.ident == "Vec" && rel_through.is_some() {
+                                    Some(quote! { ::rquery_orm::relations::ManyToMany })
+                                } else if seg.ident == "Vec" {
+                                    Some(quote! { ::rquery_orm::relations::HasMany })
+                                } else if seg.ident == "Option" {
+                                    Some(quote! { ::rquery_orm::relations::BelongsTo })
+                                } else {
+                                    None
+                                };
+                                if let Some(descriptor) = descriptor {
+                                    relation_fields.push(
+                                        quote! { pub #ident: #descriptor<#struct_name, #target> },
+                                    );
+                                    relation_inits.push(quote! {
+                                        #ident: #descriptor::new(
+                                            &<#struct_name as ::rquery_orm::mapping::Entity>::table().relations[#rel_idx],
+                                            |e, v| e.#ident = v,
+                                        )
+                                    });
+                                }
+                            }
+                        }
+                    }
+                    from_ms_fields.push(quote! { #ident: Default::default() });
+                    from_pg_fields.push(quote! { #ident: Default::default() });
+                    from_ms_fields_with_prefix.push(quote! { #ident: Default::default() });
+                    from_pg_fields_with_prefix.push(quote! { #ident: Default::default() });
                     continue;
                 }
 
                 // column and key attributes
                 let mut col_name = ident.to_string();
-                let mut col_name_lit: Option<syn::LitStr> = None;
                 let mut is_key = false;
                 let mut is_identity = false;
                 let mut required = false;
@@ -157,6 +267,13 @@ pub fn entity(input: TokenStream) -> TokenStream {
                 let mut ignore_in_update = false;
                 let mut ignore_in_insert = false;
                 let mut ignore_in_delete = false;
+                let mut default_on_null = false;
+                let mut json = false;
+                let mut full_text = false;
+                let mut full_text_config: Option<String> = None;
+                let mut concurrency_token = false;
+                let mut soft_delete = false;
+                let mut audit: Option<&'static str> = None;
                 let mut key_ignore_in_update = false;
                 let mut key_ignore_in_insert = false;
 
@@ -200,16 +317,59 @@ pub fn entity(input: TokenStream) -> TokenStream {
                                             if let Lit::Bool(b) = &nv.lit { ignore_in_delete = b.value; }
                                         } else if nv.path.is_ident("ignore") {
                                             if let Lit::Bool(b) = &nv.lit { ignore = b.value; }
+                                        } else if nv.path.is_ident("default_on_null") {
+                                            if let Lit::Bool(b) = &nv.lit {
+                                                default_on_null = b.value;
+                                            }
+                                        } else if nv.path.is_ident("json") {
+                                            if let Lit::Bool(b) = &nv.lit {
+                                                json = b.value;
+                                            }
+                                        } else if nv.path.is_ident("full_text") {
+                                            match &nv.lit {
+                                                Lit::Bool(b) => full_text = b.value,
+                                                Lit::Str(s) => {
+                                                    full_text = true;
+                                                    full_text_config = Some(s.value());
+                                                }
+                                                _ => {}
+                                            }
                                         }
                                     }
                                     NestedMeta::Meta(Meta::Path(p)) => {
-                                        if p.is_ident("required") { required = true; }
-                                        else if p.is_ident("allow_null") { allow_null = true; }
-                                        else if p.is_ident("allow_empty") { allow_empty = true; }
-                                        else if p.is_ident("ignore") { ignore = true; }
-                                        else if p.is_ident("ignore_in_update") { ignore_in_update = true; }
-                                        else if p.is_ident("ignore_in_insert") { ignore_in_insert = true; }
-                                        else if p.is_ident("ignore_in_delete") { ignore_in_delete = true; }
+                                        if p.is_ident("required") {
+                                            required = true;
+                                        } else if p.is_ident("allow_null") {
+                                            allow_null = true;
+                                        } else if p.is_ident("allow_empty") {
+                                            allow_empty = true;
+                                        } else if p.is_ident("ignore") {
+                                            ignore = true;
+                                        } else if p.is_ident("ignore_in_update") {
+                                            ignore_in_update = true;
+                                        } else if p.is_ident("ignore_in_insert") {
+                                            ignore_in_insert = true;
+                                        } else if p.is_ident("ignore_in_delete") {
+                                            ignore_in_delete = true;
+                                        } else if p.is_ident("default_on_null") {
+                                            default_on_null = true;
+                                        } else if p.is_ident("json") {
+                                            json = true;
+                                        } else if p.is_ident("full_text") {
+                                            full_text = true;
+                                        } else if p.is_ident("concurrency_token") {
+                                            concurrency_token = true;
+                                        } else if p.is_ident("soft_delete") {
+                                            soft_delete = true;
+                                        } else if p.is_ident("created_at") {
+                                            audit = Some("CreatedAt");
+                                        } else if p.is_ident("updated_at") {
+                                            audit = Some("UpdatedAt");
+                                        } else if p.is_ident("created_by") {
+                                            audit = Some("CreatedBy");
+                                        } else if p.is_ident("updated_by") {
+                                            audit = Some("UpdatedBy");
+                                        }
                                     }
                                     _ => {}
                                 }
@@ -245,6 +405,10 @@ pub fn entity(input: TokenStream) -> TokenStream {
                 // literal for column name token
                 let col_name_lit_inner = syn::LitStr::new(&col_name, proc_macro2::Span::call_site());
 
+                // created_* columns are never updated
+                let ignore_in_update =
+                    ignore_in_update || matches!(audit, Some("CreatedAt") | Some("CreatedBy"));
+
                 let max_length_token = match max_length { Some(v) => quote! { Some(#v) }, None => quote! { None } };
                 let min_length_token = match min_length { Some(v) => quote! { Some(#v) }, None => quote! { None } };
                 let regex_token = match regex.as_ref() { Some(s) => quote! { Some(#s) }, None => quote! { None } };
@@ -254,6 +418,17 @@ pub fn entity(input: TokenStream) -> TokenStream {
                 let err_allow_null_token = match err_allow_null.as_ref() { Some(s) => quote! { Some(#s) }, None => quote! { None } };
                 let err_allow_empty_token = match err_allow_empty.as_ref() { Some(s) => quote! { Some(#s) }, None => quote! { None } };
                 let err_regex_token = match err_regex.as_ref() { Some(s) => quote! { Some(#s) }, None => quote! { None } };
+                let audit_token = match audit {
+                    Some(kind) => {
+                        let kind = syn::Ident::new(kind, proc_macro2::Span::call_site());
+                        quote! { Some(::rquery_orm::mapping::AuditColumn::#kind) }
+                    }
+                    None => quote! { None },
+                };
+                let full_text_config_token = match full_text_config.as_ref() {
+                    Some(s) => quote! { Some(#s) },
+                    None => quote! { None },
+                };
 
                 columns.push(quote! {
                     ::rquery_orm::mapping::ColumnMeta {
@@ -274,6 +449,11 @@ pub fn entity(input: TokenStream) -> TokenStream {
                         ignore_in_update: #ignore_in_update,
                         ignore_in_insert: #ignore_in_insert,
                         ignore_in_delete: #ignore_in_delete,
+                        default_on_null: #default_on_null,
+                        full_text: #full_text,
+                        full_text_config: #full_text_config_token,
+                        concurrency_token: #concurrency_token,
+                        audit: #audit_token,
                     }
                 });
 
@@ -286,8 +466,9 @@ pub fn entity(input: TokenStream) -> TokenStream {
                             ignore_in_insert: #key_ignore_in_insert,
                         }
                     });
-                    if first_key_col.is_empty() {
-                        first_key_col = col_name.clone();
+                    key_cols.push(col_name.clone());
+                    key_fields.push((ident.clone(), ty.clone()));
+                    if key_fields.len() == 1 {
                         if let syn::Type::Path(tp) = &ty {
                             if tp.qself.is_none() {
                                 if tp.path.is_ident("i32") {
@@ -306,51 +487,232 @@ pub fn entity(input: TokenStream) -> TokenStream {
                     if is_identity { has_identity = true; }
                 }
 
+                // json columns are serialized with serde instead of ToParam; a
+                // value serde cannot serialize fails the insert or update
+                if json && (is_key || concurrency_token) {
+                    return syn::Error::new_spanned(
+                        ident,
+                        "json columns cannot be keys or concurrency tokens",
+                    )
+                    .to_compile_error()
+                    .into();
+                }
+                let to_param = if json {
+                    quote! { ::rquery_orm::mapping::json_param(#col_name_lit_inner, &self.#ident)? }
+                } else {
+                    quote! { self.#ident.clone().to_param() }
+                };
+                if json {
+                    column_value_arms.push(quote! { #col_name_lit_inner => ::rquery_orm::mapping::json_param(#col_name_lit_inner, &self.#ident).ok(), });
+                } else {
+                    column_value_arms.push(quote! { #col_name_lit_inner => Some(#to_param), });
+                }
+                // audit columns take the repository's clock and user when given
+                let stamp = match audit {
+                    Some("CreatedAt") | Some("UpdatedAt") => {
+                        quote! { match audit { Some(a) => a.now.to_param(), None => #to_param } }
+                    }
+                    Some(_) => {
+                        quote! { match audit.and_then(|a| a.user.clone()) { Some(v) => v, None => #to_param } }
+                    }
+                    None => {
+                        quote! { match audit.and_then(|a| a.column(#col_name_lit_inner)) { Some(v) => v, None => #to_param } }
+                    }
+                };
+
                 // push associated const for this column
                 assoc_consts.push(quote! { pub const #ident: &'static str = #col_name_lit_inner; });
+                column_ref_fields
+                    .push(quote! { pub #ident: ::rquery_orm::query::Column<#struct_name, #ty> });
+                column_ref_inits.push(quote! { #ident: ::rquery_orm::query::Column::new(qualifier, #col_name_lit_inner) });
 
-                if is_option {
-                    if is_string {
-                        from_ms_fields.push(quote! { #ident: row.try_get::<&str, _>(#col_name_lit_inner)?.map(|v| v.to_string()) });
-                        from_ms_fields_with_prefix.push(quote! { #ident: { let k = format!("{}_{}", prefix, #col_name_lit_inner); row.try_get::<&str, _>(k.as_str())?.map(|v| v.to_string()) } });
+                // row mapping: NULLs and missing columns surface as MappingError
+                let field_name = ident.to_string();
+                let prefixed_col =
+                    quote! { format!("{}_{}", prefix, #col_name_lit_inner).as_str() };
+                // SQL Server hands out text and binary borrowed from the row
+                let is_bytes = !is_array
+                    && matches!(&inner_ty, syn::Type::Path(tp) if tp.path.segments.len() == 1 && tp.path.segments[0].ident == "Vec");
+                let (borrow_ty, to_owned) = if is_bytes {
+                    (quote! { &[u8] }, quote! { to_vec })
+                } else {
+                    (quote! { &str }, quote! { to_string })
+                };
+                let (ms_get, ms_get_with) = if is_array || json {
+                    if is_option {
+                        (
+                            quote! { ::rquery_orm::mapping::ms_json_optional::<#inner_ty>(row, ENTITY, #field_name, #col_name_lit_inner)? },
+                            quote! { ::rquery_orm::mapping::ms_json_optional::<#inner_ty>(row, ENTITY, #field_name, #prefixed_col)? },
+                        )
+                    } else if default_on_null {
+                        (
+                            quote! { ::rquery_orm::mapping::ms_json_optional::<#ty>(row, ENTITY, #field_name, #col_name_lit_inner)?.unwrap_or_default() },
+                            quote! { ::rquery_orm::mapping::ms_json_optional::<#ty>(row, ENTITY, #field_name, #prefixed_col)?.unwrap_or_default() },
+                        )
+                    } else {
+                        (
+                            quote! { ::rquery_orm::mapping::ms_json_required::<#ty>(row, ENTITY, #field_name, #col_name_lit_inner)? },
+                            quote! { ::rquery_orm::mapping::ms_json_required::<#ty>(row, ENTITY, #field_name, #prefixed_col)? },
+                        )
+                    }
+                } else if is_option {
+                    if is_string || is_bytes {
+                        (
+                            quote! { ::rquery_orm::mapping::ms_optional::<#borrow_ty>(row, ENTITY, #field_name, #col_name_lit_inner)?.map(|v| v.#to_owned()) },
+                            quote! { ::rquery_orm::mapping::ms_optional::<#borrow_ty>(row, ENTITY, #field_name, #prefixed_col)?.map(|v| v.#to_owned()) },
+                        )
+                    } else {
+                        (
+                            quote! { ::rquery_orm::mapping::ms_optional::<#inner_ty>(row, ENTITY, #field_name, #col_name_lit_inner)? },
+                            quote! { ::rquery_orm::mapping::ms_optional::<#inner_ty>(row, ENTITY, #field_name, #prefixed_col)? },
+                        )
+                    }
+                } else if default_on_null {
+                    if is_string || is_bytes {
+                        (
+                            quote! { ::rquery_orm::mapping::ms_optional::<#borrow_ty>(row, ENTITY, #field_name, #col_name_lit_inner)?.map(|v| v.#to_owned()).unwrap_or_default() },
+                            quote! { ::rquery_orm::mapping::ms_optional::<#borrow_ty>(row, ENTITY, #field_name, #prefixed_col)?.map(|v| v.#to_owned()).unwrap_or_default() },
+                        )
                     } else {
-                        from_ms_fields.push(quote! { #ident: row.try_get::<#inner_ty, _>(#col_name_lit_inner)? });
-                        from_ms_fields_with_prefix.push(quote! { #ident: { let k = format!("{}_{}", prefix, #col_name_lit_inner); row.try_get::<#inner_ty, _>(k.as_str())? } });
+                        (
+                            quote! { ::rquery_orm::mapping::ms_optional::<#ty>(row, ENTITY, #field_name, #col_name_lit_inner)?.unwrap_or_default() },
+                            quote! { ::rquery_orm::mapping::ms_optional::<#ty>(row, ENTITY, #field_name, #prefixed_col)?.unwrap_or_default() },
+                        )
                     }
-                    from_pg_fields.push(quote! { #ident: row.try_get(#col_name_lit_inner)? });
-                    from_pg_fields_with_prefix.push(quote! { #ident: { let k = format!("{}_{}", prefix, #col_name_lit_inner); row.try_get(k.as_str())? } });
+                } else if is_string || is_bytes {
+                    (
+                        quote! { ::rquery_orm::mapping::ms_required::<#borrow_ty>(row, ENTITY, #field_name, #col_name_lit_inner)?.#to_owned() },
+                        quote! { ::rquery_orm::mapping::ms_required::<#borrow_ty>(row, ENTITY, #field_name, #prefixed_col)?.#to_owned() },
+                    )
                 } else {
-                    if is_string {
-                        from_ms_fields.push(quote! { #ident: row.try_get::<&str, _>(#col_name_lit_inner)?.unwrap().to_string() });
-                        from_ms_fields_with_prefix.push(quote! { #ident: { let k = format!("{}_{}", prefix, #col_name_lit_inner); row.try_get::<&str, _>(k.as_str())?.unwrap().to_string() } });
+                    (
+                        quote! { ::rquery_orm::mapping::ms_required::<#ty>(row, ENTITY, #field_name, #col_name_lit_inner)? },
+                        quote! { ::rquery_orm::mapping::ms_required::<#ty>(row, ENTITY, #field_name, #prefixed_col)? },
+                    )
+                };
+                let (pg_get, pg_get_with) = if json {
+                    if is_option {
+                        (
+                            quote! { ::rquery_orm::mapping::pg_json_optional::<#inner_ty>(row, ENTITY, #field_name, #col_name_lit_inner)? },
+                            quote! { ::rquery_orm::mapping::pg_json_optional::<#inner_ty>(row, ENTITY, #field_name, #prefixed_col)? },
+                        )
+                    } else if default_on_null {
+                        (
+                            quote! { ::rquery_orm::mapping::pg_json_optional::<#ty>(row, ENTITY, #field_name, #col_name_lit_inner)?.unwrap_or_default() },
+                            quote! { ::rquery_orm::mapping::pg_json_optional::<#ty>(row, ENTITY, #field_name, #prefixed_col)?.unwrap_or_default() },
+                        )
                     } else {
-                        from_ms_fields.push(quote! { #ident: row.try_get::<#inner_ty, _>(#col_name_lit_inner)?.unwrap() });
-                        from_ms_fields_with_prefix.push(quote! { #ident: { let k = format!("{}_{}", prefix, #col_name_lit_inner); row.try_get::<#inner_ty, _>(k.as_str())?.unwrap() } });
+                        (
+                            quote! { ::rquery_orm::mapping::pg_json_required::<#ty>(row, ENTITY, #field_name, #col_name_lit_inner)? },
+                            quote! { ::rquery_orm::mapping::pg_json_required::<#ty>(row, ENTITY, #field_name, #prefixed_col)? },
+                        )
                     }
-                    from_pg_fields.push(quote! { #ident: row.try_get(#col_name_lit_inner)? });
-                    from_pg_fields_with_prefix.push(quote! { #ident: { let k = format!("{}_{}", prefix, #col_name_lit_inner); row.try_get(k.as_str())? } });
+                } else if is_option {
+                    (
+                        quote! { ::rquery_orm::mapping::pg_optional::<#inner_ty>(row, ENTITY, #field_name, #col_name_lit_inner)? },
+                        quote! { ::rquery_orm::mapping::pg_optional::<#inner_ty>(row, ENTITY, #field_name, #prefixed_col)? },
+                    )
+                } else if default_on_null {
+                    (
+                        quote! { ::rquery_orm::mapping::pg_optional::<#ty>(row, ENTITY, #field_name, #col_name_lit_inner)?.unwrap_or_default() },
+                        quote! { ::rquery_orm::mapping::pg_optional::<#ty>(row, ENTITY, #field_name, #prefixed_col)?.unwrap_or_default() },
+                    )
+                } else {
+                    (
+                        quote! { ::rquery_orm::mapping::pg_required::<#ty>(row, ENTITY, #field_name, #col_name_lit_inner)? },
+                        quote! { ::rquery_orm::mapping::pg_required::<#ty>(row, ENTITY, #field_name, #prefixed_col)? },
+                    )
+                };
+                from_ms_fields.push(quote! { #ident: #ms_get });
+                from_ms_fields_with_prefix.push(quote! { #ident: #ms_get_with });
+                from_pg_fields.push(quote! { #ident: #pg_get });
+                from_pg_fields_with_prefix.push(quote! { #ident: #pg_get_with });
+
+                // soft delete marks rows with a timestamp or, for any other
+                // type, a boolean flag
+                let is_timestamp = matches!(&inner_ty, syn::Type::Path(tp)
+                    if tp.path.segments.last().map_or(false, |s| s.ident == "NaiveDateTime" || s.ident == "DateTime"));
+                column_kinds.push((col_name.clone(), is_timestamp));
+                if soft_delete {
+                    soft_delete_column = Some((col_name.clone(), is_timestamp));
                 }
 
-                if !is_identity && !ignore && !ignore_in_insert && !key_ignore_in_insert {
+                // A Vec<u8> or Option<Vec<u8>> token is a SQL Server rowversion,
+                // written by the server; any other token is an integer version
+                // bumped on update.
+                let is_rowversion = concurrency_token && is_bytes;
+                if concurrency_token {
+                    let int_ty = match &inner_ty {
+                        syn::Type::Path(tp) => tp.path.segments.last().map(|s| s.ident.to_string()),
+                        _ => None,
+                    };
+                    let sql_ty = match int_ty.as_deref() {
+                        _ if is_bytes => "BINARY(8)",
+                        Some("i16") => "SMALLINT",
+                        Some("i32") => "INT",
+                        Some("i64") => "BIGINT",
+                        _ => {
+                            return syn::Error::new_spanned(
+                                ident,
+                                "concurrency tokens must be i16, i32, i64 or Vec<u8>",
+                            )
+                            .to_compile_error()
+                            .into();
+                        }
+                    };
+                    token_column.get_or_insert((col_name.clone(), sql_ty));
+                    token_refresh_ms.push(quote! { self.#ident = #ms_get; });
+                    token_refresh_pg.push(quote! { self.#ident = #pg_get; });
+                }
+
+                if !is_identity
+                    && !ignore
+                    && !ignore_in_insert
+                    && !key_ignore_in_insert
+                    && !is_rowversion
+                {
                     insert_stmts.push(quote! {
                         cols.push(#col_name);
                         vals.push(match style {
                             ::rquery_orm::query::PlaceholderStyle::AtP => format!("@P{}", idx),
                             ::rquery_orm::query::PlaceholderStyle::Dollar => format!("${}", idx),
                         });
-                        params.push(self.#ident.clone().to_param());
+                        params.push(#stamp);
                         idx += 1;
                     });
                 }
 
-                if !is_key {
+                if concurrency_token {
+                    if !is_rowversion {
+                        update_set_stmts.push(quote! {
+                            sets.push(format!("{} = {} + 1", #col_name, #col_name));
+                        });
+                    }
+                    update_where_stmts.push(quote! {
+                        wheres.push(format!("{} = {}", #col_name, match style {
+                            ::rquery_orm::query::PlaceholderStyle::AtP => format!("@P{}", idx),
+                            ::rquery_orm::query::PlaceholderStyle::Dollar => format!("${}", idx),
+                        }));
+                        params.push(#to_param);
+                        idx += 1;
+                    });
+                    delete_where_stmts.push(quote! {
+                        wheres.push(format!("{} = {}", #col_name, match style {
+                            ::rquery_orm::query::PlaceholderStyle::AtP => format!("@P{}", idx),
+                            ::rquery_orm::query::PlaceholderStyle::Dollar => format!("${}", idx),
+                        }));
+                        params.push(#to_param);
+                        idx += 1;
+                    });
+                } else if !is_key {
                     if !ignore && !ignore_in_update {
+                        update_stamps = true;
                         update_set_stmts.push(quote! {
                             sets.push(format!("{} = {}", #col_name, match style {
                                 ::rquery_orm::query::PlaceholderStyle::AtP => format!("@P{}", idx),
                                 ::rquery_orm::query::PlaceholderStyle::Dollar => format!("${}", idx),
                             }));
-                            params.push(self.#ident.clone().to_param());
+                            params.push(#stamp);
                             idx += 1;
                         });
                     }
@@ -360,7 +722,7 @@ pub fn entity(input: TokenStream) -> TokenStream {
                             ::rquery_orm::query::PlaceholderStyle::AtP => format!("@P{}", idx),
                             ::rquery_orm::query::PlaceholderStyle::Dollar => format!("${}", idx),
                         }));
-                        params.push(self.#ident.clone().to_param());
+                        params.push(#to_param);
                         idx += 1;
                     });
                     delete_where_stmts.push(quote! {
@@ -368,7 +730,7 @@ pub fn entity(input: TokenStream) -> TokenStream {
                             ::rquery_orm::query::PlaceholderStyle::AtP => format!("@P{}", idx),
                             ::rquery_orm::query::PlaceholderStyle::Dollar => format!("${}", idx),
                         }));
-                        params.push(self.#ident.clone().to_param());
+                        params.push(#to_param);
                         idx += 1;
                     });
                 }
@@ -480,7 +842,116 @@ pub fn entity(input: TokenStream) -> TokenStream {
         }
         None => quote! { None },
     };
-    let first_key_col_literal = first_key_col.clone();
+    // `#[table(soft_delete = "..")]` may name a column that is not mapped;
+    // it is then treated as a flag
+    let soft_delete = soft_delete_column.or_else(|| {
+        table_soft_delete.map(|c| {
+            let is_timestamp = column_kinds.iter().any(|(n, ts)| *n == c && *ts);
+            (c, is_timestamp)
+        })
+    });
+    let soft_delete_tokens = match soft_delete {
+        Some((column, is_timestamp)) => {
+            let column = syn::LitStr::new(&column, proc_macro2::Span::call_site());
+            let kind = if is_timestamp {
+                quote! { ::rquery_orm::mapping::SoftDeleteKind::Timestamp }
+            } else {
+                quote! { ::rquery_orm::mapping::SoftDeleteKind::Flag }
+            };
+            quote! { Some(::rquery_orm::mapping::SoftDeleteMeta { column: #column, kind: #kind }) }
+        }
+        None => quote! { None },
+    };
+    // single-value key traits only make sense when the key has one column
+    if key_fields.len() > 1 {
+        key_trait_impls.clear();
+        let idents = key_fields.iter().map(|(i, _)| i);
+        let tys = key_fields.iter().map(|(_, t)| t);
+        key_trait_impls.push(quote! {
+            impl ::rquery_orm::mapping::KeyAsTuple for #struct_name {
+                type Key = (#(#tys),*);
+                fn key(&self) -> Self::Key { (#(self.#idents.clone()),*) }
+            }
+        });
+    }
+
+    let relations_impl = if relation_fields.is_empty() {
+        quote! {}
+    } else {
+        let relations_name =
+            syn::Ident::new(&format!("{}Relations", struct_name), struct_name.span());
+        quote! {
+            #vis struct #relations_name {
+                #(#relation_fields),*
+            }
+
+            impl ::rquery_orm::mapping::HasRelations for #struct_name {
+                type Relations = #relations_name;
+                fn relations() -> Self::Relations {
+                    #relations_name { #(#relation_inits),* }
+                }
+            }
+        }
+    };
+
+    let columns_name = syn::Ident::new(&format!("{}Columns", struct_name), struct_name.span());
+    let columns_impl = quote! {
+        #vis struct #columns_name {
+            #(#column_ref_fields),*
+        }
+
+        impl ::rquery_orm::mapping::HasColumns for #struct_name {
+            type Columns = #columns_name;
+            fn columns(qualifier: &'static str) -> Self::Columns {
+                #columns_name { #(#column_ref_inits),* }
+            }
+        }
+    };
+
+    // the AuditValues parameter is only bound when a statement stamps it
+    let insert_audit = if insert_stmts.is_empty() {
+        quote! { _audit }
+    } else {
+        quote! { audit }
+    };
+    let update_audit = if update_stamps {
+        quote! { audit }
+    } else {
+        quote! { _audit }
+    };
+
+    // only an entity with a concurrency token can read it back from an update
+    let update_returning = token_column.map(|(column, sql_ty)| {
+        let ms_sql = format!(
+            "DECLARE @token TABLE ({0} {1}); UPDATE {{}} SET {{}} OUTPUT inserted.{0} INTO @token WHERE {{}}; SELECT {0} FROM @token",
+            column, sql_ty
+        );
+        let pg_sql = format!("UPDATE {{}} SET {{}} WHERE {{}} RETURNING {}", column);
+        quote! {
+            fn build_update_returning(&self, style: ::rquery_orm::query::PlaceholderStyle, #update_audit: Option<&::rquery_orm::audit::AuditValues>, filters: &[(&'static str, ::rquery_orm::query::SqlParam)]) -> anyhow::Result<(String, Vec<::rquery_orm::query::SqlParam>)> {
+                use ::rquery_orm::query::ToParam;
+                let mut sets: Vec<String> = Vec::new();
+                let mut wheres: Vec<String> = Vec::new();
+                let mut params: Vec<::rquery_orm::query::SqlParam> = Vec::new();
+                let mut idx = 1;
+                #(#update_set_stmts)*
+                #(#update_where_stmts)*
+                for (column, value) in filters {
+                    wheres.push(format!("{} = {}", column, match style {
+                        ::rquery_orm::query::PlaceholderStyle::AtP => format!("@P{}", idx),
+                        ::rquery_orm::query::PlaceholderStyle::Dollar => format!("${}", idx),
+                    }));
+                    params.push(value.clone());
+                    idx += 1;
+                }
+                let sql = match style {
+                    ::rquery_orm::query::PlaceholderStyle::AtP => format!(#ms_sql, #table_name, sets.join(", "), wheres.join(" AND ")),
+                    ::rquery_orm::query::PlaceholderStyle::Dollar => format!(#pg_sql, #table_name, sets.join(", "), wheres.join(" AND ")),
+                };
+                Ok((sql, params))
+            }
+        }
+    });
 
     let expanded = quote! {
         const COLUMNS: &[::rquery_orm::mapping::ColumnMeta] = &[#(#columns),*];
@@ -492,6 +963,7 @@ pub fn entity(input: TokenStream) -> TokenStream {
             columns: COLUMNS,
             keys: KEYS,
             relations: RELATIONS,
+            soft_delete: #soft_delete_tokens,
         };
 
         impl ::rquery_orm::mapping::Entity for #struct_name {
@@ -502,18 +974,22 @@ pub fn entity(input: TokenStream) -> TokenStream {
 
         impl ::rquery_orm::mapping::FromRowNamed for #struct_name {
             fn from_row_ms(row: &tiberius::Row) -> anyhow::Result<Self> {
+                const ENTITY: &str = stringify!(#struct_name);
                 Ok(Self { #(#from_ms_fields),* })
             }
             fn from_row_pg(row: &tokio_postgres::Row) -> anyhow::Result<Self> {
+                const ENTITY: &str = stringify!(#struct_name);
                 Ok(Self { #(#from_pg_fields),* })
             }
         }
 
         impl ::rquery_orm::mapping::FromRowWithPrefix for #struct_name {
             fn from_row_ms_with(row: &tiberius::Row, prefix: &str) -> anyhow::Result<Self> {
+                const ENTITY: &str = stringify!(#struct_name);
                 Ok(Self { #(#from_ms_fields_with_prefix),* })
             }
             fn from_row_pg_with(row: &tokio_postgres::Row, prefix: &str) -> anyhow::Result<Self> {
+                const ENTITY: &str = stringify!(#struct_name);
                 Ok(Self { #(#from_pg_fields_with_prefix),* })
             }
         }
@@ -527,55 +1003,99 @@ pub fn entity(input: TokenStream) -> TokenStream {
         }
 
         impl ::rquery_orm::mapping::Persistable for #struct_name {
-            fn build_insert(&self, style: ::rquery_orm::query::PlaceholderStyle) -> (String, Vec<::rquery_orm::query::SqlParam>, bool) {
+            fn build_insert(&self, style: ::rquery_orm::query::PlaceholderStyle) -> anyhow::Result<(String, Vec<::rquery_orm::query::SqlParam>, bool)> {
+                self.build_insert_audited(style, None)
+            }
+
+            fn build_insert_audited(&self, style: ::rquery_orm::query::PlaceholderStyle, #insert_audit: Option<&::rquery_orm::audit::AuditValues>) -> anyhow::Result<(String, Vec<::rquery_orm::query::SqlParam>, bool)> {
                 use ::rquery_orm::query::ToParam;
-                let mut cols = Vec::new();
-                let mut vals = Vec::new();
-                let mut params = Vec::new();
+                let mut cols: Vec<&str> = Vec::new();
+                let mut vals: Vec<String> = Vec::new();
+                let mut params: Vec<::rquery_orm::query::SqlParam> = Vec::new();
                 let mut idx = 1;
                 #(#insert_stmts)*
                 let sql = format!("INSERT INTO {} ({}) VALUES ({})", #table_name, cols.join(", "), vals.join(", "));
-                (sql, params, #has_identity)
+                Ok((sql, params, #has_identity))
             }
 
-            fn build_update(&self, style: ::rquery_orm::query::PlaceholderStyle) -> (String, Vec<::rquery_orm::query::SqlParam>) {
+            fn build_update(&self, style: ::rquery_orm::query::PlaceholderStyle) -> anyhow::Result<(String, Vec<::rquery_orm::query::SqlParam>)> {
+                self.build_update_audited(style, None)
+            }
+
+            fn build_update_audited(&self, style: ::rquery_orm::query::PlaceholderStyle, #update_audit: Option<&::rquery_orm::audit::AuditValues>) -> anyhow::Result<(String, Vec<::rquery_orm::query::SqlParam>)> {
                 use ::rquery_orm::query::ToParam;
-                let mut sets = Vec::new();
-                let mut wheres = Vec::new();
-                let mut params = Vec::new();
+                let mut sets: Vec<String> = Vec::new();
+                let mut wheres: Vec<String> = Vec::new();
+                let mut params: Vec<::rquery_orm::query::SqlParam> = Vec::new();
                 let mut idx = 1;
                 #(#update_set_stmts)*
                 #(#update_where_stmts)*
                 let sql = format!("UPDATE {} SET {} WHERE {}", #table_name, sets.join(", "), wheres.join(" AND "));
-                (sql, params)
+                Ok((sql, params))
             }
 
+            #update_returning
+
             fn build_delete(&self, style: ::rquery_orm::query::PlaceholderStyle) -> (String, Vec<::rquery_orm::query::SqlParam>) {
                 use ::rquery_orm::query::ToParam;
-                let mut wheres = Vec::new();
-                let mut params = Vec::new();
+                let mut wheres: Vec<String> = Vec::new();
+                let mut params: Vec<::rquery_orm::query::SqlParam> = Vec::new();
                 let mut idx = 1;
                 #(#delete_where_stmts)*
                 let sql = format!("DELETE FROM {} WHERE {}", #table_name, wheres.join(" AND "));
                 (sql, params)
             }
 
-            fn build_delete_by_key(key: ::rquery_orm::query::SqlParam, style: ::rquery_orm::query::PlaceholderStyle) -> (String, Vec<::rquery_orm::query::SqlParam>) {
-                let placeholder = match style {
-                    ::rquery_orm::query::PlaceholderStyle::AtP => "@P1".to_string(),
-                    ::rquery_orm::query::PlaceholderStyle::Dollar => "$1".to_string(),
-                };
-                let sql = format!("DELETE FROM {} WHERE {} = {}", #table_name, #first_key_col_literal, placeholder);
-                (sql, vec![key])
+            fn build_delete_by_key(key: Vec<::rquery_orm::query::SqlParam>, style: ::rquery_orm::query::PlaceholderStyle) -> (String, Vec<::rquery_orm::query::SqlParam>) {
+                let key_cols: &[&str] = &[#(#key_cols),*];
+                let wheres: Vec<String> = key_cols
+                    .iter()
+                    .enumerate()
+                    .map(|(i, c)| format!("{} = {}", c, match style {
+                        ::rquery_orm::query::PlaceholderStyle::AtP => format!("@P{}", i + 1),
+                        ::rquery_orm::query::PlaceholderStyle::Dollar => format!("${}", i + 1),
+                    }))
+                    .collect();
+                let sql = format!("DELETE FROM {} WHERE {}", #table_name, wheres.join(" AND "));
+                (sql, key)
+            }
+
+            fn column_value(&self, column: &str) -> Option<::rquery_orm::query::SqlParam> {
+                use ::rquery_orm::query::ToParam;
+                match column {
+                    #(#column_value_arms)*
+                    _ => None,
+                }
+            }
+
+            fn refresh_token_ms(&mut self, row: &tiberius::Row) -> anyhow::Result<()> {
+                const ENTITY: &str = stringify!(#struct_name);
+                #(#token_refresh_ms)*
+                Ok(())
+            }
+
+            fn refresh_token_pg(&mut self, row: &tokio_postgres::Row) -> anyhow::Result<()> {
+                const ENTITY: &str = stringify!(#struct_name);
+                #(#token_refresh_pg)*
+                Ok(())
             }
         }
 
+        #[allow(non_upper_case_globals)]
         impl #struct_name {
             pub const TABLE: &'static str = #table_name_lit;
             #(#assoc_consts)*
+
+            pub fn cols() -> #columns_name {
+                <Self as ::rquery_orm::mapping::HasColumns>::columns(Self::TABLE)
+            }
         }
 
         #(#key_trait_impls)*
+
+        #relations_impl
+
+        #columns_impl
     };
 
     TokenStream::from(expanded)
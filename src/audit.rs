@@ -0,0 +1,71 @@
+use std::sync::Arc;
+
+use crate::query::SqlParam;
+
+// Source of the timestamps written to `created_at`/`updated_at` columns.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> chrono::NaiveDateTime;
+}
+
+// Current UTC time.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> chrono::NaiveDateTime {
+        chrono::Utc::now().naive_utc()
+    }
+}
+
+impl<F> Clock for F
+where
+    F: Fn() -> chrono::NaiveDateTime + Send + Sync,
+{
+    fn now(&self) -> chrono::NaiveDateTime {
+        self()
+    }
+}
+
+// Value written to `created_by`/`updated_by` columns; `None` keeps whatever
+// the entity holds.
+pub trait CurrentUser: Send + Sync {
+    fn current_user(&self) -> Option<SqlParam>;
+}
+
+impl<F> CurrentUser for F
+where
+    F: Fn() -> Option<SqlParam> + Send + Sync,
+{
+    fn current_user(&self) -> Option<SqlParam> {
+        self()
+    }
+}
+
+// Values stamped into the audit columns of one insert or update, plus
+// the columns pinned by the repository's query filters (e.g. TenantId).
+#[derive(Clone, Debug)]
+pub struct AuditValues {
+    pub now: chrono::NaiveDateTime,
+    pub user: Option<SqlParam>,
+    pub columns: Vec<(&'static str, SqlParam)>,
+}
+
+impl AuditValues {
+    pub(crate) fn capture(
+        clock: &Arc<dyn Clock>,
+        user: &Option<Arc<dyn CurrentUser>>,
+        columns: Vec<(&'static str, SqlParam)>,
+    ) -> Self {
+        Self {
+            now: clock.now(),
+            user: user.as_ref().and_then(|u| u.current_user()),
+            columns,
+        }
+    }
+
+    pub fn column(&self, name: &str) -> Option<SqlParam> {
+        self.columns
+            .iter()
+            .find(|(c, _)| *c == name)
+            .map(|(_, v)| v.clone())
+    }
+}
@@ -0,0 +1,137 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+
+use crate::db::{DatabaseRef, DbRows};
+use crate::mapping::{Entity, FromRowNamed};
+use crate::query::{fit_in_lists, PlaceholderStyle, Query, SqlParam};
+
+// Several queries sent in one round trip. Each `add` appends a query and
+// `execute_async` returns a tuple with one `Vec` per query, in order:
+//
+//     let (employees, countries) = db.batch().add(q1).add(q2).execute_async().await?;
+pub struct Batch<Q> {
+    db: Arc<DatabaseRef>,
+    queries: Q,
+}
+
+impl DatabaseRef {
+    pub fn batch(&self) -> Batch<()> {
+        Batch {
+            db: Arc::new(self.clone()),
+            queries: (),
+        }
+    }
+}
+
+// `Batch` is not an arithmetic type; `add` just appends a query.
+#[allow(clippy::should_implement_trait)]
+impl Batch<()> {
+    pub fn add<A>(self, query: Query<A>) -> Batch<(Query<A>,)>
+    where
+        A: Entity + FromRowNamed,
+    {
+        Batch {
+            db: self.db,
+            queries: (query,),
+        }
+    }
+}
+
+// SQL Server numbers placeholders across the whole batch, PostgreSQL runs
+// each statement on its own.
+fn statement<T>(
+    query: &Query<T>,
+    db: &DatabaseRef,
+    offset: &mut usize,
+) -> Result<(String, Vec<SqlParam>)>
+where
+    T: Entity + FromRowNamed,
+{
+    query.check()?;
+    let style = db.placeholder_style();
+    if query.style() != style {
+        return Err(anyhow!(
+            "batched query uses a different placeholder style than the connection"
+        ));
+    }
+    let mut params = Vec::new();
+    let sql = match style {
+        PlaceholderStyle::AtP => {
+            // pad with the earlier statements' parameters so numbering continues
+            params.resize(*offset, SqlParam::Null);
+            let sql = fit_in_lists(style, &mut params, |params| query.statement_sql(params));
+            params.drain(..*offset);
+            *offset += params.len();
+            sql
+        }
+        PlaceholderStyle::Dollar => query.statement_sql(&mut params),
+    };
+    Ok((sql, params))
+}
+
+macro_rules! impl_batch_add {
+    ($($name:ident $idx:tt),+ ; $next:ident) => {
+        #[allow(clippy::should_implement_trait)]
+        impl<$($name),+> Batch<($(Query<$name>,)+)>
+        where
+            $($name: Entity + FromRowNamed,)+
+        {
+            pub fn add<$next>(self, query: Query<$next>) -> Batch<($(Query<$name>,)+ Query<$next>,)>
+            where
+                $next: Entity + FromRowNamed,
+            {
+                Batch {
+                    db: self.db,
+                    queries: ($(self.queries.$idx,)+ query,),
+                }
+            }
+        }
+    };
+}
+
+impl_batch_add!(A 0; B);
+impl_batch_add!(A 0, B 1; C);
+impl_batch_add!(A 0, B 1, C 2; D);
+impl_batch_add!(A 0, B 1, C 2, D 3; E);
+impl_batch_add!(A 0, B 1, C 2, D 3, E 4; F);
+
+macro_rules! impl_batch_execute {
+    ($count:literal; $($name:ident $idx:tt),+) => {
+        impl<$($name),+> Batch<($(Query<$name>,)+)>
+        where
+            $($name: Entity + FromRowNamed + Send,)+
+        {
+            pub fn to_sql(&self) -> Result<Vec<(String, Vec<SqlParam>)>> {
+                let mut offset = 0;
+                Ok(vec![$(statement(&self.queries.$idx, &self.db, &mut offset)?),+])
+            }
+
+            pub async fn execute_async(self) -> Result<($(Vec<$name>,)+)> {
+                let statements = self.to_sql()?;
+                let sets = self.db.query_batch(&statements).await?;
+                if sets.len() != $count {
+                    return Err(anyhow!(
+                        "batch of {} queries returned {} result sets",
+                        $count,
+                        sets.len()
+                    ));
+                }
+                let mut sets = sets.into_iter();
+                Ok(($(
+                    self.queries
+                        .$idx
+                        .materialize(&self.db, sets.next().unwrap_or(DbRows::Postgres(Vec::new())))
+                        .await?,
+                )+))
+            }
+        }
+    };
+}
+
+impl_batch_execute!(1; A 0);
+impl_batch_execute!(2; A 0, B 1);
+impl_batch_execute!(3; A 0, B 1, C 2);
+impl_batch_execute!(4; A 0, B 1, C 2, D 3);
+impl_batch_execute!(5; A 0, B 1, C 2, D 3, E 4);
+impl_batch_execute!(6; A 0, B 1, C 2, D 3, E 4, F 5);
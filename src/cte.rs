@@ -0,0 +1,131 @@
+use std::marker::PhantomData;
+
+use anyhow::{anyhow, Result};
+
+use crate::mapping::{Entity, FromRowNamed, HasColumns};
+use crate::query::{Alias, PlaceholderStyle, Query, SqlParam};
+
+// A member query of a CTE body, type-erased so that CTEs over different
+// entities can be attached to the same statement.
+pub(crate) trait CteMember: Send + Sync {
+    fn member_sql(&self, params: &mut Vec<SqlParam>) -> String;
+    fn check(&self) -> Result<()>;
+}
+
+impl<U> CteMember for Query<U>
+where
+    U: Entity + FromRowNamed + Send + Sync,
+{
+    fn member_sql(&self, params: &mut Vec<SqlParam>) -> String {
+        self.cte_member_sql(params)
+    }
+
+    fn check(&self) -> Result<()> {
+        Query::check(self)
+    }
+}
+
+pub(crate) struct CteClause {
+    name: &'static str,
+    recursive: bool,
+    members: Vec<Box<dyn CteMember>>,
+}
+
+impl CteClause {
+    // A CTE needs a body from Select or Recursive; checked with the
+    // statement it is attached to.
+    pub(crate) fn check(&self) -> Result<()> {
+        if self.members.is_empty() {
+            return Err(anyhow!(
+                "CTE {} has no query; define it with Select or Recursive",
+                self.name
+            ));
+        }
+        self.members.iter().try_for_each(|m| m.check())
+    }
+}
+
+// Named query attached to a statement with `Query::With`. Rows of the CTE
+// have the shape of `U` and are read through its `FromRowNamed` mapping.
+//
+//     let chain = Cte::<Employee>::new("ManagerChain");
+//     let c = chain.alias("c");
+//     let chain = chain.Recursive(anchor, step_joining_c);
+pub struct Cte<U> {
+    clause: CteClause,
+    _u: PhantomData<fn() -> U>,
+}
+
+#[allow(non_snake_case)]
+impl<U> Cte<U>
+where
+    U: Entity + FromRowNamed + HasColumns + Send + Sync + 'static,
+{
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            clause: CteClause {
+                name,
+                recursive: false,
+                members: Vec::new(),
+            },
+            _u: PhantomData,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.clause.name
+    }
+
+    // Alias over the CTE, for selecting from or joining it (including from
+    // the recursive member itself).
+    pub fn alias(&self, name: &'static str) -> Alias<U> {
+        Alias::over(self.clause.name, name)
+    }
+
+    pub fn Select(mut self, query: Query<U>) -> Self {
+        self.clause.recursive = false;
+        self.clause.members = vec![Box::new(query)];
+        self
+    }
+
+    // `anchor UNION ALL step`, where `step` joins the CTE through `alias`.
+    pub fn Recursive(mut self, anchor: Query<U>, step: Query<U>) -> Self {
+        self.clause.recursive = true;
+        self.clause.members = vec![Box::new(anchor), Box::new(step)];
+        self
+    }
+}
+
+impl<U> Cte<U> {
+    pub(crate) fn into_clause(self) -> CteClause {
+        self.clause
+    }
+}
+
+// "WITH a AS (...), b AS (...) " ahead of the main statement. PostgreSQL
+// needs RECURSIVE as soon as one CTE refers to itself; SQL Server does not
+// accept the keyword.
+pub(crate) fn with_sql(
+    ctes: &[CteClause],
+    style: PlaceholderStyle,
+    params: &mut Vec<SqlParam>,
+) -> String {
+    if ctes.is_empty() {
+        return String::new();
+    }
+    let recursive = ctes.iter().any(|c| c.recursive);
+    let mut sql = String::from("WITH ");
+    if recursive && style == PlaceholderStyle::Dollar {
+        sql.push_str("RECURSIVE ");
+    }
+    let defs: Vec<String> = ctes
+        .iter()
+        .map(|c| {
+            let body: Vec<String> = c.members.iter().map(|m| m.member_sql(params)).collect();
+            format!("{} AS ({})", c.name, body.join(" UNION ALL "))
+        })
+        .collect();
+    sql.push_str(&defs.join(", "));
+    sql.push(' ');
+    sql
+}
@@ -1,20 +1,37 @@
 use anyhow::Result;
+use futures::TryStreamExt;
 use native_tls::TlsConnector;
 use postgres_native_tls::MakeTlsConnector;
 use std::sync::Arc;
+use std::time::Instant;
 use tokio::net::TcpStream;
 use tokio::sync::Mutex;
 use tokio_postgres::NoTls;
 use tokio_util::compat::{Compat, TokioAsyncWriteCompatExt};
+use tracing::Instrument;
+
+use crate::interceptor::{row_count, Interceptor, InterceptorChain, Statement, StatementKind};
+use crate::mapping::FromRowNamed;
+use crate::query::{json_array, PlaceholderStyle, SqlParam};
+use crate::telemetry::Telemetry;
 
 pub enum DbKind {
     Mssql,
     Postgres,
 }
 
+#[derive(Clone)]
 pub enum DatabaseRef {
     Mssql(Arc<Mutex<tiberius::Client<Compat<TcpStream>>>>),
     Postgres(Arc<tokio_postgres::Client>),
+    // A connection whose statements pass through an interceptor chain.
+    Intercepted(Arc<DatabaseRef>, InterceptorChain),
+}
+
+// Rows returned by a statement, in the driver's own representation.
+pub enum DbRows {
+    Mssql(Vec<tiberius::Row>),
+    Postgres(Vec<tokio_postgres::Row>),
 }
 
 impl DatabaseRef {
@@ -22,8 +39,416 @@ impl DatabaseRef {
         match self {
             DatabaseRef::Mssql(_) => DbKind::Mssql,
             DatabaseRef::Postgres(_) => DbKind::Postgres,
+            DatabaseRef::Intercepted(inner, _) => inner.kind(),
+        }
+    }
+
+    // Adds an interceptor that sees every statement run through the
+    // returned reference, after the ones already registered.
+    pub fn with_interceptor(self, interceptor: impl Interceptor + 'static) -> Self {
+        let (inner, mut chain) = match self {
+            DatabaseRef::Intercepted(inner, chain) => (inner, chain),
+            db => (Arc::new(db), InterceptorChain::default()),
+        };
+        chain.push(Arc::new(interceptor));
+        DatabaseRef::Intercepted(inner, chain)
+    }
+
+    pub fn placeholder_style(&self) -> PlaceholderStyle {
+        match self.kind() {
+            DbKind::Mssql => PlaceholderStyle::AtP,
+            DbKind::Postgres => PlaceholderStyle::Dollar,
+        }
+    }
+
+    // Runs a hand written statement and maps every row with `FromRowNamed`.
+    // Placeholders follow the connection's style (`@P1` or `$1`).
+    pub async fn raw_query<T: FromRowNamed>(
+        &self,
+        sql: &str,
+        params: &[SqlParam],
+    ) -> Result<Vec<T>> {
+        match self.query_rows(sql, params).await? {
+            DbRows::Mssql(rows) => rows.iter().map(T::from_row_ms).collect(),
+            DbRows::Postgres(rows) => rows.iter().map(T::from_row_pg).collect(),
+        }
+    }
+
+    // Runs a hand written statement and returns the number of affected rows.
+    pub async fn raw_execute(&self, sql: &str, params: &[SqlParam]) -> Result<u64> {
+        self.execute_on(None, sql, params).await
+    }
+
+    // Runs a statement and collects its rows; on SQL Server rows of every
+    // result set are returned in order.
+    pub async fn query_rows(&self, sql: &str, params: &[SqlParam]) -> Result<DbRows> {
+        self.query_rows_on(None, sql, params).await
+    }
+
+    // Like raw_execute, reporting `table` as the span's `db.sql.table`.
+    pub(crate) async fn execute_on(
+        &self,
+        table: Option<&str>,
+        sql: &str,
+        params: &[SqlParam],
+    ) -> Result<u64> {
+        let telemetry = Telemetry::start(self, StatementKind::Execute, table, sql);
+        let result = self
+            .execute_intercepted(sql, params)
+            .instrument(telemetry.span())
+            .await;
+        telemetry.finish(&result, |n| *n);
+        result
+    }
+
+    pub(crate) async fn query_rows_on(
+        &self,
+        table: Option<&str>,
+        sql: &str,
+        params: &[SqlParam],
+    ) -> Result<DbRows> {
+        let telemetry = Telemetry::start(self, StatementKind::Query, table, sql);
+        let result = self
+            .query_intercepted(sql, params)
+            .instrument(telemetry.span())
+            .await;
+        telemetry.finish(&result, row_count);
+        result
+    }
+
+    async fn execute_intercepted(&self, sql: &str, params: &[SqlParam]) -> Result<u64> {
+        match self {
+            DatabaseRef::Intercepted(inner, chain) => {
+                let mut statement = Statement::new(StatementKind::Execute, sql, params);
+                chain.before(&mut statement)?;
+                let started = Instant::now();
+                let result = inner
+                    .execute_direct(&statement.sql, &statement.params)
+                    .await;
+                chain.after(&statement, started, &result, |n| *n);
+                result
+            }
+            _ => self.execute_direct(sql, params).await,
+        }
+    }
+
+    async fn query_intercepted(&self, sql: &str, params: &[SqlParam]) -> Result<DbRows> {
+        match self {
+            DatabaseRef::Intercepted(inner, chain) => {
+                let mut statement = Statement::new(StatementKind::Query, sql, params);
+                chain.before(&mut statement)?;
+                let started = Instant::now();
+                let result = inner.query_direct(&statement.sql, &statement.params).await;
+                chain.after(&statement, started, &result, row_count);
+                result
+            }
+            _ => self.query_direct(sql, params).await,
+        }
+    }
+
+    async fn execute_direct(&self, sql: &str, params: &[SqlParam]) -> Result<u64> {
+        match self {
+            DatabaseRef::Mssql(conn) => {
+                let boxed = ms_params(params)?;
+                let refs: Vec<&dyn tiberius::ToSql> =
+                    boxed.iter().map(|b| &**b as &dyn tiberius::ToSql).collect();
+                let mut guard = conn.lock().await;
+                let res = guard.execute(sql, &refs[..]).await?;
+                Ok(res.total())
+            }
+            DatabaseRef::Postgres(pg) => {
+                let boxed = pg_params(params)?;
+                let refs: Vec<&(dyn tokio_postgres::types::ToSql + Sync)> =
+                    boxed.iter().map(|b| &**b as _).collect();
+                Ok(pg.execute(sql, &refs[..]).await?)
+            }
+            DatabaseRef::Intercepted(..) => Box::pin(self.execute_intercepted(sql, params)).await,
+        }
+    }
+
+    async fn query_direct(&self, sql: &str, params: &[SqlParam]) -> Result<DbRows> {
+        match self {
+            DatabaseRef::Mssql(conn) => {
+                let boxed = ms_params(params)?;
+                let refs: Vec<&dyn tiberius::ToSql> =
+                    boxed.iter().map(|b| &**b as &dyn tiberius::ToSql).collect();
+                let mut guard = conn.lock().await;
+                let mut stream = guard.query(sql, &refs[..]).await?;
+                let mut rows = Vec::new();
+                while let Some(item) = stream.try_next().await? {
+                    if let Some(row) = item.into_row() {
+                        rows.push(row);
+                    }
+                }
+                Ok(DbRows::Mssql(rows))
+            }
+            DatabaseRef::Postgres(pg) => {
+                let boxed = pg_params(params)?;
+                let refs: Vec<&(dyn tokio_postgres::types::ToSql + Sync)> =
+                    boxed.iter().map(|b| &**b as _).collect();
+                Ok(DbRows::Postgres(pg.query(sql, &refs[..]).await?))
+            }
+            DatabaseRef::Intercepted(..) => Box::pin(self.query_intercepted(sql, params)).await,
+        }
+    }
+}
+
+impl DatabaseRef {
+    // Runs a batch of statements in one round trip and returns one row set
+    // per statement. SQL Server receives a single batch whose placeholders
+    // were numbered across all statements; PostgreSQL pipelines the
+    // statements on the connection.
+    // Intercepted batches report each statement separately, all with the
+    // duration of the whole round trip.
+    pub(crate) async fn query_batch(
+        &self,
+        statements: &[(String, Vec<SqlParam>)],
+    ) -> Result<Vec<DbRows>> {
+        let sql: Vec<&str> = statements.iter().map(|(s, _)| s.as_str()).collect();
+        let telemetry = Telemetry::start(self, StatementKind::Batch, None, &sql.join("; "));
+        let result = self
+            .batch_intercepted(statements)
+            .instrument(telemetry.span())
+            .await;
+        telemetry.finish(&result, |sets| sets.iter().map(row_count).sum());
+        result
+    }
+
+    async fn batch_intercepted(
+        &self,
+        statements: &[(String, Vec<SqlParam>)],
+    ) -> Result<Vec<DbRows>> {
+        match self {
+            DatabaseRef::Intercepted(inner, chain) => {
+                let mut intercepted = Vec::new();
+                for (sql, params) in statements {
+                    let mut statement = Statement::new(StatementKind::Batch, sql, params);
+                    chain.before(&mut statement)?;
+                    intercepted.push(statement);
+                }
+                let rewritten: Vec<(String, Vec<SqlParam>)> = intercepted
+                    .iter()
+                    .map(|s| (s.sql.clone(), s.params.clone()))
+                    .collect();
+                let started = Instant::now();
+                let result = inner.batch_direct(&rewritten).await;
+                for (i, statement) in intercepted.iter().enumerate() {
+                    chain.after(statement, started, &result, |sets| {
+                        sets.get(i).map(row_count).unwrap_or(0)
+                    });
+                }
+                result
+            }
+            _ => self.batch_direct(statements).await,
+        }
+    }
+
+    async fn batch_direct(&self, statements: &[(String, Vec<SqlParam>)]) -> Result<Vec<DbRows>> {
+        match self {
+            DatabaseRef::Mssql(conn) => {
+                let sql: Vec<&str> = statements.iter().map(|(s, _)| s.as_str()).collect();
+                let params: Vec<SqlParam> = statements
+                    .iter()
+                    .flat_map(|(_, p)| p.iter().cloned())
+                    .collect();
+                let boxed = ms_params(&params)?;
+                let refs: Vec<&dyn tiberius::ToSql> =
+                    boxed.iter().map(|b| &**b as &dyn tiberius::ToSql).collect();
+                let mut guard = conn.lock().await;
+                let sets = guard
+                    .query(sql.join("; "), &refs[..])
+                    .await?
+                    .into_results()
+                    .await?;
+                Ok(sets.into_iter().map(DbRows::Mssql).collect())
+            }
+            DatabaseRef::Postgres(pg) => {
+                let bound = statements
+                    .iter()
+                    .map(|(sql, params)| Ok((sql, pg_params(params)?)))
+                    .collect::<Result<Vec<_>>>()?;
+                let pending = bound.iter().map(|(sql, boxed)| async move {
+                    let refs: Vec<&(dyn tokio_postgres::types::ToSql + Sync)> =
+                        boxed.iter().map(|b| &**b as _).collect();
+                    pg.query(sql.as_str(), &refs[..])
+                        .await
+                        .map(DbRows::Postgres)
+                });
+                Ok(futures::future::try_join_all(pending).await?)
+            }
+            DatabaseRef::Intercepted(..) => Box::pin(self.batch_intercepted(statements)).await,
+        }
+    }
+}
+
+// A SqlParam::Json that does not parse fails the bind instead of sending
+// something other than what the caller wrote.
+fn json_arg(v: &str) -> Result<serde_json::Value> {
+    serde_json::from_str(v).map_err(|e| anyhow::anyhow!("JSON parameter is not valid JSON: {}", e))
+}
+
+pub(crate) fn ms_params(
+    params: &[SqlParam],
+) -> Result<Vec<Box<dyn tiberius::ToSql + Send + Sync>>> {
+    params
+        .iter()
+        .map(|p| -> Result<Box<dyn tiberius::ToSql + Send + Sync>> {
+            Ok(match p {
+                SqlParam::I32(v) => Box::new(*v),
+                SqlParam::I64(v) => Box::new(*v),
+                SqlParam::Bool(v) => Box::new(*v),
+                SqlParam::Text(v) => Box::new(v.clone()),
+                SqlParam::Uuid(v) => Box::new(*v),
+                SqlParam::Decimal(v) => Box::new(v.to_string()),
+                SqlParam::DateTime(v) => Box::new(*v),
+                SqlParam::Bytes(v) => Box::new(v.clone()),
+                SqlParam::Array(v) => Box::new(json_array(v)?),
+                SqlParam::Json(v) => {
+                    json_arg(v)?;
+                    Box::new(v.clone())
+                }
+                SqlParam::Null => Box::new(Option::<i32>::None),
+            })
+        })
+        .collect()
+}
+
+pub(crate) fn pg_params(
+    params: &[SqlParam],
+) -> Result<Vec<Box<dyn tokio_postgres::types::ToSql + Send + Sync>>> {
+    params
+        .iter()
+        .map(
+            |p| -> Result<Box<dyn tokio_postgres::types::ToSql + Send + Sync>> {
+                Ok(match p {
+                    SqlParam::I32(v) => Box::new(*v),
+                    SqlParam::I64(v) => Box::new(*v),
+                    SqlParam::Bool(v) => Box::new(*v),
+                    SqlParam::Text(v) => Box::new(v.clone()),
+                    SqlParam::Uuid(v) => Box::new(*v),
+                    SqlParam::Decimal(v) => Box::new(v.to_string()),
+                    SqlParam::DateTime(v) => Box::new(*v),
+                    SqlParam::Bytes(v) => Box::new(v.clone()),
+                    SqlParam::Array(v) => Box::new(PgArray(v.clone())),
+                    SqlParam::Json(v) => Box::new(json_arg(v)?),
+                    SqlParam::Null => Box::new(PgNull),
+                })
+            },
+        )
+        .collect()
+}
+
+// Binds a SqlParam::Array as whatever array type the server expects, so an
+// empty list or a list of I32 works against int8[] as well as int4[].
+#[derive(Debug)]
+struct PgArray(Vec<SqlParam>);
+
+type BoxError = Box<dyn std::error::Error + Sync + Send>;
+
+impl PgArray {
+    fn elements<T>(&self, f: impl Fn(&SqlParam) -> Option<T>) -> Result<Vec<Option<T>>, BoxError> {
+        self.0
+            .iter()
+            .map(|p| match p {
+                SqlParam::Null => Ok(None),
+                p => f(p).map(Some).ok_or_else(|| {
+                    format!("array element {:?} does not match the column type", p).into()
+                }),
+            })
+            .collect()
+    }
+}
+
+impl tokio_postgres::types::ToSql for PgArray {
+    fn to_sql(
+        &self,
+        ty: &tokio_postgres::types::Type,
+        out: &mut bytes::BytesMut,
+    ) -> Result<tokio_postgres::types::IsNull, BoxError> {
+        use tokio_postgres::types::{Kind, Type};
+        let member = match ty.kind() {
+            Kind::Array(member) => member,
+            _ => return Err(format!("cannot bind an array to {}", ty).into()),
+        };
+        match *member {
+            Type::INT2 => self
+                .elements(|p| match p {
+                    SqlParam::I32(v) => i16::try_from(*v).ok(),
+                    _ => None,
+                })?
+                .to_sql(ty, out),
+            Type::INT4 => self
+                .elements(|p| match p {
+                    SqlParam::I32(v) => Some(*v),
+                    _ => None,
+                })?
+                .to_sql(ty, out),
+            Type::INT8 => self
+                .elements(|p| match p {
+                    SqlParam::I32(v) => Some(*v as i64),
+                    SqlParam::I64(v) => Some(*v),
+                    _ => None,
+                })?
+                .to_sql(ty, out),
+            Type::BOOL => self
+                .elements(|p| match p {
+                    SqlParam::Bool(v) => Some(*v),
+                    _ => None,
+                })?
+                .to_sql(ty, out),
+            Type::TEXT | Type::VARCHAR | Type::BPCHAR | Type::NAME => self
+                .elements(|p| match p {
+                    SqlParam::Text(v) => Some(v.clone()),
+                    _ => None,
+                })?
+                .to_sql(ty, out),
+            Type::UUID => self
+                .elements(|p| match p {
+                    SqlParam::Uuid(v) => Some(*v),
+                    _ => None,
+                })?
+                .to_sql(ty, out),
+            Type::TIMESTAMP => self
+                .elements(|p| match p {
+                    SqlParam::DateTime(v) => Some(*v),
+                    _ => None,
+                })?
+                .to_sql(ty, out),
+            Type::BYTEA => self
+                .elements(|p| match p {
+                    SqlParam::Bytes(v) => Some(v.clone()),
+                    _ => None,
+                })?
+                .to_sql(ty, out),
+            _ => Err(format!("unsupported array type {}", ty).into()),
         }
     }
+
+    fn accepts(ty: &tokio_postgres::types::Type) -> bool {
+        matches!(ty.kind(), tokio_postgres::types::Kind::Array(_))
+    }
+
+    tokio_postgres::types::to_sql_checked!();
+}
+
+// Binds NULL to a parameter of any type, e.g. a jsonb or text column.
+#[derive(Debug)]
+struct PgNull;
+
+impl tokio_postgres::types::ToSql for PgNull {
+    fn to_sql(
+        &self,
+        _ty: &tokio_postgres::types::Type,
+        _out: &mut bytes::BytesMut,
+    ) -> Result<tokio_postgres::types::IsNull, BoxError> {
+        Ok(tokio_postgres::types::IsNull::Yes)
+    }
+
+    fn accepts(_ty: &tokio_postgres::types::Type) -> bool {
+        true
+    }
+
+    tokio_postgres::types::to_sql_checked!();
 }
 
 pub async fn connect_mssql(
@@ -0,0 +1,66 @@
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::db::DatabaseRef;
+
+// Lifecycle callbacks run by `GenericRepository` around persistence. An
+// error from a before_* hook aborts the operation; `before_insert` and
+// `before_update` may return a replacement entity that is written instead
+// of the one passed in. after_* hooks run once the statement succeeded and
+// cannot undo it: their errors are logged under `rquery_orm::hooks` and the
+// operation still succeeds. `restore_async` runs no hooks, and neither do
+// the tuple queries of `GenericRepository<(A, B, ..)>`.
+#[async_trait]
+pub trait EntityHooks<T>: Send + Sync {
+    async fn before_insert(&self, _entity: &T, _db: &DatabaseRef) -> Result<Option<T>>
+    where
+        T: Send + Sync,
+    {
+        Ok(None)
+    }
+
+    async fn after_insert(&self, _entity: &T, _db: &DatabaseRef) -> Result<()>
+    where
+        T: Send + Sync,
+    {
+        Ok(())
+    }
+
+    async fn before_update(&self, _entity: &T, _db: &DatabaseRef) -> Result<Option<T>>
+    where
+        T: Send + Sync,
+    {
+        Ok(None)
+    }
+
+    async fn after_update(&self, _entity: &T, _db: &DatabaseRef) -> Result<()>
+    where
+        T: Send + Sync,
+    {
+        Ok(())
+    }
+
+    async fn before_delete(&self, _entity: &T, _db: &DatabaseRef) -> Result<()>
+    where
+        T: Send + Sync,
+    {
+        Ok(())
+    }
+
+    async fn after_delete(&self, _entity: &T, _db: &DatabaseRef) -> Result<()>
+    where
+        T: Send + Sync,
+    {
+        Ok(())
+    }
+
+    // Runs for every entity materialized by a query of the repository,
+    // including queries run in a Batch, after its includes were loaded. An
+    // error fails the query.
+    async fn after_load(&self, _entity: &mut T, _db: &DatabaseRef) -> Result<()>
+    where
+        T: Send,
+    {
+        Ok(())
+    }
+}
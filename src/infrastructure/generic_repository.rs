@@ -3,14 +3,26 @@ use std::sync::Arc;
 
 use async_trait::async_trait;
 
-use crate::db::{DatabaseRef, DbKind};
-use crate::mapping::{Entity, FromRowNamed, Persistable, Validatable};
-use crate::query::{DualQuery, Expr, PlaceholderStyle, Query, SqlParam};
+use crate::audit::{AuditValues, Clock, CurrentUser, SystemClock};
+use crate::db::{DatabaseRef, DbRows};
+use crate::hooks::EntityHooks;
+use crate::interceptor::row_count;
+use crate::mapping::{
+    AuditColumn, ConcurrencyConflict, Entity, FromRowNamed, JoinTuple, Persistable, RelationMeta,
+    SoftDeleteMeta, TableMeta, Validatable,
+};
+use crate::query::{Expr, JoinQuery, PlaceholderStyle, Query, SqlParam, ToKey};
 use crate::repository::{Crud, QueryExecutor, Repository};
 use anyhow::{anyhow, Result};
 
+type FilterValue = Arc<dyn Fn() -> SqlParam + Send + Sync>;
+
 pub struct GenericRepository<T> {
     db: Arc<DatabaseRef>,
+    clock: Arc<dyn Clock>,
+    user: Option<Arc<dyn CurrentUser>>,
+    filters: Vec<(&'static str, FilterValue)>,
+    hooks: Option<Arc<dyn EntityHooks<T>>>,
     _t: PhantomData<T>,
 }
 
@@ -18,15 +30,74 @@ impl<T> GenericRepository<T> {
     pub fn new(db: DatabaseRef) -> Self {
         Self {
             db: Arc::new(db),
+            clock: Arc::new(SystemClock),
+            user: None,
+            filters: Vec::new(),
+            hooks: None,
             _t: PhantomData,
         }
     }
+
+    // Clock for `created_at`/`updated_at` columns; defaults to UTC now.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    // Provider for `created_by`/`updated_by` columns.
+    pub fn with_current_user(mut self, user: impl CurrentUser + 'static) -> Self {
+        self.user = Some(Arc::new(user));
+        self
+    }
+
+    // Global filter `column = value()` applied to every Select, update and
+    // delete issued through this repository; inserts stamp the column.
+    // `value` is evaluated per statement, e.g. to read the current tenant.
+    pub fn with_query_filter<F>(mut self, column: &'static str, value: F) -> Self
+    where
+        F: Fn() -> SqlParam + Send + Sync + 'static,
+    {
+        self.filters.push((column, Arc::new(value)));
+        self
+    }
+
+    // Lifecycle hooks run by the Crud methods and by queries from Select().
+    pub fn with_hooks(mut self, hooks: impl EntityHooks<T> + 'static) -> Self {
+        self.hooks = Some(Arc::new(hooks));
+        self
+    }
+
+    fn filter_values(&self) -> Vec<(&'static str, SqlParam)> {
+        self.filters.iter().map(|(c, v)| (*c, v())).collect()
+    }
+
+    fn audit_values(&self) -> AuditValues {
+        AuditValues::capture(&self.clock, &self.user, self.filter_values())
+    }
+
+    // Appends the query filters to the WHERE of an UPDATE or DELETE.
+    fn scoped(&self, (mut sql, mut params): (String, Vec<SqlParam>)) -> (String, Vec<SqlParam>) {
+        let style = self.db.placeholder_style();
+        for (column, value) in self.filter_values() {
+            params.push(value);
+            sql.push_str(&format!(
+                " AND {} = {}",
+                column,
+                style.placeholder(params.len())
+            ));
+        }
+        (sql, params)
+    }
 }
 
 impl<T> Clone for GenericRepository<T> {
     fn clone(&self) -> Self {
         Self {
             db: self.db.clone(),
+            clock: self.clock.clone(),
+            user: self.user.clone(),
+            filters: self.filters.clone(),
+            hooks: self.hooks.clone(),
             _t: PhantomData,
         }
     }
@@ -38,79 +109,284 @@ where
     T: Entity + FromRowNamed + Validatable + Persistable + Send + Sync,
 {
     fn Select(&self) -> Query<T> {
-        let style = match self.db.as_ref().kind() {
-            DbKind::Mssql => PlaceholderStyle::AtP,
-            DbKind::Postgres => PlaceholderStyle::Dollar,
-        };
-        Query::new(T::table().name, style).with_db(self.db.clone())
+        let style = self.db.placeholder_style();
+        Query::new(T::table().name, style)
+            .with_db(self.db.clone())
+            .with_query_filters(self.filter_values())
+            .with_hooks(self.hooks.clone())
     }
 
-    async fn get_by_key_async(&self, key: SqlParam) -> Result<Option<T>> {
+    async fn get_by_key_async<K: ToKey + Send>(&self, key: K) -> Result<Option<T>> {
         let table = T::table();
-        let pk = table
-            .keys
-            .first()
-            .ok_or_else(|| anyhow!("no primary key metadata"))?;
-        let expr = Expr::Col(format!("{}.{}", table.name, pk.column)).eq(Expr::Param(key));
+        let values = key_values(table, key)?;
+        let mut expr: Option<Expr> = None;
+        for (k, v) in table.keys.iter().zip(values) {
+            let cmp = Expr::Col(format!("{}.{}", table.name, k.column)).eq(Expr::Param(v));
+            expr = Some(match expr {
+                Some(prev) => prev.and(cmp),
+                None => cmp,
+            });
+        }
+        let expr = expr.ok_or_else(|| anyhow!("no primary key metadata"))?;
         self.Select().Where(expr).to_single_async().await
     }
 }
 
-impl<T, U> GenericRepository<(T, U)>
-where
-    T: Entity + crate::mapping::FromRowWithPrefix + Send + Sync,
-    U: Entity + crate::mapping::FromRowWithPrefix + Send + Sync,
-{
-    pub fn Select(&self) -> DualQuery<T, U> {
-        let style = match self.db.as_ref().kind() {
-            DbKind::Mssql => PlaceholderStyle::AtP,
-            DbKind::Postgres => PlaceholderStyle::Dollar,
-        };
-        DualQuery::<T, U>::new(style).with_db(self.db.clone())
-    }
+macro_rules! impl_join_select {
+    ($($name:ident),+) => {
+        #[allow(non_snake_case)]
+        impl<$($name),+> GenericRepository<($($name,)+)>
+        where
+            ($($name,)+): JoinTuple,
+        {
+            pub fn Select(&self) -> JoinQuery<($($name,)+)> {
+                JoinQuery::new(self.db.placeholder_style())
+                    .with_db(self.db.clone())
+                    .with_query_filters(self.filter_values())
+            }
+        }
+    };
 }
 
+impl_join_select!(A, B);
+impl_join_select!(A, B, C);
+impl_join_select!(A, B, C, D);
+impl_join_select!(A, B, C, D, E);
+impl_join_select!(A, B, C, D, E, F);
+
 #[async_trait]
 impl<T> Crud<T> for GenericRepository<T>
 where
     T: Entity + FromRowNamed + Validatable + Persistable + Send + Sync,
 {
     async fn insert_async(&self, entity: &T) -> Result<()> {
-        entity.validate().map_err(|e| anyhow!(e.join(", ")))?;
-        let style = match self.db.as_ref().kind() {
-            DbKind::Mssql => PlaceholderStyle::AtP,
-            DbKind::Postgres => PlaceholderStyle::Dollar,
+        let replaced = match &self.hooks {
+            Some(hooks) => hooks.before_insert(entity, &self.db).await?,
+            None => None,
         };
-        let (sql, params, _has_identity) = entity.build_insert(style);
-        execute(&self.db, &sql, &params).await.map(|_| ())
+        let entity = replaced.as_ref().unwrap_or(entity);
+        entity.validate().map_err(|e| anyhow!(e.join(", ")))?;
+        let table = T::table();
+        if let Some((column, _)) = self
+            .filters
+            .iter()
+            .find(|(c, _)| !table.columns.iter().any(|m| m.name == *c))
+        {
+            return Err(anyhow!(
+                "query filter column {} is not mapped on {}",
+                column,
+                table.name
+            ));
+        }
+        let style = self.db.placeholder_style();
+        let audit = self.audit_values();
+        let (sql, params, _has_identity) = entity.build_insert_audited(style, Some(&audit))?;
+        self.db
+            .execute_on(Some(T::table().name), &sql, &params)
+            .await?;
+        if let Some(hooks) = &self.hooks {
+            after_hook("after_insert", hooks.after_insert(entity, &self.db).await);
+        }
+        Ok(())
     }
 
     async fn update_async(&self, entity: &T) -> Result<()> {
+        let replaced = match &self.hooks {
+            Some(hooks) => hooks.before_update(entity, &self.db).await?,
+            None => None,
+        };
+        let entity = replaced.as_ref().unwrap_or(entity);
         entity.validate().map_err(|e| anyhow!(e.join(", ")))?;
-        let style = match self.db.as_ref().kind() {
-            DbKind::Mssql => PlaceholderStyle::AtP,
-            DbKind::Postgres => PlaceholderStyle::Dollar,
+        let style = self.db.placeholder_style();
+        let audit = self.audit_values();
+        let (sql, params) = self.scoped(entity.build_update_audited(style, Some(&audit))?);
+        let affected = self
+            .db
+            .execute_on(Some(T::table().name), &sql, &params)
+            .await?;
+        check_concurrency::<T>(affected)?;
+        if let Some(hooks) = &self.hooks {
+            after_hook("after_update", hooks.after_update(entity, &self.db).await);
+        }
+        Ok(())
+    }
+
+    async fn update_and_refresh_async(&self, entity: &mut T) -> Result<()> {
+        let table = T::table();
+        if !table.columns.iter().any(|c| c.concurrency_token) {
+            return self.update_async(entity).await;
+        }
+        let mut replaced = match &self.hooks {
+            Some(hooks) => hooks.before_update(entity, &self.db).await?,
+            None => None,
         };
-        let (sql, params) = entity.build_update(style);
-        execute(&self.db, &sql, &params).await.map(|_| ())
+        let written = replaced.as_ref().unwrap_or(entity);
+        written.validate().map_err(|e| anyhow!(e.join(", ")))?;
+        let style = self.db.placeholder_style();
+        let audit = self.audit_values();
+        let (sql, params) =
+            written.build_update_returning(style, Some(&audit), &self.filter_values())?;
+        let rows = self
+            .db
+            .query_rows_on(Some(table.name), &sql, &params)
+            .await?;
+        check_concurrency::<T>(row_count(&rows))?;
+        for e in std::iter::once(&mut *entity).chain(replaced.as_mut()) {
+            match &rows {
+                DbRows::Mssql(rows) => e.refresh_token_ms(&rows[0])?,
+                DbRows::Postgres(rows) => e.refresh_token_pg(&rows[0])?,
+            }
+        }
+        if let Some(hooks) = &self.hooks {
+            let result = hooks
+                .after_update(replaced.as_ref().unwrap_or(entity), &self.db)
+                .await;
+            after_hook("after_update", result);
+        }
+        Ok(())
     }
 
     async fn delete_by_entity_async(&self, entity: &T) -> Result<()> {
-        let style = match self.db.as_ref().kind() {
-            DbKind::Mssql => PlaceholderStyle::AtP,
-            DbKind::Postgres => PlaceholderStyle::Dollar,
+        if let Some(hooks) = &self.hooks {
+            hooks.before_delete(entity, &self.db).await?;
+        }
+        let style = self.db.placeholder_style();
+        let (sql, params) = match &T::table().soft_delete {
+            Some(meta) => {
+                let table = T::table();
+                let key = table
+                    .keys
+                    .iter()
+                    .map(|k| entity.column_value(k.column).unwrap_or(SqlParam::Null))
+                    .collect();
+                let tokens = table
+                    .columns
+                    .iter()
+                    .filter(|c| c.concurrency_token)
+                    .map(|c| {
+                        (
+                            c.name,
+                            entity.column_value(c.name).unwrap_or(SqlParam::Null),
+                        )
+                    })
+                    .collect();
+                let audit = self.audit_values();
+                mark_deleted_sql(table, meta, true, key, tokens, &audit, style)
+            }
+            None => entity.build_delete(style),
         };
-        let (sql, params) = entity.build_delete(style);
-        execute(&self.db, &sql, &params).await.map(|_| ())
+        let (sql, params) = self.scoped((sql, params));
+        let affected = self
+            .db
+            .execute_on(Some(T::table().name), &sql, &params)
+            .await?;
+        check_concurrency::<T>(affected)?;
+        if let Some(hooks) = &self.hooks {
+            after_hook("after_delete", hooks.after_delete(entity, &self.db).await);
+        }
+        Ok(())
     }
 
-    async fn delete_by_key_async(&self, key: SqlParam) -> Result<()> {
-        let style = match self.db.as_ref().kind() {
-            DbKind::Mssql => PlaceholderStyle::AtP,
-            DbKind::Postgres => PlaceholderStyle::Dollar,
+    async fn delete_by_key_async<K: ToKey + Send>(&self, key: K) -> Result<()> {
+        if self.hooks.is_some() {
+            // delete hooks need the entity, so load it first
+            return match self.get_by_key_async(key).await? {
+                Some(entity) => self.delete_by_entity_async(&entity).await,
+                None => Ok(()),
+            };
+        }
+        let style = self.db.placeholder_style();
+        let table = T::table();
+        let values = key_values(table, key)?;
+        let (sql, params) = match &table.soft_delete {
+            Some(meta) => {
+                let audit = self.audit_values();
+                mark_deleted_sql(table, meta, true, values, Vec::new(), &audit, style)
+            }
+            None => T::build_delete_by_key(values, style),
         };
-        let (sql, params) = T::build_delete_by_key(key, style);
-        execute(&self.db, &sql, &params).await.map(|_| ())
+        let (sql, params) = self.scoped((sql, params));
+        self.db
+            .execute_on(Some(T::table().name), &sql, &params)
+            .await
+            .map(|_| ())
+    }
+}
+
+impl<T> GenericRepository<T>
+where
+    T: Entity + Send + Sync,
+{
+    // Undoes a soft delete of the row with `key`.
+    pub async fn restore_async<K: ToKey + Send>(&self, key: K) -> Result<()> {
+        let table = T::table();
+        let meta = table
+            .soft_delete
+            .as_ref()
+            .ok_or_else(|| anyhow!("{} does not use soft delete", table.name))?;
+        let style = self.db.placeholder_style();
+        let values = key_values(table, key)?;
+        let audit = self.audit_values();
+        let (sql, params) = self.scoped(mark_deleted_sql(
+            table,
+            meta,
+            false,
+            values,
+            Vec::new(),
+            &audit,
+            style,
+        ));
+        self.db
+            .execute_on(Some(T::table().name), &sql, &params)
+            .await
+            .map(|_| ())
+    }
+}
+
+impl<T> GenericRepository<T>
+where
+    T: Entity + Persistable + Send + Sync,
+{
+    // Inserts a row into the join table of the many-to-many relation of `T`
+    // whose related table is `U`.
+    pub async fn attach<U>(&self, entity: &T, related: &U) -> Result<()>
+    where
+        U: Entity + Persistable + Send + Sync,
+    {
+        let (link, local, remote) = link_values(entity, related)?;
+        let style = self.db.placeholder_style();
+        let sql = format!(
+            "INSERT INTO {} ({}, {}) VALUES ({}, {})",
+            link.through.unwrap_or_default(),
+            link.local_key.unwrap_or_default(),
+            link.remote_key.unwrap_or_default(),
+            style.placeholder(1),
+            style.placeholder(2)
+        );
+        self.db
+            .execute_on(link.through, &sql, &[local, remote])
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn detach<U>(&self, entity: &T, related: &U) -> Result<()>
+    where
+        U: Entity + Persistable + Send + Sync,
+    {
+        let (link, local, remote) = link_values(entity, related)?;
+        let style = self.db.placeholder_style();
+        let sql = format!(
+            "DELETE FROM {} WHERE {} = {} AND {} = {}",
+            link.through.unwrap_or_default(),
+            link.local_key.unwrap_or_default(),
+            style.placeholder(1),
+            link.remote_key.unwrap_or_default(),
+            style.placeholder(2)
+        );
+        self.db
+            .execute_on(link.through, &sql, &[local, remote])
+            .await
+            .map(|_| ())
     }
 }
 
@@ -119,50 +395,131 @@ impl<T> Repository<T> for GenericRepository<T> where
 {
 }
 
-async fn execute(db: &Arc<DatabaseRef>, sql: &str, params: &[SqlParam]) -> Result<u64> {
-    match db.as_ref() {
-        DatabaseRef::Mssql(conn) => {
-            let mut guard = conn.lock().await;
-            let mut boxed: Vec<Box<dyn tiberius::ToSql + Send + Sync>> = Vec::new();
-            for p in params {
-                let b: Box<dyn tiberius::ToSql + Send + Sync> = match p {
-                    SqlParam::I32(v) => Box::new(*v),
-                    SqlParam::I64(v) => Box::new(*v),
-                    SqlParam::Bool(v) => Box::new(*v),
-                    SqlParam::Text(v) => Box::new(v.clone()),
-                    SqlParam::Uuid(v) => Box::new(*v),
-                    SqlParam::Decimal(v) => Box::new(v.to_string()),
-                    SqlParam::DateTime(v) => Box::new(*v),
-                    SqlParam::Bytes(v) => Box::new(v.clone()),
-                    SqlParam::Null => Box::new(Option::<i32>::None),
-                };
-                boxed.push(b);
-            }
-            let refs: Vec<&dyn tiberius::ToSql> =
-                boxed.iter().map(|b| &**b as &dyn tiberius::ToSql).collect();
-            let res = guard.execute(sql, &refs[..]).await?;
-            Ok(res.total())
-        }
-        DatabaseRef::Postgres(pg) => {
-            let mut boxed: Vec<Box<dyn tokio_postgres::types::ToSql + Send + Sync>> = Vec::new();
-            for p in params {
-                let b: Box<dyn tokio_postgres::types::ToSql + Send + Sync> = match p {
-                    SqlParam::I32(v) => Box::new(*v),
-                    SqlParam::I64(v) => Box::new(*v),
-                    SqlParam::Bool(v) => Box::new(*v),
-                    SqlParam::Text(v) => Box::new(v.clone()),
-                    SqlParam::Uuid(v) => Box::new(*v),
-                    SqlParam::Decimal(v) => Box::new(v.to_string()),
-                    SqlParam::DateTime(v) => Box::new(*v),
-                    SqlParam::Bytes(v) => Box::new(v.clone()),
-                    SqlParam::Null => Box::new(Option::<i32>::None),
-                };
-                boxed.push(b);
-            }
-            let refs: Vec<&(dyn tokio_postgres::types::ToSql + Sync)> =
-                boxed.iter().map(|b| &**b as _).collect();
-            let res = pg.execute(sql, &refs[..]).await?;
-            Ok(res)
+// Finds the many-to-many relation of `T` targeting `U` and the key values
+// to store in its join table.
+fn link_values<T, U>(entity: &T, related: &U) -> Result<(&'static RelationMeta, SqlParam, SqlParam)>
+where
+    T: Entity + Persistable,
+    U: Entity + Persistable,
+{
+    let table = T::table();
+    let target = U::table();
+    let link = table
+        .relations
+        .iter()
+        .find(|r| r.through.is_some() && r.table == target.name)
+        .ok_or_else(|| {
+            anyhow!(
+                "{} has no many-to-many relation to {}",
+                table.name,
+                target.name
+            )
+        })?;
+    if link.local_key.is_none() || link.remote_key.is_none() {
+        return Err(anyhow!(
+            "relation {} needs local_key and remote_key",
+            link.name
+        ));
+    }
+    let local = table
+        .keys
+        .first()
+        .and_then(|k| entity.column_value(k.column))
+        .ok_or_else(|| anyhow!("no primary key metadata on {}", table.name))?;
+    let remote = target
+        .keys
+        .first()
+        .and_then(|k| related.column_value(k.column))
+        .ok_or_else(|| anyhow!("no primary key metadata on {}", target.name))?;
+    Ok((link, local, remote))
+}
+
+// UPDATE marking the row with `key` as deleted, or restoring it. A deleted
+// timestamp is `audit.now`. Concurrency `tokens` are checked like in a
+// regular update; integer ones are bumped. updated_* audit columns are
+// stamped as well.
+fn mark_deleted_sql(
+    table: &TableMeta,
+    meta: &SoftDeleteMeta,
+    deleted: bool,
+    key: Vec<SqlParam>,
+    tokens: Vec<(&'static str, SqlParam)>,
+    audit: &AuditValues,
+    style: PlaceholderStyle,
+) -> (String, Vec<SqlParam>) {
+    let mut params = Vec::new();
+    let value = if deleted {
+        meta.deleted_value(style, audit.now, &mut params)
+    } else {
+        meta.restored_value(style).to_string()
+    };
+    let mut sets = vec![format!("{} = {}", meta.column, value)];
+    let mut wheres = Vec::new();
+    for c in table.columns {
+        let stamp = match c.audit {
+            Some(AuditColumn::UpdatedAt) => Some(SqlParam::DateTime(audit.now)),
+            Some(AuditColumn::UpdatedBy) => audit.user.clone(),
+            _ => None,
+        };
+        if let Some(v) = stamp {
+            params.push(v);
+            sets.push(format!("{} = {}", c.name, style.placeholder(params.len())));
         }
     }
+    for (k, v) in table.keys.iter().zip(key) {
+        params.push(v);
+        wheres.push(format!(
+            "{} = {}",
+            k.column,
+            style.placeholder(params.len())
+        ));
+    }
+    for (column, v) in tokens {
+        if !matches!(v, SqlParam::Bytes(_)) {
+            sets.push(format!("{} = {} + 1", column, column));
+        }
+        params.push(v);
+        wheres.push(format!("{} = {}", column, style.placeholder(params.len())));
+    }
+    let sql = format!(
+        "UPDATE {} SET {} WHERE {}",
+        table.name,
+        sets.join(", "),
+        wheres.join(" AND ")
+    );
+    (sql, params)
+}
+
+// The write has happened by the time an after_* hook runs and there is no
+// transaction to undo it, so a failing hook is logged instead of turning
+// the operation into an error.
+fn after_hook(hook: &str, result: Result<()>) {
+    if let Err(error) = result {
+        tracing::warn!(target: "rquery_orm::hooks", hook, error = %error, "hook failed after the write");
+    }
+}
+
+// With a concurrency token, touching no row means the token was stale.
+fn check_concurrency<T: Entity>(affected: u64) -> Result<()> {
+    let table = T::table();
+    if affected == 0 && table.columns.iter().any(|c| c.concurrency_token) {
+        return Err(ConcurrencyConflict { table: table.name }.into());
+    }
+    Ok(())
+}
+
+fn key_values<K: ToKey>(table: &TableMeta, key: K) -> Result<Vec<SqlParam>> {
+    if table.keys.is_empty() {
+        return Err(anyhow!("no primary key metadata"));
+    }
+    let values = key.to_key();
+    if values.len() != table.keys.len() {
+        return Err(anyhow!(
+            "{} expects {} key value(s) but {} were given",
+            table.name,
+            table.keys.len(),
+            values.len()
+        ));
+    }
+    Ok(values)
 }
@@ -0,0 +1,217 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+
+use crate::db::DbRows;
+use crate::query::SqlParam;
+
+// Round trip a statement belongs to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StatementKind {
+    Query,
+    Execute,
+    Batch,
+    Procedure,
+}
+
+// A statement about to be sent. Interceptors may rewrite `sql` and `params`.
+#[derive(Clone, Debug)]
+pub struct Statement {
+    pub kind: StatementKind,
+    pub sql: String,
+    pub params: Vec<SqlParam>,
+}
+
+impl Statement {
+    pub fn new(kind: StatementKind, sql: &str, params: &[SqlParam]) -> Self {
+        Self {
+            kind,
+            sql: sql.to_string(),
+            params: params.to_vec(),
+        }
+    }
+}
+
+// How a statement went. `rows` is the affected row count of an execute and
+// the number of returned rows otherwise.
+#[derive(Debug)]
+pub struct Outcome<'a> {
+    pub duration: Duration,
+    pub rows: Option<u64>,
+    pub error: Option<&'a anyhow::Error>,
+}
+
+// Sees every statement run through a `DatabaseRef` before it is sent and
+// its outcome afterwards. An error from `before` aborts the statement.
+pub trait Interceptor: Send + Sync {
+    fn before(&self, _statement: &mut Statement) -> Result<()> {
+        Ok(())
+    }
+
+    fn after(&self, _statement: &Statement, _outcome: &Outcome<'_>) {}
+}
+
+// Lets a shared interceptor be registered while its owner keeps a handle.
+impl<I> Interceptor for Arc<I>
+where
+    I: Interceptor + ?Sized,
+{
+    fn before(&self, statement: &mut Statement) -> Result<()> {
+        (**self).before(statement)
+    }
+
+    fn after(&self, statement: &Statement, outcome: &Outcome<'_>) {
+        (**self).after(statement, outcome)
+    }
+}
+
+// Interceptors in registration order; `after` runs in reverse order.
+#[derive(Clone, Default)]
+pub struct InterceptorChain {
+    interceptors: Vec<Arc<dyn Interceptor>>,
+}
+
+impl InterceptorChain {
+    pub(crate) fn push(&mut self, interceptor: Arc<dyn Interceptor>) {
+        self.interceptors.push(interceptor);
+    }
+
+    pub(crate) fn before(&self, statement: &mut Statement) -> Result<()> {
+        for i in &self.interceptors {
+            i.before(statement)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn after<R>(
+        &self,
+        statement: &Statement,
+        started: Instant,
+        result: &Result<R>,
+        rows: impl Fn(&R) -> u64,
+    ) {
+        let outcome = Outcome {
+            duration: started.elapsed(),
+            rows: result.as_ref().ok().map(rows),
+            error: result.as_ref().err(),
+        };
+        for i in self.interceptors.iter().rev() {
+            i.after(statement, &outcome);
+        }
+    }
+}
+
+pub(crate) fn row_count(rows: &DbRows) -> u64 {
+    match rows {
+        DbRows::Mssql(rows) => rows.len() as u64,
+        DbRows::Postgres(rows) => rows.len() as u64,
+    }
+}
+
+// Emits a `tracing` event per statement under the `rquery_orm::sql` target:
+// DEBUG on success, ERROR on failure. Parameter values are redacted unless
+// `with_params` is set.
+#[derive(Clone, Debug, Default)]
+pub struct TracingInterceptor {
+    params: bool,
+}
+
+impl TracingInterceptor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_params(mut self) -> Self {
+        self.params = true;
+        self
+    }
+
+    pub fn render_params(&self, params: &[SqlParam]) -> String {
+        if self.params {
+            format!("{:?}", params)
+        } else {
+            format!("[{}]", vec!["?"; params.len()].join(", "))
+        }
+    }
+}
+
+impl Interceptor for TracingInterceptor {
+    fn after(&self, statement: &Statement, outcome: &Outcome<'_>) {
+        let params = self.render_params(&statement.params);
+        let elapsed_ms = outcome.duration.as_secs_f64() * 1000.0;
+        match outcome.error {
+            Some(error) => tracing::error!(
+                target: "rquery_orm::sql",
+                kind = ?statement.kind,
+                sql = %statement.sql,
+                params = %params,
+                elapsed_ms,
+                error = %error,
+                "statement failed"
+            ),
+            None => tracing::debug!(
+                target: "rquery_orm::sql",
+                kind = ?statement.kind,
+                sql = %statement.sql,
+                params = %params,
+                elapsed_ms,
+                rows = outcome.rows.unwrap_or(0),
+                "statement executed"
+            ),
+        }
+    }
+}
+
+// Emits a WARN event under `rquery_orm::slow` for statements that take at
+// least `threshold`. Parameters are never logged.
+#[derive(Clone, Debug)]
+pub struct SlowQueryLog {
+    threshold: Duration,
+}
+
+impl SlowQueryLog {
+    pub fn new(threshold: Duration) -> Self {
+        Self { threshold }
+    }
+}
+
+impl Interceptor for SlowQueryLog {
+    fn after(&self, statement: &Statement, outcome: &Outcome<'_>) {
+        if outcome.duration >= self.threshold {
+            tracing::warn!(
+                target: "rquery_orm::slow",
+                kind = ?statement.kind,
+                sql = %statement.sql,
+                elapsed_ms = outcome.duration.as_secs_f64() * 1000.0,
+                threshold_ms = self.threshold.as_secs_f64() * 1000.0,
+                "slow statement"
+            );
+        }
+    }
+}
+
+// Rewrites statements before they are sent, e.g. to prepend a comment
+// naming the service.
+pub struct StatementRewriter<F> {
+    rewrite: F,
+}
+
+impl<F> StatementRewriter<F>
+where
+    F: Fn(&mut Statement) + Send + Sync,
+{
+    pub fn new(rewrite: F) -> Self {
+        Self { rewrite }
+    }
+}
+
+impl<F> Interceptor for StatementRewriter<F>
+where
+    F: Fn(&mut Statement) + Send + Sync,
+{
+    fn before(&self, statement: &mut Statement) -> Result<()> {
+        (self.rewrite)(statement);
+        Ok(())
+    }
+}
@@ -1,17 +1,41 @@
+pub mod audit;
+pub mod batch;
+pub mod cte;
 pub mod db;
+pub mod hooks;
 pub mod infrastructure;
+pub mod interceptor;
 pub mod mapping;
+pub mod procedure;
 pub mod query;
+pub mod relations;
 pub mod repository;
 pub mod services;
+pub mod telemetry;
 
-pub use db::{connect_mssql, connect_postgres, DatabaseRef, DbKind};
+pub use audit::{AuditValues, Clock, CurrentUser, SystemClock};
+pub use batch::Batch;
+pub use cte::Cte;
+pub use db::{connect_mssql, connect_postgres, DatabaseRef, DbKind, DbRows};
+pub use hooks::EntityHooks;
 pub use infrastructure::generic_repository::GenericRepository;
+pub use interceptor::{
+    Interceptor, InterceptorChain, Outcome, SlowQueryLog, Statement, StatementKind,
+    StatementRewriter, TracingInterceptor,
+};
 pub use mapping::{
-    ColumnMeta, Entity, FromRowNamed, FromRowWithPrefix, KeyAsGuid, KeyAsInt, KeyAsString,
-    KeyMeta, Persistable, RelationMeta, TableMeta, Validatable,
+    AuditColumn, ColumnMeta, ConcurrencyConflict, Entity, FromRowNamed, FromRowWithPrefix,
+    HasColumns, HasRelations, JoinPart, JoinTuple, KeyAsGuid, KeyAsInt, KeyAsString, KeyAsTuple,
+    KeyMeta, MappingError, Persistable, RelationMeta, SoftDeleteKind, SoftDeleteMeta, TableMeta,
+    Validatable,
+};
+pub use procedure::{Procedure, ProcedureResult};
+pub use query::{
+    alias, Alias, Case, Column, ColumnRef, Comparable, DualQuery, Expr, FullTextMode, IntoExpr,
+    JoinQuery, JoinType, NullsOrder, PlaceholderStyle, Query, SqlParam, SqlType, TableHint, ToKey,
+    ToParam, Window,
 };
-pub use query::{DualQuery, Expr, JoinType, PlaceholderStyle, Query, SqlParam, ToParam};
+pub use relations::{BelongsTo, HasMany, IncludeLoader, IncludeScope, ManyToMany};
 pub use repository::{Crud, QueryExecutor, Repository};
 
 pub use rquery_orm_macros::Entity; // derive macro
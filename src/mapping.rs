@@ -1,4 +1,5 @@
-use crate::query::{PlaceholderStyle, SqlParam};
+use crate::audit::AuditValues;
+use crate::query::{PlaceholderStyle, SqlParam, ToParam};
 
 pub struct ColumnMeta {
     pub name: &'static str,
@@ -18,6 +19,24 @@ pub struct ColumnMeta {
     pub ignore_in_update: bool,
     pub ignore_in_insert: bool,
     pub ignore_in_delete: bool,
+    pub default_on_null: bool,
+    // Column is covered by a full-text index; the config names the
+    // PostgreSQL text search configuration (e.g. "english").
+    pub full_text: bool,
+    pub full_text_config: Option<&'static str>,
+    // Checked in the WHERE of updates and deletes; integer tokens are
+    // incremented by every update.
+    pub concurrency_token: bool,
+    pub audit: Option<AuditColumn>,
+}
+
+// Audit columns filled by the repository on insert and update.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuditColumn {
+    CreatedAt,
+    UpdatedAt,
+    CreatedBy,
+    UpdatedBy,
 }
 
 pub struct KeyMeta {
@@ -32,6 +51,9 @@ pub struct RelationMeta {
     pub foreign_key: &'static str,
     pub table: &'static str,
     pub table_number: Option<u32>,
+    pub through: Option<&'static str>,
+    pub local_key: Option<&'static str>,
+    pub remote_key: Option<&'static str>,
     pub ignore_in_update: bool,
     pub ignore_in_insert: bool,
 }
@@ -42,6 +64,75 @@ pub struct TableMeta {
     pub columns: &'static [ColumnMeta],
     pub keys: &'static [KeyMeta],
     pub relations: &'static [RelationMeta],
+    pub soft_delete: Option<SoftDeleteMeta>,
+}
+
+// Column marking rows as deleted instead of removing them.
+pub struct SoftDeleteMeta {
+    pub column: &'static str,
+    pub kind: SoftDeleteKind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SoftDeleteKind {
+    // boolean, deleted when true
+    Flag,
+    // nullable timestamp, deleted when set
+    Timestamp,
+}
+
+impl SoftDeleteMeta {
+    // Value written by a delete and by a restore. A timestamp column is
+    // stamped with `now` as the next parameter, so the repository's Clock
+    // decides the time rather than the server.
+    pub(crate) fn deleted_value(
+        &self,
+        style: PlaceholderStyle,
+        now: chrono::NaiveDateTime,
+        params: &mut Vec<SqlParam>,
+    ) -> String {
+        match self.kind {
+            SoftDeleteKind::Timestamp => {
+                params.push(SqlParam::DateTime(now));
+                style.placeholder(params.len())
+            }
+            SoftDeleteKind::Flag => flag_value(style, true).to_string(),
+        }
+    }
+
+    pub(crate) fn restored_value(&self, style: PlaceholderStyle) -> &'static str {
+        match self.kind {
+            SoftDeleteKind::Timestamp => "NULL",
+            SoftDeleteKind::Flag => flag_value(style, false),
+        }
+    }
+
+    // Condition selecting live (or, with `deleted`, deleted) rows.
+    pub(crate) fn filter_sql(
+        &self,
+        qualifier: &str,
+        style: PlaceholderStyle,
+        deleted: bool,
+    ) -> String {
+        let column = format!("{}.{}", qualifier, self.column);
+        match (self.kind, deleted) {
+            (SoftDeleteKind::Timestamp, false) => format!("{} IS NULL", column),
+            (SoftDeleteKind::Timestamp, true) => format!("{} IS NOT NULL", column),
+            (SoftDeleteKind::Flag, false) => {
+                format!("{} = {}", column, self.restored_value(style))
+            }
+            (SoftDeleteKind::Flag, true) => format!("{} = {}", column, flag_value(style, true)),
+        }
+    }
+}
+
+fn flag_value(style: PlaceholderStyle, deleted: bool) -> &'static str {
+    match (style, deleted) {
+        (PlaceholderStyle::Dollar, true) => "TRUE",
+        (PlaceholderStyle::Dollar, false) => "FALSE",
+        (PlaceholderStyle::AtP, true) => "1",
+        (PlaceholderStyle::AtP, false) => "0",
+    }
 }
 
 pub trait Entity {
@@ -60,15 +151,551 @@ pub trait FromRowWithPrefix: Sized {
     fn from_row_pg_with(row: &tokio_postgres::Row, prefix: &str) -> anyhow::Result<Self>;
 }
 
+#[derive(Debug, thiserror::Error)]
+pub enum MappingError {
+    #[error("{entity}.{field}: column {column} not found in row")]
+    MissingColumn {
+        entity: &'static str,
+        field: &'static str,
+        column: String,
+    },
+    #[error("{entity}.{field}: unexpected NULL in column {column} ({sql_type})")]
+    UnexpectedNull {
+        entity: &'static str,
+        field: &'static str,
+        column: String,
+        sql_type: String,
+    },
+    #[error("{entity}.{field}: column {column} ({sql_type}) could not be converted: {message}")]
+    Conversion {
+        entity: &'static str,
+        field: &'static str,
+        column: String,
+        sql_type: String,
+        message: String,
+    },
+}
+
+// Returned by update and delete when the concurrency token no longer
+// matches, i.e. the row was changed or removed since it was read.
+#[derive(Debug, thiserror::Error)]
+#[error("{table}: the row was modified or deleted by another transaction")]
+pub struct ConcurrencyConflict {
+    pub table: &'static str,
+}
+
+// Row accessors used by the code generated for FromRowNamed and
+// FromRowWithPrefix. They resolve the column by name and turn driver
+// failures into a MappingError that names the entity, field and column.
+pub fn ms_optional<'a, R>(
+    row: &'a tiberius::Row,
+    entity: &'static str,
+    field: &'static str,
+    column: &str,
+) -> Result<Option<R>, MappingError>
+where
+    R: tiberius::FromSql<'a>,
+{
+    let idx = ms_index(row, entity, field, column)?;
+    ms_read(row, idx, entity, field, column)
+}
+
+pub fn ms_required<'a, R>(
+    row: &'a tiberius::Row,
+    entity: &'static str,
+    field: &'static str,
+    column: &str,
+) -> Result<R, MappingError>
+where
+    R: tiberius::FromSql<'a>,
+{
+    let idx = ms_index(row, entity, field, column)?;
+    ms_read(row, idx, entity, field, column)?.ok_or_else(|| MappingError::UnexpectedNull {
+        entity,
+        field,
+        column: column.to_string(),
+        sql_type: format!("{:?}", row.columns()[idx].column_type()),
+    })
+}
+
+fn ms_index(
+    row: &tiberius::Row,
+    entity: &'static str,
+    field: &'static str,
+    column: &str,
+) -> Result<usize, MappingError> {
+    row.columns()
+        .iter()
+        .position(|c| c.name() == column)
+        .ok_or_else(|| MappingError::MissingColumn {
+            entity,
+            field,
+            column: column.to_string(),
+        })
+}
+
+fn ms_read<'a, R>(
+    row: &'a tiberius::Row,
+    idx: usize,
+    entity: &'static str,
+    field: &'static str,
+    column: &str,
+) -> Result<Option<R>, MappingError>
+where
+    R: tiberius::FromSql<'a>,
+{
+    row.try_get::<R, usize>(idx)
+        .map_err(|e| MappingError::Conversion {
+            entity,
+            field,
+            column: column.to_string(),
+            sql_type: format!("{:?}", row.columns()[idx].column_type()),
+            message: e.to_string(),
+        })
+}
+
+// SQL Server has no array or json type; array fields and `#[column(json)]`
+// fields are stored there as JSON text.
+pub fn ms_json_optional<R>(
+    row: &tiberius::Row,
+    entity: &'static str,
+    field: &'static str,
+    column: &str,
+) -> Result<Option<R>, MappingError>
+where
+    R: serde::de::DeserializeOwned,
+{
+    ms_optional::<&str>(row, entity, field, column)?
+        .map(|text| {
+            serde_json::from_str(text).map_err(|e| MappingError::Conversion {
+                entity,
+                field,
+                column: column.to_string(),
+                sql_type: "json".to_string(),
+                message: e.to_string(),
+            })
+        })
+        .transpose()
+}
+
+pub fn ms_json_required<R>(
+    row: &tiberius::Row,
+    entity: &'static str,
+    field: &'static str,
+    column: &str,
+) -> Result<R, MappingError>
+where
+    R: serde::de::DeserializeOwned,
+{
+    ms_json_optional(row, entity, field, column)?.ok_or_else(|| MappingError::UnexpectedNull {
+        entity,
+        field,
+        column: column.to_string(),
+        sql_type: "json".to_string(),
+    })
+}
+
+pub fn pg_json_optional<R>(
+    row: &tokio_postgres::Row,
+    entity: &'static str,
+    field: &'static str,
+    column: &str,
+) -> Result<Option<R>, MappingError>
+where
+    R: serde::de::DeserializeOwned,
+{
+    pg_optional::<serde_json::Value>(row, entity, field, column)?
+        .map(|value| {
+            serde_json::from_value(value).map_err(|e| MappingError::Conversion {
+                entity,
+                field,
+                column: column.to_string(),
+                sql_type: "jsonb".to_string(),
+                message: e.to_string(),
+            })
+        })
+        .transpose()
+}
+
+pub fn pg_json_required<R>(
+    row: &tokio_postgres::Row,
+    entity: &'static str,
+    field: &'static str,
+    column: &str,
+) -> Result<R, MappingError>
+where
+    R: serde::de::DeserializeOwned,
+{
+    pg_json_optional(row, entity, field, column)?.ok_or_else(|| MappingError::UnexpectedNull {
+        entity,
+        field,
+        column: column.to_string(),
+        sql_type: "jsonb".to_string(),
+    })
+}
+
+// Parameter for a `#[column(json)]` field; `None` and unit values become NULL.
+pub fn json_param<T: serde::Serialize>(column: &str, value: &T) -> anyhow::Result<SqlParam> {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::Null) => Ok(SqlParam::Null),
+        Ok(v) => Ok(SqlParam::Json(v.to_string())),
+        Err(e) => anyhow::bail!("{}: value could not be serialized as JSON: {}", column, e),
+    }
+}
+
+pub fn pg_optional<'a, R>(
+    row: &'a tokio_postgres::Row,
+    entity: &'static str,
+    field: &'static str,
+    column: &str,
+) -> Result<Option<R>, MappingError>
+where
+    R: tokio_postgres::types::FromSql<'a>,
+{
+    let idx = pg_index(row, entity, field, column)?;
+    pg_read(row, idx, entity, field, column)
+}
+
+pub fn pg_required<'a, R>(
+    row: &'a tokio_postgres::Row,
+    entity: &'static str,
+    field: &'static str,
+    column: &str,
+) -> Result<R, MappingError>
+where
+    R: tokio_postgres::types::FromSql<'a>,
+{
+    let idx = pg_index(row, entity, field, column)?;
+    pg_read(row, idx, entity, field, column)?.ok_or_else(|| MappingError::UnexpectedNull {
+        entity,
+        field,
+        column: column.to_string(),
+        sql_type: row.columns()[idx].type_().name().to_string(),
+    })
+}
+
+fn pg_index(
+    row: &tokio_postgres::Row,
+    entity: &'static str,
+    field: &'static str,
+    column: &str,
+) -> Result<usize, MappingError> {
+    pg_position(row, column).ok_or_else(|| MappingError::MissingColumn {
+        entity,
+        field,
+        column: column.to_string(),
+    })
+}
+
+// Same lookup as `Row::get(&str)`: an exact match first, then an ASCII
+// case-insensitive one, since PostgreSQL folds unquoted names to lower case.
+fn pg_position(row: &tokio_postgres::Row, column: &str) -> Option<usize> {
+    let columns = row.columns();
+    columns.iter().position(|c| c.name() == column).or_else(|| {
+        columns
+            .iter()
+            .position(|c| c.name().eq_ignore_ascii_case(column))
+    })
+}
+
+fn pg_read<'a, R>(
+    row: &'a tokio_postgres::Row,
+    idx: usize,
+    entity: &'static str,
+    field: &'static str,
+    column: &str,
+) -> Result<Option<R>, MappingError>
+where
+    R: tokio_postgres::types::FromSql<'a>,
+{
+    row.try_get::<usize, Option<R>>(idx)
+        .map_err(|e| MappingError::Conversion {
+            entity,
+            field,
+            column: column.to_string(),
+            sql_type: row.columns()[idx].type_().name().to_string(),
+            message: e.to_string(),
+        })
+}
+
+// True when the named column is absent or holds NULL, whatever its type.
+pub fn ms_is_null(row: &tiberius::Row, column: &str) -> bool {
+    use tiberius::ColumnData;
+    match row.cells().find(|(c, _)| c.name() == column) {
+        None => true,
+        Some((_, data)) => match data {
+            ColumnData::U8(v) => v.is_none(),
+            ColumnData::I16(v) => v.is_none(),
+            ColumnData::I32(v) => v.is_none(),
+            ColumnData::I64(v) => v.is_none(),
+            ColumnData::F32(v) => v.is_none(),
+            ColumnData::F64(v) => v.is_none(),
+            ColumnData::Bit(v) => v.is_none(),
+            ColumnData::String(v) => v.is_none(),
+            ColumnData::Guid(v) => v.is_none(),
+            ColumnData::Binary(v) => v.is_none(),
+            ColumnData::Numeric(v) => v.is_none(),
+            ColumnData::Xml(v) => v.is_none(),
+            ColumnData::DateTime(v) => v.is_none(),
+            ColumnData::SmallDateTime(v) => v.is_none(),
+            ColumnData::Time(v) => v.is_none(),
+            ColumnData::Date(v) => v.is_none(),
+            ColumnData::DateTime2(v) => v.is_none(),
+            ColumnData::DateTimeOffset(v) => v.is_none(),
+        },
+    }
+}
+
+pub fn pg_is_null(row: &tokio_postgres::Row, column: &str) -> bool {
+    struct AnyValue;
+    impl<'a> tokio_postgres::types::FromSql<'a> for AnyValue {
+        fn from_sql(
+            _: &tokio_postgres::types::Type,
+            _: &'a [u8],
+        ) -> Result<Self, Box<dyn std::error::Error + Sync + Send>> {
+            Ok(AnyValue)
+        }
+        fn accepts(_: &tokio_postgres::types::Type) -> bool {
+            true
+        }
+    }
+    match pg_position(row, column) {
+        None => true,
+        Some(idx) => !matches!(row.try_get::<usize, Option<AnyValue>>(idx), Ok(Some(_))),
+    }
+}
+
+// Reads a column as a SqlParam without knowing the field type up front.
+// Yields None when the row has no such column. Only the types a key can
+// have are supported; any other type is an error rather than a silently
+// dropped value.
+pub fn ms_param(row: &tiberius::Row, column: &str) -> anyhow::Result<Option<SqlParam>> {
+    use tiberius::ColumnData;
+    let Some(idx) = row.columns().iter().position(|c| c.name() == column) else {
+        return Ok(None);
+    };
+    let Some((col, data)) = row.cells().nth(idx) else {
+        return Ok(None);
+    };
+    let param = match data {
+        ColumnData::U8(v) => v.map(|v| SqlParam::I32(v as i32)),
+        ColumnData::I16(v) => v.map(|v| SqlParam::I32(v as i32)),
+        ColumnData::I32(v) => v.map(SqlParam::I32),
+        ColumnData::I64(v) => v.map(SqlParam::I64),
+        ColumnData::Bit(v) => v.map(SqlParam::Bool),
+        ColumnData::String(v) => v.as_ref().map(|v| SqlParam::Text(v.to_string())),
+        ColumnData::Guid(v) => v.map(SqlParam::Uuid),
+        ColumnData::Binary(v) => v.as_ref().map(|v| SqlParam::Bytes(v.to_vec())),
+        ColumnData::Numeric(_) => row
+            .try_get::<rust_decimal::Decimal, usize>(idx)?
+            .map(SqlParam::Decimal),
+        ColumnData::DateTime(_) | ColumnData::SmallDateTime(_) | ColumnData::DateTime2(_) => row
+            .try_get::<chrono::NaiveDateTime, usize>(idx)?
+            .map(SqlParam::DateTime),
+        _ => {
+            return Err(anyhow::anyhow!(
+                "column {} ({:?}) cannot be read as a parameter",
+                column,
+                col.column_type()
+            ))
+        }
+    };
+    Ok(Some(param.unwrap_or(SqlParam::Null)))
+}
+
+pub fn pg_param(row: &tokio_postgres::Row, column: &str) -> anyhow::Result<Option<SqlParam>> {
+    use tokio_postgres::types::Type;
+    let Some(idx) = pg_position(row, column) else {
+        return Ok(None);
+    };
+    let ty = row.columns()[idx].type_();
+    let param = if *ty == Type::INT2 {
+        row.try_get::<usize, Option<i16>>(idx)?
+            .map(|v| SqlParam::I32(v as i32))
+    } else if *ty == Type::INT4 {
+        row.try_get::<usize, Option<i32>>(idx)?.map(SqlParam::I32)
+    } else if *ty == Type::INT8 {
+        row.try_get::<usize, Option<i64>>(idx)?.map(SqlParam::I64)
+    } else if *ty == Type::BOOL {
+        row.try_get::<usize, Option<bool>>(idx)?.map(SqlParam::Bool)
+    } else if *ty == Type::TEXT || *ty == Type::VARCHAR || *ty == Type::BPCHAR || *ty == Type::NAME
+    {
+        row.try_get::<usize, Option<String>>(idx)?
+            .map(SqlParam::Text)
+    } else if *ty == Type::UUID {
+        row.try_get::<usize, Option<uuid::Uuid>>(idx)?
+            .map(SqlParam::Uuid)
+    } else if *ty == Type::TIMESTAMP {
+        row.try_get::<usize, Option<chrono::NaiveDateTime>>(idx)?
+            .map(SqlParam::DateTime)
+    } else if *ty == Type::BYTEA {
+        row.try_get::<usize, Option<Vec<u8>>>(idx)?
+            .map(SqlParam::Bytes)
+    } else if *ty == Type::JSON || *ty == Type::JSONB {
+        row.try_get::<usize, Option<serde_json::Value>>(idx)?
+            .map(|v| SqlParam::Json(v.to_string()))
+    } else if *ty == Type::INT4_ARRAY {
+        row.try_get::<usize, Option<Vec<i32>>>(idx)?
+            .map(ToParam::to_param)
+    } else if *ty == Type::INT8_ARRAY {
+        row.try_get::<usize, Option<Vec<i64>>>(idx)?
+            .map(ToParam::to_param)
+    } else if *ty == Type::TEXT_ARRAY || *ty == Type::VARCHAR_ARRAY {
+        row.try_get::<usize, Option<Vec<String>>>(idx)?
+            .map(ToParam::to_param)
+    } else {
+        return Err(anyhow::anyhow!(
+            "column {} ({}) cannot be read as a parameter",
+            column,
+            ty
+        ));
+    };
+    Ok(Some(param.unwrap_or(SqlParam::Null)))
+}
+
+// Column prefixes used for each position of a joined tuple, e.g. "t_Name".
+pub const JOIN_PREFIXES: [&str; 6] = ["t", "u", "v", "w", "x", "y"];
+
+// One element of a joined tuple. Plain entities must be present in every
+// row; `Option<E>` maps to None when all of its selected columns are NULL,
+// which is what an outer join produces for a missing match.
+pub trait JoinPart: Sized {
+    type Entity: Entity;
+    fn read_ms(row: &tiberius::Row, prefix: &str) -> anyhow::Result<Self>;
+    fn read_pg(row: &tokio_postgres::Row, prefix: &str) -> anyhow::Result<Self>;
+}
+
+impl<E: Entity + FromRowWithPrefix> JoinPart for E {
+    type Entity = E;
+    fn read_ms(row: &tiberius::Row, prefix: &str) -> anyhow::Result<Self> {
+        E::from_row_ms_with(row, prefix)
+    }
+    fn read_pg(row: &tokio_postgres::Row, prefix: &str) -> anyhow::Result<Self> {
+        E::from_row_pg_with(row, prefix)
+    }
+}
+
+impl<E: Entity + FromRowWithPrefix> JoinPart for Option<E> {
+    type Entity = E;
+    fn read_ms(row: &tiberius::Row, prefix: &str) -> anyhow::Result<Self> {
+        let all_null = E::table()
+            .columns
+            .iter()
+            .all(|c| ms_is_null(row, &format!("{}_{}", prefix, c.name)));
+        if all_null {
+            Ok(None)
+        } else {
+            E::from_row_ms_with(row, prefix).map(Some)
+        }
+    }
+    fn read_pg(row: &tokio_postgres::Row, prefix: &str) -> anyhow::Result<Self> {
+        let all_null = E::table()
+            .columns
+            .iter()
+            .all(|c| pg_is_null(row, &format!("{}_{}", prefix, c.name)));
+        if all_null {
+            Ok(None)
+        } else {
+            E::from_row_pg_with(row, prefix).map(Some)
+        }
+    }
+}
+
+// Tuples of two to six JoinParts, read from a row using JOIN_PREFIXES.
+pub trait JoinTuple: Sized {
+    fn tables() -> Vec<&'static TableMeta>;
+    fn from_row_ms(row: &tiberius::Row) -> anyhow::Result<Self>;
+    fn from_row_pg(row: &tokio_postgres::Row) -> anyhow::Result<Self>;
+}
+
+macro_rules! impl_join_tuple {
+    ($($name:ident => $idx:tt),+) => {
+        impl<$($name: JoinPart),+> JoinTuple for ($($name,)+) {
+            fn tables() -> Vec<&'static TableMeta> {
+                vec![$(<$name::Entity as Entity>::table()),+]
+            }
+            fn from_row_ms(row: &tiberius::Row) -> anyhow::Result<Self> {
+                Ok(($($name::read_ms(row, JOIN_PREFIXES[$idx])?,)+))
+            }
+            fn from_row_pg(row: &tokio_postgres::Row) -> anyhow::Result<Self> {
+                Ok(($($name::read_pg(row, JOIN_PREFIXES[$idx])?,)+))
+            }
+        }
+    };
+}
+
+impl_join_tuple!(A => 0, B => 1);
+impl_join_tuple!(A => 0, B => 1, C => 2);
+impl_join_tuple!(A => 0, B => 1, C => 2, D => 3);
+impl_join_tuple!(A => 0, B => 1, C => 2, D => 3, E => 4);
+impl_join_tuple!(A => 0, B => 1, C => 2, D => 3, E => 4, F => 5);
+
 pub trait Validatable {
     fn validate(&self) -> Result<(), Vec<String>>;
 }
 
 pub trait Persistable {
-    fn build_insert(&self, style: PlaceholderStyle) -> (String, Vec<SqlParam>, bool);
-    fn build_update(&self, style: PlaceholderStyle) -> (String, Vec<SqlParam>);
+    // Inserts and updates fail when a json column cannot be serialized.
+    fn build_insert(
+        &self,
+        style: PlaceholderStyle,
+    ) -> anyhow::Result<(String, Vec<SqlParam>, bool)>;
+    fn build_update(&self, style: PlaceholderStyle) -> anyhow::Result<(String, Vec<SqlParam>)>;
+    // Like build_insert/build_update, with audit columns taken from `audit`.
+    fn build_insert_audited(
+        &self,
+        style: PlaceholderStyle,
+        _audit: Option<&AuditValues>,
+    ) -> anyhow::Result<(String, Vec<SqlParam>, bool)> {
+        self.build_insert(style)
+    }
+    fn build_update_audited(
+        &self,
+        style: PlaceholderStyle,
+        _audit: Option<&AuditValues>,
+    ) -> anyhow::Result<(String, Vec<SqlParam>)> {
+        self.build_update(style)
+    }
+    // Like build_update_audited with `filters` ANDed into the WHERE, for an
+    // UPDATE whose rows are the new value of the concurrency token:
+    // RETURNING on PostgreSQL, OUTPUT ... INTO a table variable and a SELECT
+    // from it on SQL Server, which also works on tables with triggers.
+    fn build_update_returning(
+        &self,
+        _style: PlaceholderStyle,
+        _audit: Option<&AuditValues>,
+        _filters: &[(&'static str, SqlParam)],
+    ) -> anyhow::Result<(String, Vec<SqlParam>)> {
+        anyhow::bail!("the entity has no concurrency token to read back")
+    }
     fn build_delete(&self, style: PlaceholderStyle) -> (String, Vec<SqlParam>);
-    fn build_delete_by_key(key: SqlParam, style: PlaceholderStyle) -> (String, Vec<SqlParam>);
+    fn build_delete_by_key(key: Vec<SqlParam>, style: PlaceholderStyle) -> (String, Vec<SqlParam>);
+    // Value of a mapped column, used to match related rows by key.
+    fn column_value(&self, _column: &str) -> Option<SqlParam> {
+        None
+    }
+    // Copies the concurrency token of a row returned by an update into self.
+    fn refresh_token_ms(&mut self, _row: &tiberius::Row) -> anyhow::Result<()> {
+        Ok(())
+    }
+    fn refresh_token_pg(&mut self, _row: &tokio_postgres::Row) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+// Implemented by the derive for entities with `Vec<U>` or `Option<U>`
+// relation fields; `Relations` exposes one descriptor per field so that
+// `Query::Include(|r| r.orders)` can pick what to load.
+pub trait HasRelations: Sized {
+    type Relations;
+    fn relations() -> Self::Relations;
+}
+
+// Implemented by the derive; `Columns` holds one typed `Column` per mapped
+// field, rendered against `qualifier` (the table name or an alias).
+pub trait HasColumns: Sized {
+    type Columns;
+    fn columns(qualifier: &'static str) -> Self::Columns;
 }
 
 pub trait KeyAsInt {
@@ -82,3 +709,9 @@ pub trait KeyAsGuid {
 pub trait KeyAsString {
     fn key(&self) -> String;
 }
+
+// Implemented for entities declaring more than one #[key], in field order.
+pub trait KeyAsTuple {
+    type Key;
+    fn key(&self) -> Self::Key;
+}
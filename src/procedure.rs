@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+use std::time::Instant;
+
+use anyhow::{anyhow, Result};
+use tracing::Instrument;
+
+use crate::db::{ms_params, pg_params, DatabaseRef, DbRows};
+use crate::interceptor::{row_count, Statement, StatementKind};
+use crate::mapping::{ms_param, pg_param, FromRowNamed};
+use crate::query::{PlaceholderStyle, SqlParam, SqlType, ToParam};
+use crate::telemetry::Telemetry;
+
+const RETURN_COLUMN: &str = "rquery_return";
+
+// Stored procedure (SQL Server) or set returning function (PostgreSQL)
+// call. Arguments are passed positionally; OUTPUT parameters are named.
+//
+//     let call = Procedure::new("dbo.GetOrders").arg(42).output("Total", SqlType::Int);
+//     let res = db.execute_procedure(&call).await?;
+//     let orders: Vec<Order> = res.result_set(0)?;
+pub struct Procedure {
+    name: String,
+    args: Vec<SqlParam>,
+    outputs: Vec<(String, SqlType)>,
+}
+
+impl Procedure {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            args: Vec::new(),
+            outputs: Vec::new(),
+        }
+    }
+
+    pub fn arg<V: ToParam>(mut self, value: V) -> Self {
+        self.args.push(value.to_param());
+        self
+    }
+
+    pub fn args(mut self, values: &[SqlParam]) -> Self {
+        self.args.extend(values.iter().cloned());
+        self
+    }
+
+    // SQL Server OUTPUT parameter. On PostgreSQL OUT parameters come back
+    // as columns of the function's row and are read by name from it.
+    pub fn output(mut self, name: &str, sql_type: SqlType) -> Self {
+        self.outputs.push((name.to_string(), sql_type));
+        self
+    }
+
+    // On SQL Server the call is wrapped in a batch that captures the return
+    // value and OUTPUT parameters into a trailing result set.
+    pub fn to_sql(&self, style: PlaceholderStyle) -> Result<(String, Vec<SqlParam>)> {
+        check_identifier(&self.name)?;
+        for (name, _) in &self.outputs {
+            check_identifier(name)?;
+        }
+        let args: Vec<String> = (1..=self.args.len())
+            .map(|i| style.placeholder(i))
+            .collect();
+        let sql = match style {
+            PlaceholderStyle::Dollar => format!("SELECT * FROM {}({})", self.name, args.join(", ")),
+            PlaceholderStyle::AtP => {
+                let mut decls = vec![format!("@{} INT", RETURN_COLUMN)];
+                let mut exec_args = args;
+                let mut selects = vec![format!("@{} AS {}", RETURN_COLUMN, RETURN_COLUMN)];
+                for (name, ty) in &self.outputs {
+                    decls.push(format!("@rquery_{} {}", name, ty.to_sql(style)));
+                    exec_args.push(format!("@{} = @rquery_{} OUTPUT", name, name));
+                    selects.push(format!("@rquery_{} AS {}", name, name));
+                }
+                let mut exec = format!("EXEC @{} = {}", RETURN_COLUMN, self.name);
+                if !exec_args.is_empty() {
+                    exec.push(' ');
+                    exec.push_str(&exec_args.join(", "));
+                }
+                format!(
+                    "DECLARE {}; {}; SELECT {};",
+                    decls.join(", "),
+                    exec,
+                    selects.join(", ")
+                )
+            }
+        };
+        Ok((sql, self.args.clone()))
+    }
+}
+
+// Names are written into the statement, so only identifier characters
+// (optionally schema qualified or bracketed) are accepted.
+fn check_identifier(name: &str) -> Result<()> {
+    let ok = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '[' | ']'));
+    if ok {
+        Ok(())
+    } else {
+        Err(anyhow!("invalid procedure or parameter name: {}", name))
+    }
+}
+
+pub struct ProcedureResult {
+    result_sets: Vec<DbRows>,
+    outputs: HashMap<String, SqlParam>,
+    return_value: Option<i32>,
+}
+
+impl ProcedureResult {
+    pub fn result_set_count(&self) -> usize {
+        self.result_sets.len()
+    }
+
+    // Maps the result set at `index`; a missing set maps to no rows.
+    pub fn result_set<T: FromRowNamed>(&self, index: usize) -> Result<Vec<T>> {
+        match self.result_sets.get(index) {
+            Some(DbRows::Mssql(rows)) => rows.iter().map(T::from_row_ms).collect(),
+            Some(DbRows::Postgres(rows)) => rows.iter().map(T::from_row_pg).collect(),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    pub fn output(&self, name: &str) -> Option<&SqlParam> {
+        self.outputs.get(name)
+    }
+
+    // RETURN value of a SQL Server procedure.
+    pub fn return_value(&self) -> Option<i32> {
+        self.return_value
+    }
+}
+
+impl DatabaseRef {
+    pub async fn execute_procedure(&self, call: &Procedure) -> Result<ProcedureResult> {
+        let (sql, params) = call.to_sql(self.placeholder_style())?;
+        let telemetry = Telemetry::start(self, StatementKind::Procedure, None, &sql);
+        let result = self
+            .procedure_intercepted(call, sql, params)
+            .instrument(telemetry.span())
+            .await;
+        telemetry.finish(&result, |r| r.result_sets.iter().map(row_count).sum());
+        result
+    }
+
+    async fn procedure_intercepted(
+        &self,
+        call: &Procedure,
+        sql: String,
+        params: Vec<SqlParam>,
+    ) -> Result<ProcedureResult> {
+        match self {
+            DatabaseRef::Intercepted(inner, chain) => {
+                let mut statement = Statement::new(StatementKind::Procedure, &sql, &params);
+                chain.before(&mut statement)?;
+                let started = Instant::now();
+                let result = inner
+                    .procedure_direct(call, statement.sql.clone(), &statement.params)
+                    .await;
+                chain.after(&statement, started, &result, |r| {
+                    r.result_sets.iter().map(row_count).sum()
+                });
+                result
+            }
+            _ => self.procedure_direct(call, sql, &params).await,
+        }
+    }
+
+    async fn procedure_direct(
+        &self,
+        call: &Procedure,
+        sql: String,
+        params: &[SqlParam],
+    ) -> Result<ProcedureResult> {
+        match self {
+            DatabaseRef::Mssql(conn) => {
+                let boxed = ms_params(params)?;
+                let refs: Vec<&dyn tiberius::ToSql> =
+                    boxed.iter().map(|b| &**b as &dyn tiberius::ToSql).collect();
+                let mut guard = conn.lock().await;
+                let mut sets = guard.query(sql, &refs[..]).await?.into_results().await?;
+                let trailer = sets
+                    .pop()
+                    .ok_or_else(|| anyhow!("{} returned no status row", call.name))?;
+                let row = trailer
+                    .first()
+                    .ok_or_else(|| anyhow!("{} returned no status row", call.name))?;
+                let return_value = match ms_param(row, RETURN_COLUMN)? {
+                    Some(SqlParam::I32(v)) => Some(v),
+                    _ => None,
+                };
+                let mut outputs = HashMap::new();
+                for (name, _) in &call.outputs {
+                    let value = ms_param(row, name)?.unwrap_or(SqlParam::Null);
+                    outputs.insert(name.clone(), value);
+                }
+                Ok(ProcedureResult {
+                    result_sets: sets.into_iter().map(DbRows::Mssql).collect(),
+                    outputs,
+                    return_value,
+                })
+            }
+            DatabaseRef::Postgres(pg) => {
+                let boxed = pg_params(params)?;
+                let refs: Vec<&(dyn tokio_postgres::types::ToSql + Sync)> =
+                    boxed.iter().map(|b| &**b as _).collect();
+                let rows = pg.query(sql.as_str(), &refs[..]).await?;
+                let mut outputs = HashMap::new();
+                if let Some(row) = rows.first() {
+                    for (name, _) in &call.outputs {
+                        let value = pg_param(row, name)?.unwrap_or(SqlParam::Null);
+                        outputs.insert(name.clone(), value);
+                    }
+                }
+                Ok(ProcedureResult {
+                    result_sets: vec![DbRows::Postgres(rows)],
+                    outputs,
+                    return_value: None,
+                })
+            }
+            DatabaseRef::Intercepted(..) => {
+                Box::pin(self.procedure_intercepted(call, sql, params.to_vec())).await
+            }
+        }
+    }
+
+    // Calls a procedure or function and maps its first result set.
+    pub async fn call_procedure<T: FromRowNamed>(
+        &self,
+        name: &str,
+        params: &[SqlParam],
+    ) -> Result<Vec<T>> {
+        let call = Procedure::new(name).args(params);
+        self.execute_procedure(&call).await?.result_set(0)
+    }
+}
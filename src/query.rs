@@ -1,11 +1,17 @@
+use std::fmt;
 use std::marker::PhantomData;
-
+use std::ops::Deref;
 use std::sync::Arc;
 
-use crate::db::DatabaseRef;
-use crate::mapping::{Entity, FromRowWithPrefix};
-use anyhow::Result;
-use futures::TryStreamExt;
+use crate::cte::{with_sql, Cte, CteClause};
+use crate::db::{DatabaseRef, DbRows};
+use crate::hooks::EntityHooks;
+use crate::mapping::{
+    ms_param, pg_param, Entity, FromRowNamed, HasColumns, HasRelations, JoinTuple, KeyAsGuid,
+    KeyAsInt, KeyAsString, KeyAsTuple, TableMeta, JOIN_PREFIXES,
+};
+use crate::relations::{IncludeJoin, IncludeLoader, IncludeScope};
+use anyhow::{anyhow, Result};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum PlaceholderStyle {
@@ -13,7 +19,17 @@ pub enum PlaceholderStyle {
     Dollar,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+impl PlaceholderStyle {
+    // Placeholder for the 1-based parameter `idx`, e.g. `@P3` or `$3`.
+    pub fn placeholder(self, idx: usize) -> String {
+        match self {
+            PlaceholderStyle::AtP => format!("@P{}", idx),
+            PlaceholderStyle::Dollar => format!("${}", idx),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum SqlParam {
     I32(i32),
     I64(i64),
@@ -23,6 +39,10 @@ pub enum SqlParam {
     Decimal(rust_decimal::Decimal),
     DateTime(chrono::NaiveDateTime),
     Bytes(Vec<u8>),
+    // A PostgreSQL array; SQL Server receives it as a JSON array string.
+    Array(Vec<SqlParam>),
+    // Serialized JSON document, bound as jsonb on PostgreSQL.
+    Json(String),
     Null,
 }
 
@@ -50,7 +70,7 @@ impl ToParam for String {
         SqlParam::Text(self)
     }
 }
-impl<'a> ToParam for &'a str {
+impl ToParam for &str {
     fn to_param(self) -> SqlParam {
         SqlParam::Text(self.to_string())
     }
@@ -76,6 +96,26 @@ impl ToParam for Vec<u8> {
     }
 }
 
+macro_rules! impl_array_param {
+    ($($t:ty),+) => {
+        $(
+            impl ToParam for Vec<$t> {
+                fn to_param(self) -> SqlParam {
+                    SqlParam::Array(self.into_iter().map(ToParam::to_param).collect())
+                }
+            }
+        )+
+    };
+}
+
+impl_array_param!(i32, i64, bool, String, uuid::Uuid);
+
+impl ToParam for SqlParam {
+    fn to_param(self) -> SqlParam {
+        self
+    }
+}
+
 impl<T: ToParam> ToParam for Option<T> {
     fn to_param(self) -> SqlParam {
         match self {
@@ -85,10 +125,41 @@ impl<T: ToParam> ToParam for Option<T> {
     }
 }
 
+// Values identifying a row by its primary key. Single values map to a
+// one-column key; tuples map positionally onto `TableMeta.keys`.
+pub trait ToKey {
+    fn to_key(self) -> Vec<SqlParam>;
+}
+
+impl<T: ToParam> ToKey for T {
+    fn to_key(self) -> Vec<SqlParam> {
+        vec![self.to_param()]
+    }
+}
+
+macro_rules! impl_to_key_tuple {
+    ($($name:ident),+) => {
+        impl<$($name: ToParam),+> ToKey for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn to_key(self) -> Vec<SqlParam> {
+                let ($($name,)+) = self;
+                vec![$($name.to_param()),+]
+            }
+        }
+    };
+}
+
+impl_to_key_tuple!(A, B);
+impl_to_key_tuple!(A, B, C);
+impl_to_key_tuple!(A, B, C, D);
+
 #[derive(Clone, Debug)]
 pub enum Expr {
     Col(String),
     Param(SqlParam),
+    // Integer written into the SQL text, for arguments that must be
+    // constants such as the offset of LAG/LEAD.
+    Literal(i64),
     Binary {
         left: Box<Expr>,
         op: &'static str,
@@ -102,7 +173,71 @@ pub enum Expr {
         left: Box<Expr>,
         list: Vec<SqlParam>,
     },
+    // `IS NULL`, or `IS NOT NULL` when negated.
+    IsNull {
+        expr: Box<Expr>,
+        negated: bool,
+    },
     Group(Box<Expr>),
+    Func {
+        name: &'static str,
+        args: Vec<Expr>,
+    },
+    Case {
+        branches: Vec<(Expr, Expr)>,
+        otherwise: Option<Box<Expr>>,
+    },
+    Cast {
+        expr: Box<Expr>,
+        to: SqlType,
+    },
+    Over {
+        func: Box<Expr>,
+        window: Window,
+    },
+    // Hand written fragment whose placeholders are numbered from 1 in
+    // either style; they are renumbered to follow the enclosing statement.
+    Raw(String, Vec<SqlParam>),
+    // JSON document access: the text at a dotted path, containment of a
+    // document and presence of a top-level key.
+    JsonText {
+        expr: Box<Expr>,
+        path: Vec<String>,
+    },
+    JsonContains {
+        expr: Box<Expr>,
+        value: serde_json::Value,
+    },
+    JsonHasKey {
+        expr: Box<Expr>,
+        key: String,
+    },
+    // Full-text match; `config` is the PostgreSQL text search configuration.
+    FullText {
+        cols: Vec<Expr>,
+        query: SqlParam,
+        mode: FullTextMode,
+        config: Option<&'static str>,
+    },
+    // Relevance of a row for a full-text query. SQL Server reads it from
+    // FREETEXTTABLE/CONTAINSTABLE joined on the table's key.
+    FullTextRank {
+        table: &'static str,
+        qualifier: String,
+        key: &'static str,
+        columns: Vec<&'static str>,
+        query: SqlParam,
+        mode: FullTextMode,
+        config: Option<&'static str>,
+    },
+}
+
+// FreeText matches words in any form (FREETEXT, plainto_tsquery); Contains
+// takes the dialect's search syntax (CONTAINS, to_tsquery).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FullTextMode {
+    FreeText,
+    Contains,
 }
 
 impl Expr {
@@ -184,25 +319,142 @@ impl Expr {
             list: ps,
         }
     }
+    pub fn is_null(self) -> Expr {
+        Expr::IsNull {
+            expr: Box::new(self),
+            negated: false,
+        }
+    }
+    pub fn is_not_null(self) -> Expr {
+        Expr::IsNull {
+            expr: Box::new(self),
+            negated: true,
+        }
+    }
     pub fn group(self) -> Expr {
         Expr::Group(Box::new(self))
     }
 
+    // `NAME(arg, ...)`; the name is written verbatim, so it must not come
+    // from user input.
+    pub fn func(name: &'static str, args: Vec<Expr>) -> Expr {
+        Expr::Func { name, args }
+    }
+    // Hand written fragment, rendered in parentheses so that an OR inside
+    // it stays together.
+    pub fn raw(sql: &str, params: Vec<SqlParam>) -> Expr {
+        Expr::Raw(sql.to_string(), params)
+    }
+    pub fn coalesce(args: Vec<Expr>) -> Expr {
+        Expr::func("COALESCE", args)
+    }
+    pub fn cast(self, to: SqlType) -> Expr {
+        Expr::Cast {
+            expr: Box::new(self),
+            to,
+        }
+    }
+    pub fn case_when(condition: Expr, then: impl Into<Expr>) -> Case {
+        Case {
+            branches: vec![(condition, then.into())],
+        }
+    }
+
+    // Window functions, completed with `over`.
+    pub fn row_number() -> Expr {
+        Expr::func("ROW_NUMBER", Vec::new())
+    }
+    pub fn rank() -> Expr {
+        Expr::func("RANK", Vec::new())
+    }
+    pub fn dense_rank() -> Expr {
+        Expr::func("DENSE_RANK", Vec::new())
+    }
+    pub fn lag(expr: impl Into<Expr>, offset: u32) -> Expr {
+        Expr::func("LAG", vec![expr.into(), Expr::Literal(offset.into())])
+    }
+    pub fn lead(expr: impl Into<Expr>, offset: u32) -> Expr {
+        Expr::func("LEAD", vec![expr.into(), Expr::Literal(offset.into())])
+    }
+    pub fn over(self, window: Window) -> Expr {
+        Expr::Over {
+            func: Box::new(self),
+            window,
+        }
+    }
+
+    // `doc #>> '{a,b}'` on PostgreSQL, `JSON_VALUE(doc, '$.a.b')` on SQL
+    // Server. Numeric segments index into arrays: "items.0.name".
+    pub fn json_text(self, path: &str) -> Expr {
+        Expr::JsonText {
+            expr: Box::new(self),
+            path: path.split('.').map(str::to_string).collect(),
+        }
+    }
+
+    // `doc @> $1` on PostgreSQL. SQL Server has no containment operator, so
+    // every leaf of `value` becomes a JSON_VALUE comparison and every array
+    // item an OPENJSON lookup.
+    pub fn json_contains(self, value: serde_json::Value) -> Expr {
+        Expr::JsonContains {
+            expr: Box::new(self),
+            value,
+        }
+    }
+
+    // `doc ? $1` on PostgreSQL; on SQL Server a key holding JSON null counts
+    // as missing.
+    pub fn json_has_key(self, key: &str) -> Expr {
+        Expr::JsonHasKey {
+            expr: Box::new(self),
+            key: key.to_string(),
+        }
+    }
+
+    // Full-text predicate over one or more columns, e.g.
+    // `Expr::full_text(vec![c.title, c.body], "rust orm", Some("english"))`.
+    // `config` is the PostgreSQL text search configuration; SQL Server
+    // ignores it.
+    pub fn full_text<C: Into<Expr>>(
+        cols: Vec<C>,
+        query: &str,
+        config: Option<&'static str>,
+    ) -> Expr {
+        Expr::FullText {
+            cols: cols.into_iter().map(Into::into).collect(),
+            query: query.to_param(),
+            mode: FullTextMode::FreeText,
+            config,
+        }
+    }
+
+    pub fn full_text_contains<C: Into<Expr>>(
+        cols: Vec<C>,
+        query: &str,
+        config: Option<&'static str>,
+    ) -> Expr {
+        Expr::FullText {
+            cols: cols.into_iter().map(Into::into).collect(),
+            query: query.to_param(),
+            mode: FullTextMode::Contains,
+            config,
+        }
+    }
+
     pub fn to_sql_with(&self, style: PlaceholderStyle, params: &mut Vec<SqlParam>) -> String {
         match self {
             Expr::Col(c) => c.clone(),
+            Expr::Literal(n) => n.to_string(),
             Expr::Param(p) => {
                 params.push(p.clone());
-                let idx = params.len();
-                match style {
-                    PlaceholderStyle::AtP => format!("@P{}", idx),
-                    PlaceholderStyle::Dollar => format!("${}", idx),
-                }
+                style.placeholder(params.len())
             }
             Expr::Binary { left, op, right } => {
                 let l = left.to_sql_with(style, params);
                 let r = right.to_sql_with(style, params);
-                if *op == "AND" || *op == "OR" {
+                // OR binds looser than AND, so it is kept together as a
+                // whole wherever it ends up
+                if *op == "AND" {
                     format!("{} {} {}", l, op, r)
                 } else {
                     format!("({} {} {})", l, op, r)
@@ -210,287 +462,1507 @@ impl Expr {
             }
             Expr::Like { left, right } => {
                 params.push(right.clone());
-                let idx = params.len();
-                let ph = match style {
-                    PlaceholderStyle::AtP => format!("@P{}", idx),
-                    PlaceholderStyle::Dollar => format!("${}", idx),
-                };
+                let ph = style.placeholder(params.len());
                 format!("({} LIKE {})", left.to_sql_with(style, params), ph)
             }
-            Expr::InList { left, list } => {
-                let mut phs = Vec::new();
-                for p in list {
-                    params.push(p.clone());
-                    let idx = params.len();
-                    phs.push(match style {
-                        PlaceholderStyle::AtP => format!("@P{}", idx),
-                        PlaceholderStyle::Dollar => format!("${}", idx),
-                    });
+            // Nothing is in an empty list; `IN ()` is not valid SQL
+            Expr::InList { list, .. } if list.is_empty() => "(1 = 0)".to_string(),
+            // PostgreSQL binds the whole list as one array. SQL Server takes
+            // the list from a JSON string when fit_in_lists renders the
+            // statement again because expanding its lists bound too many
+            // parameters.
+            Expr::InList { left, list } if style == PlaceholderStyle::Dollar => {
+                let left = left.to_sql_with(style, params);
+                params.push(SqlParam::Array(list.clone()));
+                format!("{} = ANY({})", left, style.placeholder(params.len()))
+            }
+            Expr::InList { left, list } if IN_LISTS_AS_JSON.get() => {
+                let left = left.to_sql_with(style, params);
+                match (openjson_type(list), openjson_array(list)) {
+                    (Some(ty), Some(array)) => {
+                        params.push(SqlParam::Text(array));
+                        let ph = style.placeholder(params.len());
+                        if ty == OPENJSON_BINARY {
+                            format!(
+                                "{} IN (SELECT CONVERT(VARBINARY(MAX), value, 1) FROM OPENJSON({}) WITH (value {} '$'))",
+                                left, ph, ty
+                            )
+                        } else {
+                            format!(
+                                "{} IN (SELECT value FROM OPENJSON({}) WITH (value {} '$'))",
+                                left, ph, ty
+                            )
+                        }
+                    }
+                    _ => expand_in_list(left, list, style, params),
                 }
-                format!(
-                    "{} IN ({})",
-                    left.to_sql_with(style, params),
-                    phs.join(", ")
-                )
             }
+            Expr::InList { left, list } => {
+                let left = left.to_sql_with(style, params);
+                expand_in_list(left, list, style, params)
+            }
+            Expr::IsNull { expr, negated } => format!(
+                "({} IS {}NULL)",
+                expr.to_sql_with(style, params),
+                if *negated { "NOT " } else { "" }
+            ),
             Expr::Group(e) => format!("({})", e.to_sql_with(style, params)),
+            Expr::Func { name, args } => {
+                let args: Vec<String> = args.iter().map(|a| a.to_sql_with(style, params)).collect();
+                format!("{}({})", name, args.join(", "))
+            }
+            Expr::Case {
+                branches,
+                otherwise,
+            } => {
+                let mut sql = String::from("CASE");
+                for (when, then) in branches {
+                    sql.push_str(&format!(
+                        " WHEN {} THEN {}",
+                        when.to_sql_with(style, params),
+                        then.to_sql_with(style, params)
+                    ));
+                }
+                if let Some(e) = otherwise {
+                    sql.push_str(&format!(" ELSE {}", e.to_sql_with(style, params)));
+                }
+                sql.push_str(" END");
+                sql
+            }
+            Expr::Cast { expr, to } => format!(
+                "CAST({} AS {})",
+                expr.to_sql_with(style, params),
+                to.to_sql(style)
+            ),
+            Expr::Over { func, window } => {
+                let func = func.to_sql_with(style, params);
+                let mut parts = Vec::new();
+                if !window.partition_by.is_empty() {
+                    let cols: Vec<String> = window
+                        .partition_by
+                        .iter()
+                        .map(|e| e.to_sql_with(style, params))
+                        .collect();
+                    parts.push(format!("PARTITION BY {}", cols.join(", ")));
+                }
+                if !window.order_by.is_empty() {
+                    parts.push(format!(
+                        "ORDER BY {}",
+                        order_by_sql(&window.order_by, style, params)
+                    ));
+                }
+                format!("{} OVER ({})", func, parts.join(" "))
+            }
+            Expr::Raw(sql, raw_params) => {
+                let base = params.len();
+                params.extend(raw_params.iter().cloned());
+                format!("({})", renumber(sql, style, base))
+            }
+            Expr::JsonText { expr, path } => {
+                let doc = expr.to_sql_with(style, params);
+                match style {
+                    PlaceholderStyle::Dollar if path.len() == 1 => {
+                        format!("({} ->> {})", doc, sql_literal(&path[0]))
+                    }
+                    PlaceholderStyle::Dollar => format!("({} #>> {})", doc, pg_json_path(path)),
+                    PlaceholderStyle::AtP => format!("JSON_VALUE({}, {})", doc, ms_json_path(path)),
+                }
+            }
+            Expr::JsonContains { expr, value } => {
+                let doc = expr.to_sql_with(style, params);
+                match style {
+                    PlaceholderStyle::Dollar => {
+                        params.push(SqlParam::Json(value.to_string()));
+                        format!("({} @> {})", doc, style.placeholder(params.len()))
+                    }
+                    PlaceholderStyle::AtP => {
+                        let mut conds = Vec::new();
+                        ms_json_contains(&doc, &mut Vec::new(), value, 0, params, &mut conds);
+                        if conds.is_empty() {
+                            "(1 = 1)".to_string()
+                        } else {
+                            format!("({})", conds.join(" AND "))
+                        }
+                    }
+                }
+            }
+            Expr::JsonHasKey { expr, key } => {
+                let doc = expr.to_sql_with(style, params);
+                match style {
+                    PlaceholderStyle::Dollar => {
+                        params.push(SqlParam::Text(key.clone()));
+                        format!("({} ? {})", doc, style.placeholder(params.len()))
+                    }
+                    PlaceholderStyle::AtP => {
+                        let path = ms_json_path(std::slice::from_ref(key));
+                        format!(
+                            "(JSON_VALUE({doc}, {path}) IS NOT NULL OR JSON_QUERY({doc}, {path}) IS NOT NULL)",
+                            doc = doc,
+                            path = path
+                        )
+                    }
+                }
+            }
+            Expr::FullText {
+                cols,
+                query,
+                mode,
+                config,
+            } => {
+                let cols: Vec<String> = cols.iter().map(|c| c.to_sql_with(style, params)).collect();
+                params.push(query.clone());
+                let ph = style.placeholder(params.len());
+                match style {
+                    PlaceholderStyle::Dollar => {
+                        let (vector, query) = pg_text_search(&cols, *config, *mode, &ph);
+                        format!("({} @@ {})", vector, query)
+                    }
+                    PlaceholderStyle::AtP => {
+                        let func = match mode {
+                            FullTextMode::FreeText => "FREETEXT",
+                            FullTextMode::Contains => "CONTAINS",
+                        };
+                        format!("{}({}, {})", func, ms_column_list(&cols), ph)
+                    }
+                }
+            }
+            Expr::FullTextRank {
+                table,
+                qualifier,
+                key,
+                columns,
+                query,
+                mode,
+                config,
+            } => {
+                params.push(query.clone());
+                let ph = style.placeholder(params.len());
+                match style {
+                    PlaceholderStyle::Dollar => {
+                        let cols: Vec<String> = columns
+                            .iter()
+                            .map(|c| format!("{}.{}", qualifier, c))
+                            .collect();
+                        let (vector, query) = pg_text_search(&cols, *config, *mode, &ph);
+                        format!("ts_rank({}, {})", vector, query)
+                    }
+                    PlaceholderStyle::AtP => {
+                        let func = match mode {
+                            FullTextMode::FreeText => "FREETEXTTABLE",
+                            FullTextMode::Contains => "CONTAINSTABLE",
+                        };
+                        let cols: Vec<String> = columns.iter().map(|c| c.to_string()).collect();
+                        format!(
+                            "(SELECT ft.[RANK] FROM {}({}, {}, {}) AS ft WHERE ft.[KEY] = {}.{})",
+                            func,
+                            table,
+                            ms_column_list(&cols),
+                            ph,
+                            qualifier,
+                            key
+                        )
+                    }
+                }
+            }
         }
     }
 }
 
-#[macro_export]
-macro_rules! col {
-    ($name:expr) => {
-        $crate::query::Expr::Col($name.to_string())
+// `to_tsvector(..)` and the matching tsquery for a placeholder. Several
+// columns are searched as one document.
+fn pg_text_search(
+    cols: &[String],
+    config: Option<&str>,
+    mode: FullTextMode,
+    ph: &str,
+) -> (String, String) {
+    let config = config
+        .map(|c| format!("{}, ", sql_literal(c)))
+        .unwrap_or_default();
+    let doc = if cols.len() == 1 {
+        cols[0].clone()
+    } else {
+        format!("concat_ws(' ', {})", cols.join(", "))
     };
-}
-
-#[macro_export]
-macro_rules! val {
-    ($v:expr) => {
-        $crate::query::Expr::Param($crate::query::ToParam::to_param($v))
+    let func = match mode {
+        FullTextMode::FreeText => "plainto_tsquery",
+        FullTextMode::Contains => "to_tsquery",
     };
+    (
+        format!("to_tsvector({}{})", config, doc),
+        format!("{}({}{})", func, config, ph),
+    )
 }
 
-// Helper macro for column equality to reduce duplication in on! and condition!
-#[macro_export]
-macro_rules! __col_eq {
-    // Compare two columns
-    (($lt:ident :: $lf:ident), ($rt:ident :: $rf:ident)) => {
-        $crate::query::Expr::Col(format!("{}.{}", $lt::TABLE, $lt::$lf))
-            .eq($crate::query::Expr::Col(format!("{}.{}", $rt::TABLE, $rt::$rf)))
-    };
-    // Compare column to value
-    (($lt:ident :: $lf:ident), $rv:expr) => {
-        $crate::query::Expr::Col(format!("{}.{}", $lt::TABLE, $lt::$lf))
-            .eq($crate::query::Expr::Param($crate::query::ToParam::to_param($rv)))
-    };
+fn ms_column_list(cols: &[String]) -> String {
+    if cols.len() == 1 {
+        cols[0].clone()
+    } else {
+        format!("({})", cols.join(", "))
+    }
 }
 
-#[macro_export]
-macro_rules! on {
-    ($lt:ident :: $lf:ident == $rt:ident :: $rf:ident) => {
-        $crate::__col_eq!(($lt :: $lf), ($rt :: $rf))
-    };
-    ($lt:ident :: $lf:ident == $rv:expr) => {
-        $crate::__col_eq!(($lt :: $lf), $rv)
-    };
+fn sql_literal(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
 }
 
-#[macro_export]
-macro_rules! condition {
-    ($l:literal == $rv:expr) => {{
-        $crate::query::Expr::Col($l.to_string())
-            .eq($crate::query::Expr::Param($crate::query::ToParam::to_param($rv)))
-    }};
-    ($lt:ident :: $lf:ident == $rt:ident :: $rf:ident) => {
-        $crate::__col_eq!(($lt :: $lf), ($rt :: $rf))
-    };
-    ($lt:ident :: $lf:ident == $rv:expr) => {
-        $crate::__col_eq!(($lt :: $lf), $rv)
-    };
+// '{a,0,"b c"}' for the PostgreSQL #>> operator.
+fn pg_json_path(path: &[String]) -> String {
+    let segs: Vec<String> = path
+        .iter()
+        .map(|s| {
+            if !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                s.clone()
+            } else {
+                format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+            }
+        })
+        .collect();
+    sql_literal(&format!("{{{}}}", segs.join(",")))
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-pub enum JoinType {
-    Inner,
-    Left,
-    Right,
-    Full,
+// '$.a[0]."b c"' for JSON_VALUE, JSON_QUERY and OPENJSON.
+fn ms_json_path(path: &[String]) -> String {
+    let mut out = String::from("$");
+    for s in path {
+        if !s.is_empty() && s.chars().all(|c| c.is_ascii_digit()) {
+            out.push_str(&format!("[{}]", s));
+        } else if s
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            out.push_str(&format!(".{}", s));
+        } else {
+            out.push_str(&format!(
+                ".\"{}\"",
+                s.replace('\\', "\\\\").replace('"', "\\\"")
+            ));
+        }
+    }
+    sql_literal(&out)
 }
 
-impl JoinType {
-    fn to_sql(self) -> &'static str {
-        match self {
-            JoinType::Inner => "INNER JOIN",
-            JoinType::Left => "LEFT JOIN",
-            JoinType::Right => "RIGHT JOIN",
-            JoinType::Full => "FULL JOIN",
+// Expands `doc @> value` into SQL Server conditions: leaves compare with
+// JSON_VALUE, array items must match some element returned by OPENJSON.
+fn ms_json_contains(
+    doc: &str,
+    path: &mut Vec<String>,
+    value: &serde_json::Value,
+    depth: usize,
+    params: &mut Vec<SqlParam>,
+    out: &mut Vec<String>,
+) {
+    use serde_json::Value;
+    let style = PlaceholderStyle::AtP;
+    match value {
+        Value::Object(fields) => {
+            for (k, v) in fields {
+                path.push(k.clone());
+                ms_json_contains(doc, path, v, depth, params, out);
+                path.pop();
+            }
+        }
+        Value::Array(items) => {
+            let source = format!("OPENJSON({}, {})", doc, ms_json_path(path));
+            for item in items {
+                let cond = match item {
+                    Value::Object(_) | Value::Array(_) => {
+                        let alias = format!("j{}", depth);
+                        let mut inner = Vec::new();
+                        let item_doc = format!("{}.value", alias);
+                        ms_json_contains(
+                            &item_doc,
+                            &mut Vec::new(),
+                            item,
+                            depth + 1,
+                            params,
+                            &mut inner,
+                        );
+                        if inner.is_empty() {
+                            inner.push("1 = 1".to_string());
+                        }
+                        format!(
+                            "EXISTS (SELECT 1 FROM {} AS {} WHERE {})",
+                            source,
+                            alias,
+                            inner.join(" AND ")
+                        )
+                    }
+                    scalar => {
+                        params.push(json_scalar_param(scalar));
+                        format!(
+                            "EXISTS (SELECT 1 FROM {} WHERE value = {})",
+                            source,
+                            style.placeholder(params.len())
+                        )
+                    }
+                };
+                out.push(cond);
+            }
+        }
+        Value::Null => out.push(format!(
+            "JSON_VALUE({}, {}) IS NULL",
+            doc,
+            ms_json_path(path)
+        )),
+        scalar => {
+            params.push(json_scalar_param(scalar));
+            out.push(format!(
+                "JSON_VALUE({}, {}) = {}",
+                doc,
+                ms_json_path(path),
+                style.placeholder(params.len())
+            ));
         }
     }
 }
 
-pub struct DualQuery<T, U>
-where
-    T: Entity + FromRowWithPrefix,
-    U: Entity + FromRowWithPrefix,
-{
-    style: PlaceholderStyle,
-    db: Option<Arc<DatabaseRef>>,
-    filters: Vec<Expr>,
-    order_by: Option<String>,
-    top: Option<i64>,
-    join: Option<(JoinType, Expr)>,
-    _t: PhantomData<T>,
-    _u: PhantomData<U>,
+// JSON_VALUE and OPENJSON return text; only integers keep a numeric type.
+fn json_scalar_param(value: &serde_json::Value) -> SqlParam {
+    match value {
+        serde_json::Value::String(s) => SqlParam::Text(s.clone()),
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(i) => SqlParam::I64(i),
+            None => SqlParam::Text(n.to_string()),
+        },
+        other => SqlParam::Text(other.to_string()),
+    }
 }
 
-impl<T, U> DualQuery<T, U>
-where
-    T: Entity + FromRowWithPrefix,
-    U: Entity + FromRowWithPrefix,
-{
-    pub fn new(style: PlaceholderStyle) -> Self {
-        Self {
-            style,
-            db: None,
-            filters: Vec::new(),
-            order_by: None,
-            top: None,
-            join: None,
-            _t: PhantomData,
-            _u: PhantomData,
+// Rewrites `@Pn`/`$n` in a fragment as placeholder `base + n` of `style`.
+// Quoted text ('...' literals, "..." identifiers and PostgreSQL
+// $tag$...$tag$ bodies) is copied as written.
+fn renumber(sql: &str, style: PlaceholderStyle, base: usize) -> String {
+    let mut out = String::with_capacity(sql.len());
+    let mut rest = sql;
+    while let Some(pos) = rest.find(['@', '$', '\'', '"']) {
+        out.push_str(&rest[..pos]);
+        let tail = &rest[pos..];
+        let quoted = match tail.as_bytes()[0] {
+            q @ (b'\'' | b'"') => Some(tail[1..].find(q as char).map_or(tail.len(), |i| i + 2)),
+            b'$' => dollar_quoted_len(tail),
+            _ => None,
+        };
+        if let Some(len) = quoted {
+            out.push_str(&tail[..len]);
+            rest = &tail[len..];
+            continue;
+        }
+        let skip = if tail.starts_with('$') { 1 } else { 2 };
+        let marker_ok = tail.starts_with('$') || tail[1..].starts_with(['P', 'p']);
+        let digits = tail
+            .get(skip..)
+            .map(|t| t.len() - t.trim_start_matches(|c: char| c.is_ascii_digit()).len())
+            .unwrap_or(0);
+        if marker_ok && digits > 0 {
+            let n: usize = tail[skip..skip + digits].parse().unwrap_or(0);
+            out.push_str(&style.placeholder(base + n));
+            rest = &tail[skip + digits..];
+        } else {
+            out.push_str(&tail[..1]);
+            rest = &tail[1..];
         }
     }
+    out.push_str(rest);
+    out
+}
 
-    pub fn with_db(mut self, db: Arc<DatabaseRef>) -> Self {
-        self.db = Some(db);
-        self
+// Length of the $tag$...$tag$ body `tail` starts with, if it starts with
+// one; `$1` is a placeholder since tags cannot start with a digit.
+fn dollar_quoted_len(tail: &str) -> Option<usize> {
+    let tag_len = tail[1..].find(|c: char| !(c.is_alphanumeric() || c == '_'))?;
+    if tail[1..].starts_with(|c: char| c.is_ascii_digit()) || !tail[1 + tag_len..].starts_with('$')
+    {
+        return None;
     }
+    let delimiter = &tail[..tag_len + 2];
+    let body = &tail[delimiter.len()..];
+    Some(
+        body.find(delimiter)
+            .map_or(tail.len(), |i| 2 * delimiter.len() + i),
+    )
+}
 
-    pub fn Join(mut self, join_type: JoinType, on_expr: Expr) -> Self {
-        self.join = Some((join_type, on_expr));
-        self
-    }
+macro_rules! impl_arithmetic {
+    ($($trait:ident $method:ident $op:literal),+) => {
+        $(
+            impl<R: Into<Expr>> std::ops::$trait<R> for Expr {
+                type Output = Expr;
+                fn $method(self, rhs: R) -> Expr {
+                    Expr::Binary {
+                        left: Box::new(self),
+                        op: $op,
+                        right: Box::new(rhs.into()),
+                    }
+                }
+            }
+        )+
+    };
+}
 
-    pub fn Where(mut self, expr: Expr) -> Self {
-        self.filters.push(expr);
-        self
-    }
+impl_arithmetic!(Add add "+", Sub sub "-", Mul mul "*", Div div "/");
 
-    pub fn OrderBy(mut self, ob: &str) -> Self {
-        self.order_by = Some(ob.to_string());
-        self
-    }
+// CASE WHEN ... THEN ... [ELSE ...] END, started with `Expr::case_when`.
+pub struct Case {
+    branches: Vec<(Expr, Expr)>,
+}
 
-    pub fn Top(mut self, n: i64) -> Self {
-        self.top = Some(n);
+impl Case {
+    pub fn when(mut self, condition: Expr, then: impl Into<Expr>) -> Self {
+        self.branches.push((condition, then.into()));
         self
     }
 
-    pub fn to_sql(&self) -> (String, Vec<SqlParam>) {
-        let mut params = Vec::new();
-        let tname = T::table().name;
-        let uname = U::table().name;
-        let mut cols = Vec::new();
-        for c in T::table().columns {
-            cols.push(format!("{}.{} AS t_{}", tname, c.name, c.name));
+    pub fn otherwise(self, value: impl Into<Expr>) -> Expr {
+        Expr::Case {
+            branches: self.branches,
+            otherwise: Some(Box::new(value.into())),
         }
-        for c in U::table().columns {
-            cols.push(format!("{}.{} AS u_{}", uname, c.name, c.name));
-        }
-        let mut sql = String::new();
-        match self.style {
-            PlaceholderStyle::AtP => {
-                if let Some(n) = self.top {
-                    sql.push_str(&format!("SELECT TOP({}) {} FROM {}", n, cols.join(", "), tname));
-                } else {
-                    sql.push_str(&format!("SELECT {} FROM {}", cols.join(", "), tname));
-                }
-            }
-            PlaceholderStyle::Dollar => {
-                sql.push_str(&format!("SELECT {} FROM {}", cols.join(", "), tname));
-            }
-        }
-        if let Some((jt, on)) = &self.join {
-            sql.push(' ');
-            sql.push_str(jt.to_sql());
-            sql.push(' ');
-            sql.push_str(uname);
-            sql.push_str(" ON ");
-            sql.push_str(&on.to_sql_with(self.style, &mut params));
-        }
-        if !self.filters.is_empty() {
-            let mut it = self.filters.iter();
-            if let Some(first) = it.next() {
-                sql.push_str(" WHERE ");
-                sql.push_str(&first.to_sql_with(self.style, &mut params));
-                for f in it {
-                    sql.push_str(" AND ");
-                    sql.push_str(&f.to_sql_with(self.style, &mut params));
-                }
-            }
-        }
-        if let Some(ob) = &self.order_by {
-            sql.push_str(" ORDER BY ");
-            sql.push_str(ob);
-        }
-        if let Some(n) = self.top {
-            if self.style == PlaceholderStyle::Dollar {
-                sql.push_str(&format!(" LIMIT {}", n));
-            }
-        }
-        (sql, params)
     }
 
-    pub async fn to_list_async(self) -> Result<Vec<(T, U)>> {
-        let db = self.db.clone().expect("database reference not set");
-        let (sql, params) = self.to_sql();
-        match db.as_ref() {
-            DatabaseRef::Mssql(conn) => {
-                let mut guard = conn.lock().await;
-                let mut boxed: Vec<Box<dyn tiberius::ToSql + Send + Sync>> = Vec::new();
-                for p in &params {
-                    let b: Box<dyn tiberius::ToSql + Send + Sync> = match p {
-                        SqlParam::I32(v) => Box::new(*v),
-                        SqlParam::I64(v) => Box::new(*v),
-                        SqlParam::Bool(v) => Box::new(*v),
-                        SqlParam::Text(v) => Box::new(v.clone()),
-                        SqlParam::Uuid(v) => Box::new(*v),
-                        SqlParam::Decimal(v) => Box::new(v.to_string()),
-                        SqlParam::DateTime(v) => Box::new(*v),
-                        SqlParam::Bytes(v) => Box::new(v.clone()),
-                        SqlParam::Null => Box::new(Option::<i32>::None),
-                    };
-                    boxed.push(b);
-                }
-                let refs: Vec<&dyn tiberius::ToSql> =
-                    boxed.iter().map(|b| &**b as &dyn tiberius::ToSql).collect();
-                let mut stream = guard.query(&sql, &refs[..]).await?;
-                let mut out = Vec::new();
-                while let Some(item) = stream.try_next().await? {
-                    if let Some(row) = item.into_row() {
-                        let left = T::from_row_ms_with(&row, "t")?;
-                        let right = U::from_row_ms_with(&row, "u")?;
-                        out.push((left, right));
-                    }
-                }
-                Ok(out)
-            }
-            DatabaseRef::Postgres(pg) => {
-                let mut boxed: Vec<Box<dyn tokio_postgres::types::ToSql + Send + Sync>> =
-                    Vec::new();
-                for p in &params {
-                    let b: Box<dyn tokio_postgres::types::ToSql + Send + Sync> = match p {
-                        SqlParam::I32(v) => Box::new(*v),
-                        SqlParam::I64(v) => Box::new(*v),
-                        SqlParam::Bool(v) => Box::new(*v),
-                        SqlParam::Text(v) => Box::new(v.clone()),
-                        SqlParam::Uuid(v) => Box::new(*v),
-                        SqlParam::Decimal(v) => Box::new(v.to_string()),
-                        SqlParam::DateTime(v) => Box::new(*v),
-                        SqlParam::Bytes(v) => Box::new(v.clone()),
-                        SqlParam::Null => Box::new(Option::<i32>::None),
-                    };
-                    boxed.push(b);
-                }
-                let refs: Vec<&(dyn tokio_postgres::types::ToSql + Sync)> =
-                    boxed.iter().map(|b| &**b as _).collect();
-                let rows = pg.query(&sql, &refs[..]).await?;
-                let mut out = Vec::new();
-                for row in rows {
-                    let left = T::from_row_pg_with(&row, "t")?;
-                    let right = U::from_row_pg_with(&row, "u")?;
-                    out.push((left, right));
-                }
-                Ok(out)
-            }
+    pub fn end(self) -> Expr {
+        Expr::Case {
+            branches: self.branches,
+            otherwise: None,
         }
     }
 }
 
-struct JoinClause {
-    join_type: JoinType,
+impl From<Case> for Expr {
+    fn from(c: Case) -> Expr {
+        c.end()
+    }
+}
+
+// OVER (PARTITION BY ... ORDER BY ...) of a window function.
+#[derive(Clone, Debug, Default)]
+pub struct Window {
+    partition_by: Vec<Expr>,
+    order_by: Vec<OrderTerm>,
+}
+
+impl Window {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn partition_by(mut self, expr: impl Into<Expr>) -> Self {
+        self.partition_by.push(expr.into());
+        self
+    }
+
+    pub fn order_by(mut self, expr: impl Into<Expr>) -> Self {
+        self.order_by.push(OrderTerm::new(expr, false));
+        self
+    }
+
+    pub fn order_by_desc(mut self, expr: impl Into<Expr>) -> Self {
+        self.order_by.push(OrderTerm::new(expr, true));
+        self
+    }
+}
+
+// Target of `Expr::cast`, named per dialect.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SqlType {
+    Int,
+    BigInt,
+    Decimal(u8, u8),
+    Text,
+    Bool,
+    DateTime,
+    Uuid,
+}
+
+impl SqlType {
+    pub(crate) fn to_sql(self, style: PlaceholderStyle) -> String {
+        let mssql = style == PlaceholderStyle::AtP;
+        match self {
+            SqlType::Int if mssql => "INT".into(),
+            SqlType::Int => "INTEGER".into(),
+            SqlType::BigInt => "BIGINT".into(),
+            SqlType::Decimal(p, s) if mssql => format!("DECIMAL({}, {})", p, s),
+            SqlType::Decimal(p, s) => format!("NUMERIC({}, {})", p, s),
+            SqlType::Text if mssql => "NVARCHAR(MAX)".into(),
+            SqlType::Text => "TEXT".into(),
+            SqlType::Bool if mssql => "BIT".into(),
+            SqlType::Bool => "BOOLEAN".into(),
+            SqlType::DateTime if mssql => "DATETIME2".into(),
+            SqlType::DateTime => "TIMESTAMP".into(),
+            SqlType::Uuid if mssql => "UNIQUEIDENTIFIER".into(),
+            SqlType::Uuid => "UUID".into(),
+        }
+    }
+}
+
+// A column qualified by its table name or alias, e.g. `m.EmployeeId`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ColumnRef {
+    pub qualifier: &'static str,
+    pub name: &'static str,
+}
+
+impl ColumnRef {
+    pub fn new(qualifier: &'static str, name: &'static str) -> Self {
+        Self { qualifier, name }
+    }
+}
+
+impl fmt::Display for ColumnRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.qualifier, self.name)
+    }
+}
+
+impl From<ColumnRef> for Expr {
+    fn from(c: ColumnRef) -> Expr {
+        Expr::Col(c.to_string())
+    }
+}
+
+// Right-hand side of `on!`/`condition!`: values become parameters and
+// column references are rendered as columns.
+pub trait IntoExpr {
+    fn into_expr(self) -> Expr;
+}
+
+impl<T: ToParam> IntoExpr for T {
+    fn into_expr(self) -> Expr {
+        Expr::Param(self.to_param())
+    }
+}
+
+impl IntoExpr for ColumnRef {
+    fn into_expr(self) -> Expr {
+        self.into()
+    }
+}
+
+impl IntoExpr for Expr {
+    fn into_expr(self) -> Expr {
+        self
+    }
+}
+
+impl<T, V> IntoExpr for Column<T, V> {
+    fn into_expr(self) -> Expr {
+        self.into()
+    }
+}
+
+// Typed handle to a column of entity `T` whose field has type `V`, as
+// returned by `T::cols()`. Comparisons only accept operands of type `V`,
+// so `Employee::cols().country_id.eq(5)` is rejected by the compiler.
+pub struct Column<T, V> {
+    reference: ColumnRef,
+    _t: PhantomData<fn() -> (T, V)>,
+}
+
+impl<T, V> Column<T, V> {
+    pub fn new(qualifier: &'static str, name: &'static str) -> Self {
+        Self {
+            reference: ColumnRef::new(qualifier, name),
+            _t: PhantomData,
+        }
+    }
+
+    pub fn reference(&self) -> ColumnRef {
+        self.reference
+    }
+
+    // `eq(None)` and `ne(None)` test for NULL, since `= NULL` never holds.
+    pub fn eq<A: Comparable<V>>(self, rhs: A) -> Expr {
+        match rhs.into_operand() {
+            Expr::Param(SqlParam::Null) => Expr::from(self).is_null(),
+            rhs => Expr::from(self).eq(rhs),
+        }
+    }
+    pub fn ne<A: Comparable<V>>(self, rhs: A) -> Expr {
+        match rhs.into_operand() {
+            Expr::Param(SqlParam::Null) => Expr::from(self).is_not_null(),
+            rhs => Expr::from(self).ne(rhs),
+        }
+    }
+    pub fn gt<A: Comparable<V>>(self, rhs: A) -> Expr {
+        Expr::from(self).gt(rhs.into_operand())
+    }
+    pub fn ge<A: Comparable<V>>(self, rhs: A) -> Expr {
+        Expr::from(self).ge(rhs.into_operand())
+    }
+    pub fn lt<A: Comparable<V>>(self, rhs: A) -> Expr {
+        Expr::from(self).lt(rhs.into_operand())
+    }
+    pub fn le<A: Comparable<V>>(self, rhs: A) -> Expr {
+        Expr::from(self).le(rhs.into_operand())
+    }
+    pub fn in_list<A: Comparable<V>>(self, values: Vec<A>) -> Expr {
+        Expr::from(self).in_list(values.into_iter().map(|v| v.into_operand()).collect())
+    }
+
+    // JSON access for `#[column(json)]` columns, see Expr::json_text.
+    pub fn json_text(self, path: &str) -> Expr {
+        Expr::from(self).json_text(path)
+    }
+    pub fn json_contains(self, value: serde_json::Value) -> Expr {
+        Expr::from(self).json_contains(value)
+    }
+    pub fn json_has_key(self, key: &str) -> Expr {
+        Expr::from(self).json_has_key(key)
+    }
+}
+
+impl<T> Column<T, String> {
+    pub fn like(self, pattern: &str) -> Expr {
+        Expr::from(self).like(Expr::Param(pattern.to_param()))
+    }
+}
+
+impl<T> Column<T, Option<String>> {
+    pub fn like(self, pattern: &str) -> Expr {
+        Expr::from(self).like(Expr::Param(pattern.to_param()))
+    }
+}
+
+impl<T, V> Clone for Column<T, V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T, V> Copy for Column<T, V> {}
+
+impl<T, V> fmt::Debug for Column<T, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Column").field(&self.reference).finish()
+    }
+}
+
+impl<T, V> From<Column<T, V>> for Expr {
+    fn from(c: Column<T, V>) -> Expr {
+        c.reference.into()
+    }
+}
+
+// Operands accepted by a `Column<T, V>` comparison: values of type `V`
+// (plus `&str` for text and the inner type for nullable columns) and other
+// columns holding a `V`.
+pub trait Comparable<V> {
+    fn into_operand(self) -> Expr;
+}
+
+impl<V: ToParam> Comparable<V> for V {
+    fn into_operand(self) -> Expr {
+        Expr::Param(self.to_param())
+    }
+}
+
+impl<V: ToParam> Comparable<Option<V>> for V {
+    fn into_operand(self) -> Expr {
+        Expr::Param(self.to_param())
+    }
+}
+
+impl Comparable<String> for &str {
+    fn into_operand(self) -> Expr {
+        Expr::Param(self.to_param())
+    }
+}
+
+impl Comparable<Option<String>> for &str {
+    fn into_operand(self) -> Expr {
+        Expr::Param(self.to_param())
+    }
+}
+
+impl<U, V> Comparable<V> for Column<U, V> {
+    fn into_operand(self) -> Expr {
+        self.into()
+    }
+}
+
+// A named occurrence of an entity's table, needed to join the same entity
+// more than once. Derefs to the entity's columns rendered against the alias:
+//
+//     let m = alias::<Employee>("m");
+//     on!(Employees::manager_id == m.employee_id)
+pub struct Alias<T: HasColumns> {
+    table: &'static str,
+    name: &'static str,
+    columns: T::Columns,
+}
+
+pub fn alias<T: Entity + HasColumns>(name: &'static str) -> Alias<T> {
+    Alias::over(T::table().name, name)
+}
+
+impl<T: HasColumns> Alias<T> {
+    // Alias over a source other than the entity's table, e.g. a CTE.
+    pub(crate) fn over(table: &'static str, name: &'static str) -> Self {
+        Alias {
+            table,
+            name,
+            columns: T::columns(name),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    // "Table alias", as written in FROM and JOIN clauses.
+    pub fn table_ref(&self) -> String {
+        format!("{} {}", self.table, self.name)
+    }
+}
+
+impl<T: HasColumns> Deref for Alias<T> {
+    type Target = T::Columns;
+
+    fn deref(&self) -> &T::Columns {
+        &self.columns
+    }
+}
+
+#[macro_export]
+macro_rules! col {
+    ($name:expr) => {
+        $crate::query::Expr::Col($name.to_string())
+    };
+}
+
+#[macro_export]
+macro_rules! val {
+    ($v:expr) => {
+        $crate::query::Expr::Param($crate::query::ToParam::to_param($v))
+    };
+}
+
+// Helper macro for column equality to reduce duplication in on! and condition!
+#[macro_export]
+macro_rules! __col_eq {
+    // Compare two columns
+    (($lt:ident :: $lf:ident), ($rt:ident :: $rf:ident)) => {
+        $crate::query::Expr::Col(format!("{}.{}", $lt::TABLE, $lt::$lf))
+            .eq($crate::query::Expr::Col(format!("{}.{}", $rt::TABLE, $rt::$rf)))
+    };
+    // Compare an aliased column to a column, alias column or value
+    (($la:ident . $lf:ident), $rv:expr) => {
+        $crate::query::Expr::from($la.$lf).eq($crate::query::IntoExpr::into_expr($rv))
+    };
+    // Compare column to an alias column or value
+    (($lt:ident :: $lf:ident), $rv:expr) => {
+        $crate::query::Expr::Col(format!("{}.{}", $lt::TABLE, $lt::$lf))
+            .eq($crate::query::IntoExpr::into_expr($rv))
+    };
+}
+
+#[macro_export]
+macro_rules! on {
+    ($lt:ident :: $lf:ident == $rt:ident :: $rf:ident) => {
+        $crate::__col_eq!(($lt :: $lf), ($rt :: $rf))
+    };
+    ($lt:ident :: $lf:ident == $rv:expr) => {
+        $crate::__col_eq!(($lt :: $lf), $rv)
+    };
+    ($la:ident . $lf:ident == $rv:expr) => {
+        $crate::__col_eq!(($la.$lf), $rv)
+    };
+}
+
+#[macro_export]
+macro_rules! condition {
+    ($l:literal == $rv:expr) => {{
+        $crate::query::Expr::Col($l.to_string())
+            .eq($crate::query::Expr::Param($crate::query::ToParam::to_param($rv)))
+    }};
+    ($lt:ident :: $lf:ident == $rt:ident :: $rf:ident) => {
+        $crate::__col_eq!(($lt :: $lf), ($rt :: $rf))
+    };
+    ($lt:ident :: $lf:ident == $rv:expr) => {
+        $crate::__col_eq!(($lt :: $lf), $rv)
+    };
+    ($la:ident . $lf:ident == $rv:expr) => {
+        $crate::__col_eq!(($la.$lf), $rv)
+    };
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NullsOrder {
+    First,
+    Last,
+}
+
+#[derive(Clone, Debug)]
+struct OrderTerm {
+    expr: Expr,
+    desc: bool,
+    nulls: Option<NullsOrder>,
+}
+
+impl OrderTerm {
+    fn new(expr: impl Into<Expr>, desc: bool) -> Self {
+        Self {
+            expr: expr.into(),
+            desc,
+            nulls: None,
+        }
+    }
+}
+
+// ORDER BY terms of a Query or JoinQuery.
+#[derive(Clone, Debug, Default)]
+struct Ordering {
+    terms: Vec<OrderTerm>,
+}
+
+impl Ordering {
+    fn replace(&mut self, expr: impl Into<Expr>, desc: bool) {
+        self.terms = vec![OrderTerm::new(expr, desc)];
+    }
+
+    fn then(&mut self, expr: impl Into<Expr>, desc: bool) {
+        self.terms.push(OrderTerm::new(expr, desc));
+    }
+
+    // False when there is no term for the NULL placement to apply to.
+    fn nulls(&mut self, nulls: NullsOrder) -> bool {
+        match self.terms.last_mut() {
+            Some(last) => {
+                last.nulls = Some(nulls);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.terms.clear();
+    }
+
+    fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    fn to_sql(&self, style: PlaceholderStyle, params: &mut Vec<SqlParam>) -> String {
+        order_by_sql(&self.terms, style, params)
+    }
+}
+
+// OrderBy/ThenBy/NullsFirst/OrderByName builders of Query and JoinQuery.
+// Expects an `order_by: Ordering` field, an `error: Option<String>` field
+// and a `named_column` method resolving a sortable column by name.
+macro_rules! ordering_methods {
+    () => {
+        // Starts a new ordering, replacing any previous OrderBy/ThenBy.
+        pub fn OrderBy(mut self, column: impl Into<Expr>) -> Self {
+            self.order_by.replace(column, false);
+            self
+        }
+
+        pub fn OrderByDesc(mut self, column: impl Into<Expr>) -> Self {
+            self.order_by.replace(column, true);
+            self
+        }
+
+        pub fn ThenBy(mut self, column: impl Into<Expr>) -> Self {
+            self.order_by.then(column, false);
+            self
+        }
+
+        pub fn ThenByDesc(mut self, column: impl Into<Expr>) -> Self {
+            self.order_by.then(column, true);
+            self
+        }
+
+        // Places NULLs of the last ordering term first. Used before any
+        // OrderBy/ThenBy it makes the query fail when it is run.
+        pub fn NullsFirst(self) -> Self {
+            self.with_nulls(NullsOrder::First)
+        }
+
+        pub fn NullsLast(self) -> Self {
+            self.with_nulls(NullsOrder::Last)
+        }
+
+        fn with_nulls(mut self, nulls: NullsOrder) -> Self {
+            if !self.order_by.nulls(nulls) && self.error.is_none() {
+                self.error = Some("NullsFirst/NullsLast must follow OrderBy or ThenBy".to_string());
+            }
+            self
+        }
+
+        // Orders by a column given by name, e.g. from a `?sort=` parameter.
+        // Unknown names are rejected instead of being written into the SQL.
+        pub fn OrderByName(mut self, name: &str, desc: bool) -> Result<Self> {
+            self.order_by.clear();
+            self.ThenByName(name, desc)
+        }
+
+        pub fn ThenByName(mut self, name: &str, desc: bool) -> Result<Self> {
+            let column = self.named_column(name)?;
+            self.order_by.then(Expr::Col(column), desc);
+            Ok(self)
+        }
+    };
+}
+
+// Renders the ORDER BY list. SQL Server has no NULLS FIRST/LAST, so the
+// null placement is emulated with a leading CASE term.
+fn order_by_sql(
+    terms: &[OrderTerm],
+    style: PlaceholderStyle,
+    params: &mut Vec<SqlParam>,
+) -> String {
+    let mut parts = Vec::new();
+    for t in terms {
+        let e = t.expr.to_sql_with(style, params);
+        let dir = if t.desc { " DESC" } else { "" };
+        match (t.nulls, style) {
+            (None, _) => parts.push(format!("{}{}", e, dir)),
+            (Some(n), PlaceholderStyle::Dollar) => {
+                let n = match n {
+                    NullsOrder::First => "FIRST",
+                    NullsOrder::Last => "LAST",
+                };
+                parts.push(format!("{}{} NULLS {}", e, dir, n));
+            }
+            (Some(n), PlaceholderStyle::AtP) => {
+                let (null, other) = match n {
+                    NullsOrder::First => (0, 1),
+                    NullsOrder::Last => (1, 0),
+                };
+                parts.push(format!(
+                    "CASE WHEN {} IS NULL THEN {} ELSE {} END",
+                    e, null, other
+                ));
+                parts.push(format!("{}{}", e, dir));
+            }
+        }
+    }
+    parts.join(", ")
+}
+
+// Resolves a user supplied sort field (e.g. from `?sort=`) to a known
+// column so it can never inject SQL. Matching is case-insensitive.
+fn sortable_column(table: &TableMeta, name: &str) -> Option<&'static str> {
+    table
+        .columns
+        .iter()
+        .find(|c| c.name.eq_ignore_ascii_case(name))
+        .map(|c| c.name)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JoinType {
+    Inner,
+    Left,
+    Right,
+    Full,
+}
+
+impl JoinType {
+    fn to_sql(self) -> &'static str {
+        match self {
+            JoinType::Inner => "INNER JOIN",
+            JoinType::Left => "LEFT JOIN",
+            JoinType::Right => "RIGHT JOIN",
+            JoinType::Full => "FULL JOIN",
+        }
+    }
+}
+
+// Kept for the two-entity case; `(T, U)` selects with the `t_`/`u_` prefixes.
+pub type DualQuery<T, U> = JoinQuery<(T, U)>;
+
+// Typed query over a tuple of entities. The first element is the FROM table
+// and each `Join` call attaches the next element, in tuple order.
+pub struct JoinQuery<J>
+where
+    J: JoinTuple,
+{
+    style: PlaceholderStyle,
+    db: Option<Arc<DatabaseRef>>,
+    filters: Vec<Expr>,
+    order_by: Ordering,
+    top: Option<i64>,
+    distinct: bool,
+    // alias of each tuple slot, filled by As/JoinAs
+    aliases: Vec<Option<&'static str>>,
+    joins: Vec<(JoinType, Expr)>,
+    deleted: DeletedRows,
+    query_filters: Vec<(&'static str, SqlParam)>,
+    ignore_query_filters: bool,
+    // first misuse of the builder, reported by to_sql
+    error: Option<String>,
+    _j: PhantomData<J>,
+}
+
+#[allow(non_snake_case)]
+impl<J> JoinQuery<J>
+where
+    J: JoinTuple,
+{
+    pub fn new(style: PlaceholderStyle) -> Self {
+        Self {
+            style,
+            db: None,
+            filters: Vec::new(),
+            order_by: Ordering::default(),
+            top: None,
+            distinct: false,
+            aliases: vec![None; J::tables().len()],
+            joins: Vec::new(),
+            deleted: DeletedRows::Live,
+            query_filters: Vec::new(),
+            ignore_query_filters: false,
+            error: None,
+            _j: PhantomData,
+        }
+    }
+
+    pub fn with_db(mut self, db: Arc<DatabaseRef>) -> Self {
+        self.db = Some(db);
+        self
+    }
+
+    pub fn Distinct(mut self) -> Self {
+        self.distinct = true;
+        self
+    }
+
+    // Aliases the FROM table (the first tuple element).
+    pub fn As<U: Entity + HasColumns>(self, alias: &Alias<U>) -> Self {
+        self.with_alias(0, alias)
+    }
+
+    pub fn Join(mut self, join_type: JoinType, on_expr: Expr) -> Self {
+        self.joins.push((join_type, on_expr));
+        self
+    }
+
+    // Like Join, but the attached table is referenced through `alias`.
+    pub fn JoinAs<U: Entity + HasColumns>(
+        self,
+        join_type: JoinType,
+        alias: &Alias<U>,
+        on_expr: Expr,
+    ) -> Self {
+        let slot = self.joins.len() + 1;
+        self.with_alias(slot, alias).Join(join_type, on_expr)
+    }
+
+    // An alias of another table than the slot's is reported by to_sql.
+    fn with_alias<U: Entity + HasColumns>(mut self, slot: usize, alias: &Alias<U>) -> Self {
+        let table = J::tables().get(slot).map(|t| t.name);
+        if table == Some(U::table().name) {
+            self.aliases[slot] = Some(alias.name());
+        } else if self.error.is_none() {
+            self.error = Some(format!(
+                "alias {} of {} does not match join slot {}",
+                alias.name(),
+                U::table().name,
+                slot
+            ));
+        }
+        self
+    }
+
+    pub fn Where(mut self, expr: Expr) -> Self {
+        self.filters.push(expr);
+        self
+    }
+
+    // Soft deleted rows of every slot are filtered out unless one of these
+    // is used. OnlyDeleted keeps the deleted rows of the FROM table and
+    // joins the other slots regardless of their state.
+    pub fn WithDeleted(mut self) -> Self {
+        self.deleted = DeletedRows::All;
+        self
+    }
+
+    pub fn OnlyDeleted(mut self) -> Self {
+        self.deleted = DeletedRows::Only;
+        self
+    }
+
+    // Column = value conditions registered on the repository. Each one is
+    // applied to every slot whose table maps the column.
+    pub(crate) fn with_query_filters(mut self, filters: Vec<(&'static str, SqlParam)>) -> Self {
+        self.query_filters = filters;
+        self
+    }
+
+    pub fn IgnoreQueryFilters(mut self) -> Self {
+        self.ignore_query_filters = true;
+        self
+    }
+
+    // Soft delete and query filter conditions of a tuple slot, written
+    // against `qualifier`.
+    fn slot_conditions(
+        &self,
+        slot: usize,
+        table: &TableMeta,
+        qualifier: &str,
+        params: &mut Vec<SqlParam>,
+    ) -> Vec<String> {
+        let mut conds = Vec::new();
+        if !self.ignore_query_filters {
+            for (column, value) in &self.query_filters {
+                if table.columns.iter().any(|c| c.name == *column) {
+                    params.push(value.clone());
+                    conds.push(format!(
+                        "{}.{} = {}",
+                        qualifier,
+                        column,
+                        self.style.placeholder(params.len())
+                    ));
+                }
+            }
+        }
+        if let Some(meta) = &table.soft_delete {
+            match (self.deleted, slot) {
+                (DeletedRows::All, _) | (DeletedRows::Only, 1..) => {}
+                (DeletedRows::Live, _) => conds.push(meta.filter_sql(qualifier, self.style, false)),
+                (DeletedRows::Only, 0) => conds.push(meta.filter_sql(qualifier, self.style, true)),
+            }
+        }
+        conds
+    }
+
+    ordering_methods!();
+
+    fn named_column(&self, name: &str) -> Result<String> {
+        for (table, alias) in J::tables().iter().zip(&self.aliases) {
+            if let Some(c) = sortable_column(table, name) {
+                return Ok(format!("{}.{}", alias.unwrap_or(table.name), c));
+            }
+        }
+        Err(anyhow!("{} is not a sortable column", name))
+    }
+
+    pub fn Top(mut self, n: i64) -> Self {
+        self.top = Some(n);
+        self
+    }
+
+    // Fails unless every element after the first was attached by a Join
+    // and every alias matches its slot.
+    pub fn to_sql(&self) -> Result<(String, Vec<SqlParam>)> {
+        if let Some(error) = &self.error {
+            return Err(anyhow!("{}", error));
+        }
+        let tables = J::tables();
+        if !self.ignore_query_filters {
+            // a filter no slot can take would silently widen the query
+            if let Some((column, _)) = self.query_filters.iter().find(|(c, _)| {
+                !tables
+                    .iter()
+                    .any(|t| t.columns.iter().any(|m| m.name == *c))
+            }) {
+                return Err(anyhow!(
+                    "query filter column {} is not mapped on any joined table",
+                    column
+                ));
+            }
+        }
+        if self.joins.len() + 1 != tables.len() {
+            return Err(anyhow!(
+                "a join of {} tables needs {} Join calls, found {}",
+                tables.len(),
+                tables.len() - 1,
+                self.joins.len()
+            ));
+        }
+        let mut params = Vec::new();
+        let sql = fit_in_lists(self.style, &mut params, |params| self.select_sql(params));
+        Ok((sql, params))
+    }
+
+    // The statement of a validated join query.
+    fn select_sql(&self, params: &mut Vec<SqlParam>) -> String {
+        let tables = J::tables();
+        // each slot is written as "Table" or "Table alias" and its columns
+        // are qualified with whichever of the two names applies
+        let sources: Vec<(String, &str)> = tables
+            .iter()
+            .zip(&self.aliases)
+            .map(|(t, a)| match a {
+                Some(a) => (format!("{} {}", t.name, a), *a),
+                None => (t.name.to_string(), t.name),
+            })
+            .collect();
+        let mut cols = Vec::new();
+        for ((table, (_, qualifier)), prefix) in tables.iter().zip(&sources).zip(JOIN_PREFIXES) {
+            for c in table.columns {
+                cols.push(format!("{}.{} AS {}_{}", qualifier, c.name, prefix, c.name));
+            }
+        }
+        let tname = &sources[0].0;
+        let mut sql = String::from("SELECT ");
+        if self.distinct {
+            sql.push_str("DISTINCT ");
+        }
+        if let (PlaceholderStyle::AtP, Some(n)) = (self.style, self.top) {
+            sql.push_str(&format!("TOP({}) ", n));
+        }
+        sql.push_str(&format!("{} FROM {}", cols.join(", "), tname));
+        // conditions on a joined slot go into its ON clause, so that an
+        // outer join still returns the rows that have no live match
+        for (slot, ((jt, on), (source, qualifier))) in
+            self.joins.iter().zip(sources.iter().skip(1)).enumerate()
+        {
+            sql.push(' ');
+            sql.push_str(jt.to_sql());
+            sql.push(' ');
+            sql.push_str(source);
+            sql.push_str(" ON ");
+            sql.push_str(&on.to_sql_with(self.style, params));
+            for cond in self.slot_conditions(slot + 1, tables[slot + 1], qualifier, params) {
+                sql.push_str(" AND ");
+                sql.push_str(&cond);
+            }
+        }
+        let mut filters: Vec<String> = self
+            .filters
+            .iter()
+            .map(|f| f.to_sql_with(self.style, params))
+            .collect();
+        filters.extend(self.slot_conditions(0, tables[0], sources[0].1, params));
+        if !filters.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&filters.join(" AND "));
+        }
+        if !self.order_by.is_empty() {
+            sql.push_str(" ORDER BY ");
+            sql.push_str(&self.order_by.to_sql(self.style, params));
+        }
+        if let Some(n) = self.top {
+            if self.style == PlaceholderStyle::Dollar {
+                sql.push_str(&format!(" LIMIT {}", n));
+            }
+        }
+        sql
+    }
+
+    pub async fn to_list_async(self) -> Result<Vec<J>> {
+        let db = self.db.clone().expect("database reference not set");
+        let (sql, params) = self.to_sql()?;
+        let table = J::tables().first().map(|t| t.name);
+        match db.query_rows_on(table, &sql, &params).await? {
+            DbRows::Mssql(rows) => rows.iter().map(J::from_row_ms).collect(),
+            DbRows::Postgres(rows) => rows.iter().map(J::from_row_pg).collect(),
+        }
+    }
+}
+
+// Parameters a SQL Server statement may bind before its IN lists are sent
+// as single JSON parameters.
+const OPENJSON_THRESHOLD: usize = 1000;
+
+thread_local! {
+    // Set while fit_in_lists renders a statement with its IN lists as JSON.
+    static IN_LISTS_AS_JSON: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+// Clears IN_LISTS_AS_JSON again, also when rendering panics.
+struct InListsAsJson;
+
+impl InListsAsJson {
+    fn enter() -> Self {
+        IN_LISTS_AS_JSON.set(true);
+        InListsAsJson
+    }
+}
+
+impl Drop for InListsAsJson {
+    fn drop(&mut self) {
+        IN_LISTS_AS_JSON.set(false);
+    }
+}
+
+// Renders a statement with one parameter per IN list item. On SQL Server,
+// when the parameters then pass OPENJSON_THRESHOLD, counting those already
+// in `params` (earlier statements of a batch), the statement is rendered
+// again with every list sent through OPENJSON. The decision is taken on
+// the final count, so parameters bound after a list are included.
+pub(crate) fn fit_in_lists(
+    style: PlaceholderStyle,
+    params: &mut Vec<SqlParam>,
+    render: impl Fn(&mut Vec<SqlParam>) -> String,
+) -> String {
+    let start = params.len();
+    let sql = render(params);
+    if style == PlaceholderStyle::Dollar
+        || params.len() <= OPENJSON_THRESHOLD
+        || IN_LISTS_AS_JSON.get()
+    {
+        return sql;
+    }
+    params.truncate(start);
+    let _json = InListsAsJson::enter();
+    render(params)
+}
+
+// Binary items travel through OPENJSON as `0x...` strings.
+const OPENJSON_BINARY: &str = "VARCHAR(MAX)";
+
+// Column type used to read list items back out of OPENJSON. Items of mixed
+// types are read as text and converted by SQL Server to the type of the
+// compared column; None when an item cannot be written into JSON.
+fn openjson_type(list: &[SqlParam]) -> Option<&'static str> {
+    let mut ty = None;
+    for p in list {
+        let t = match p {
+            SqlParam::Null => continue,
+            SqlParam::I32(_) => "INT",
+            SqlParam::I64(_) => "BIGINT",
+            SqlParam::Bool(_) => "BIT",
+            SqlParam::Text(_) => "NVARCHAR(4000)",
+            SqlParam::Uuid(_) => "UNIQUEIDENTIFIER",
+            SqlParam::Decimal(_) => "DECIMAL(38, 10)",
+            SqlParam::DateTime(_) => "DATETIME2",
+            SqlParam::Bytes(_) => OPENJSON_BINARY,
+            SqlParam::Array(_) | SqlParam::Json(_) => return None,
+        };
+        match ty {
+            Some(prev) if prev != t => {
+                if prev == OPENJSON_BINARY || t == OPENJSON_BINARY {
+                    return None;
+                }
+                ty = Some("NVARCHAR(4000)");
+            }
+            _ => ty = Some(t),
+        }
+    }
+    ty
+}
+
+// One placeholder per item.
+fn expand_in_list(
+    left: String,
+    list: &[SqlParam],
+    style: PlaceholderStyle,
+    params: &mut Vec<SqlParam>,
+) -> String {
+    let mut phs = Vec::new();
+    for p in list {
+        params.push(p.clone());
+        phs.push(style.placeholder(params.len()));
+    }
+    format!("{} IN ({})", left, phs.join(", "))
+}
+
+// None when an item cannot be written into JSON; openjson_type already
+// keeps those lists off OPENJSON.
+fn openjson_array(list: &[SqlParam]) -> Option<String> {
+    let items = list.iter().map(|p| match p {
+        SqlParam::Bytes(v) => {
+            let hex: String = v.iter().map(|b| format!("{:02X}", b)).collect();
+            Ok(serde_json::Value::from(format!("0x{}", hex)))
+        }
+        p => json_value(p),
+    });
+    let items = items.collect::<anyhow::Result<Vec<_>>>().ok()?;
+    Some(serde_json::Value::Array(items).to_string())
+}
+
+pub(crate) fn json_array(items: &[SqlParam]) -> anyhow::Result<String> {
+    let items = items
+        .iter()
+        .map(json_value)
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(serde_json::Value::Array(items).to_string())
+}
+
+fn json_value(p: &SqlParam) -> anyhow::Result<serde_json::Value> {
+    use serde_json::Value;
+    Ok(match p {
+        SqlParam::I32(v) => Value::from(*v),
+        SqlParam::I64(v) => Value::from(*v),
+        SqlParam::Bool(v) => Value::from(*v),
+        SqlParam::Text(v) => Value::from(v.as_str()),
+        SqlParam::Uuid(v) => Value::from(v.to_string()),
+        SqlParam::Decimal(v) => Value::from(v.to_string()),
+        SqlParam::DateTime(v) => Value::from(v.format("%Y-%m-%dT%H:%M:%S%.f").to_string()),
+        SqlParam::Bytes(v) => Value::from(v.clone()),
+        SqlParam::Array(v) => {
+            Value::Array(v.iter().map(json_value).collect::<anyhow::Result<_>>()?)
+        }
+        SqlParam::Json(v) => serde_json::from_str(v)
+            .map_err(|e| anyhow::anyhow!("JSON parameter is not valid JSON: {}", e))?,
+        SqlParam::Null => Value::Null,
+    })
+}
+
+// SQL Server table hints, written as `FROM Table WITH (...)`. PostgreSQL
+// has no table hints, so they are ignored there.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TableHint {
+    NoLock,
+    ReadPast,
+    UpdLock,
+    RowLock,
+    HoldLock,
+    NoWait,
+}
+
+impl TableHint {
+    fn to_sql(self) -> &'static str {
+        match self {
+            TableHint::NoLock => "NOLOCK",
+            TableHint::ReadPast => "READPAST",
+            TableHint::UpdLock => "UPDLOCK",
+            TableHint::RowLock => "ROWLOCK",
+            TableHint::HoldLock => "HOLDLOCK",
+            TableHint::NoWait => "NOWAIT",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum LockMode {
+    Update,
+    Share,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum LockWait {
+    SkipLocked,
+    NoWait,
+}
+
+// Which rows of a soft delete entity a query returns.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DeletedRows {
+    Live,
+    All,
+    Only,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SetOp {
+    Union,
+    UnionAll,
+    Intersect,
+    Except,
+}
+
+impl SetOp {
+    fn to_sql(self) -> &'static str {
+        match self {
+            SetOp::Union => "UNION",
+            SetOp::UnionAll => "UNION ALL",
+            SetOp::Intersect => "INTERSECT",
+            SetOp::Except => "EXCEPT",
+        }
+    }
+}
+
+struct JoinClause {
+    join_type: JoinType,
     table: String,
     on: Expr,
 }
@@ -505,8 +1977,22 @@ where
     db: Option<Arc<DatabaseRef>>,
     joins: Vec<JoinClause>,
     filters: Vec<Expr>,
-    order_by: Option<String>,
+    order_by: Ordering,
     top: Option<i64>,
+    distinct: bool,
+    set_ops: Vec<(SetOp, Query<T>)>,
+    ctes: Vec<CteClause>,
+    computed: Vec<(Expr, &'static str)>,
+    lock: Option<LockMode>,
+    lock_wait: Option<LockWait>,
+    hints: Vec<TableHint>,
+    deleted: DeletedRows,
+    query_filters: Vec<(&'static str, SqlParam)>,
+    ignore_query_filters: bool,
+    includes: Vec<Box<dyn IncludeLoader<T>>>,
+    hooks: Option<Arc<dyn EntityHooks<T>>>,
+    // first misuse of the builder, reported when the query runs
+    error: Option<String>,
     _t: PhantomData<T>,
 }
 
@@ -522,8 +2008,21 @@ where
             db: None,
             joins: Vec::new(),
             filters: Vec::new(),
-            order_by: None,
+            order_by: Ordering::default(),
             top: None,
+            distinct: false,
+            set_ops: Vec::new(),
+            ctes: Vec::new(),
+            computed: Vec::new(),
+            lock: None,
+            lock_wait: None,
+            hints: Vec::new(),
+            deleted: DeletedRows::Live,
+            query_filters: Vec::new(),
+            ignore_query_filters: false,
+            includes: Vec::new(),
+            hooks: None,
+            error: None,
             _t: PhantomData,
         }
     }
@@ -538,6 +2037,25 @@ where
         self
     }
 
+    pub fn Include<F, L>(mut self, relation: F) -> Self
+    where
+        T: HasRelations,
+        F: FnOnce(T::Relations) -> L,
+        L: IncludeLoader<T> + 'static,
+    {
+        self.includes.push(Box::new(relation(T::relations())));
+        self
+    }
+
+    // Aliases the FROM table, e.g. `Employees e`.
+    pub fn As(mut self, alias: &Alias<T>) -> Self
+    where
+        T: HasColumns,
+    {
+        self.table = alias.table_ref();
+        self
+    }
+
     pub fn Join(mut self, join_type: JoinType, table: &str, on_expr: Expr) -> Self {
         self.joins.push(JoinClause {
             join_type,
@@ -547,29 +2065,367 @@ where
         self
     }
 
-    pub fn OrderBy(mut self, ob: &str) -> Self {
-        self.order_by = Some(ob.to_string());
+    pub fn JoinAs<U: Entity + HasColumns>(
+        self,
+        join_type: JoinType,
+        alias: &Alias<U>,
+        on_expr: Expr,
+    ) -> Self {
+        let table = alias.table_ref();
+        self.Join(join_type, &table, on_expr)
+    }
+
+    ordering_methods!();
+
+    fn named_column(&self, name: &str) -> Result<String> {
+        let table = T::table();
+        let column = sortable_column(table, name)
+            .ok_or_else(|| anyhow!("{} is not a sortable column of {}", name, table.name))?;
+        Ok(format!("{}.{}", self.parent(), column))
+    }
+
+    // Row locks: `FOR UPDATE`/`FOR SHARE` on PostgreSQL, UPDLOCK/HOLDLOCK
+    // with ROWLOCK table hints on SQL Server.
+    pub fn ForUpdate(mut self) -> Self {
+        self.lock = Some(LockMode::Update);
+        self
+    }
+
+    pub fn ForShare(mut self) -> Self {
+        self.lock = Some(LockMode::Share);
+        self
+    }
+
+    // Skips rows locked by other transactions (READPAST on SQL Server).
+    // Implies ForUpdate when no lock was requested.
+    pub fn SkipLocked(mut self) -> Self {
+        self.lock_wait = Some(LockWait::SkipLocked);
+        self.lock.get_or_insert(LockMode::Update);
+        self
+    }
+
+    // Fails instead of waiting for locked rows. Implies ForUpdate when no
+    // lock was requested.
+    pub fn NoWait(mut self) -> Self {
+        self.lock_wait = Some(LockWait::NoWait);
+        self.lock.get_or_insert(LockMode::Update);
+        self
+    }
+
+    pub fn WithHint(mut self, hint: TableHint) -> Self {
+        if !self.hints.contains(&hint) {
+            self.hints.push(hint);
+        }
+        self
+    }
+
+    // Table hints for SQL Server: the explicit ones plus those implied by
+    // the requested row lock.
+    fn table_hints(&self) -> Vec<&'static str> {
+        let mut hints: Vec<TableHint> = self.hints.clone();
+        let implied: &[TableHint] = match self.lock {
+            Some(LockMode::Update) => &[TableHint::UpdLock, TableHint::RowLock],
+            Some(LockMode::Share) => &[TableHint::HoldLock, TableHint::RowLock],
+            None => &[],
+        };
+        hints.extend(implied);
+        match self.lock_wait {
+            Some(LockWait::SkipLocked) => hints.push(TableHint::ReadPast),
+            Some(LockWait::NoWait) => hints.push(TableHint::NoWait),
+            None => {}
+        }
+        let mut out = Vec::new();
+        for h in hints {
+            if !out.contains(&h.to_sql()) {
+                out.push(h.to_sql());
+            }
+        }
+        out
+    }
+
+    // Soft deleted rows are filtered out unless one of these is used.
+    pub fn WithDeleted(mut self) -> Self {
+        self.deleted = DeletedRows::All;
+        self
+    }
+
+    pub fn OnlyDeleted(mut self) -> Self {
+        self.deleted = DeletedRows::Only;
+        self
+    }
+
+    // Column = value conditions registered on the repository, e.g. the
+    // current tenant. They are kept apart from Where so that
+    // IgnoreQueryFilters can drop them.
+    pub(crate) fn with_query_filters(mut self, filters: Vec<(&'static str, SqlParam)>) -> Self {
+        self.query_filters = filters;
+        self
+    }
+
+    pub fn IgnoreQueryFilters(mut self) -> Self {
+        self.ignore_query_filters = true;
+        self
+    }
+
+    fn include_scope(&self) -> IncludeScope {
+        IncludeScope {
+            style: self.style,
+            with_deleted: self.deleted != DeletedRows::Live,
+            query_filters: if self.ignore_query_filters {
+                Vec::new()
+            } else {
+                self.query_filters.clone()
+            },
+        }
+    }
+
+    fn soft_delete_sql(&self) -> Option<String> {
+        let meta = T::table().soft_delete.as_ref()?;
+        match self.deleted {
+            DeletedRows::All => None,
+            DeletedRows::Live => Some(meta.filter_sql(self.parent(), self.style, false)),
+            DeletedRows::Only => Some(meta.filter_sql(self.parent(), self.style, true)),
+        }
+    }
+
+    // Full-text search over the columns declared with `#[column(full_text)]`.
+    // An entity without such columns is reported by to_sql.
+    pub fn Search(self, query: &str) -> Self {
+        let (columns, config) = match self.search_columns() {
+            Ok(found) => found,
+            Err(e) => return self.fail(e),
+        };
+        let parent = self.parent().to_string();
+        let expr = Expr::FullText {
+            cols: columns
+                .iter()
+                .map(|c| Expr::Col(format!("{}.{}", parent, c)))
+                .collect(),
+            query: query.to_param(),
+            mode: FullTextMode::FreeText,
+            config,
+        };
+        self.Where(expr)
+    }
+
+    // Orders by relevance for `query`, best matches first. SQL Server joins
+    // the rank on the key, so an entity without one is reported by to_sql.
+    pub fn OrderBySearchRank(self, query: &str) -> Self {
+        let (columns, config) = match self.search_columns() {
+            Ok(found) => found,
+            Err(e) => return self.fail(e),
+        };
+        let table = T::table();
+        let Some(key) = table.keys.first() else {
+            let e = format!("{} needs a key to rank full-text matches", table.name);
+            return self.fail(e);
+        };
+        let rank = Expr::FullTextRank {
+            table: table.name,
+            qualifier: self.parent().to_string(),
+            key: key.column,
+            columns,
+            query: query.to_param(),
+            mode: FullTextMode::FreeText,
+            config,
+        };
+        self.OrderByDesc(rank)
+    }
+
+    fn search_columns(
+        &self,
+    ) -> std::result::Result<(Vec<&'static str>, Option<&'static str>), String> {
+        let table = T::table();
+        let cols: Vec<_> = table.columns.iter().filter(|c| c.full_text).collect();
+        if cols.is_empty() {
+            return Err(format!(
+                "{} declares no #[column(full_text)] columns",
+                table.name
+            ));
+        }
+        let config = cols.iter().find_map(|c| c.full_text_config);
+        Ok((cols.iter().map(|c| c.name).collect(), config))
+    }
+
+    // Keeps the first builder error for to_sql to report.
+    fn fail(mut self, error: String) -> Self {
+        if self.error.is_none() {
+            self.error = Some(error);
+        }
+        self
+    }
+
+    // Adds `expr AS alias` to the selected columns, next to the entity's.
+    pub fn Compute(mut self, expr: impl Into<Expr>, alias: &'static str) -> Self {
+        self.computed.push((expr.into(), alias));
         self
     }
 
+    fn computed_sql(&self, params: &mut Vec<SqlParam>) -> Vec<String> {
+        self.computed
+            .iter()
+            .map(|(e, alias)| format!("{} AS {}", e.to_sql_with(self.style, params), alias))
+            .collect()
+    }
+
+    // The FROM table is referenced by its alias when given as "Table T".
+    fn parent(&self) -> &str {
+        self.table.split_whitespace().last().unwrap_or(&self.table)
+    }
+
     pub fn Top(mut self, n: i64) -> Self {
         self.top = Some(n);
         self
     }
 
-    pub fn to_sql(&self) -> (String, Vec<SqlParam>) {
+    pub fn Distinct(mut self) -> Self {
+        self.distinct = true;
+        self
+    }
+
+    // Set operators combine this query with another over the same entity.
+    // OrderBy, Top and Include on the left-hand query apply to the combined
+    // result.
+    pub fn Union(self, other: Query<T>) -> Self {
+        self.combine(SetOp::Union, other)
+    }
+
+    pub fn UnionAll(self, other: Query<T>) -> Self {
+        self.combine(SetOp::UnionAll, other)
+    }
+
+    pub fn Intersect(self, other: Query<T>) -> Self {
+        self.combine(SetOp::Intersect, other)
+    }
+
+    pub fn Except(self, other: Query<T>) -> Self {
+        self.combine(SetOp::Except, other)
+    }
+
+    fn combine(mut self, op: SetOp, other: Query<T>) -> Self {
+        self.set_ops.push((op, other));
+        self
+    }
+
+    // Defines a common table expression; the query can then select from it
+    // with `As(&cte.alias(..))` or join it with `JoinAs`.
+    pub fn With<U>(mut self, cte: Cte<U>) -> Self
+    where
+        U: HasColumns,
+    {
+        self.ctes.push(cte.into_clause());
+        self
+    }
+
+    // Fails when the builder was misused, like JoinQuery::to_sql.
+    pub fn to_sql(&self) -> Result<(String, Vec<SqlParam>)> {
+        self.check()?;
         let mut params = Vec::new();
-        let mut sql = String::new();
-        match self.style {
-            PlaceholderStyle::AtP => {
-                if let Some(n) = self.top {
-                    sql.push_str(&format!("SELECT TOP({}) * FROM {}", n, self.table));
-                } else {
-                    sql.push_str(&format!("SELECT * FROM {}", self.table));
+        let sql = fit_in_lists(self.style, &mut params, |params| self.statement_sql(params));
+        Ok((sql, params))
+    }
+
+    // Whole statement with CTEs, numbered after the entries of `params`.
+    pub(crate) fn statement_sql(&self, params: &mut Vec<SqlParam>) -> String {
+        let mut sql = with_sql(&self.ctes, self.style, params);
+        sql.push_str(&self.render(params));
+        sql
+    }
+
+    // Renders the full statement, numbering placeholders after those
+    // already in `params` so it can be embedded in a larger statement.
+    fn render(&self, params: &mut Vec<SqlParam>) -> String {
+        let parent = self.parent();
+        let scope = self.include_scope();
+        let include_joins: Vec<IncludeJoin> = self
+            .includes
+            .iter()
+            .enumerate()
+            .filter_map(|(i, inc)| inc.join(parent, &format!("i{}", i), &scope, params))
+            .collect();
+        let mut cols = if include_joins.is_empty() {
+            vec!["*".to_string()]
+        } else {
+            let mut cols = vec![format!("{}.*", parent)];
+            for j in &include_joins {
+                cols.extend(j.columns.iter().cloned());
+            }
+            cols
+        };
+        // rendered first: the projection precedes every other placeholder
+        cols.extend(self.computed_sql(params));
+        let projection = cols.join(", ");
+        let mut sql = if self.set_ops.is_empty() {
+            self.select_sql(&projection, self.top, &include_joins, params)
+        } else {
+            // the combined rows are exposed under the parent name so that
+            // ordering and includes keep resolving against it
+            let mut inner = self.select_sql("*", None, &[], params);
+            for (i, (op, q)) in self.set_ops.iter().enumerate() {
+                // each operator applies to everything before it, whatever
+                // the dialect's precedence (INTERSECT binds tighter)
+                if i > 0 {
+                    inner = format!("({})", inner);
                 }
+                inner.push(' ');
+                inner.push_str(op.to_sql());
+                inner.push(' ');
+                inner.push_str(&q.operand_sql(params));
+            }
+            let mut sql = String::from("SELECT ");
+            if let (PlaceholderStyle::AtP, Some(n)) = (self.style, self.top) {
+                sql.push_str(&format!("TOP({}) ", n));
+            }
+            sql.push_str(&format!("{} FROM ({}) AS {}", projection, inner, parent));
+            for j in &include_joins {
+                sql.push(' ');
+                sql.push_str(&j.clause);
+            }
+            sql
+        };
+        if !self.order_by.is_empty() {
+            sql.push_str(" ORDER BY ");
+            sql.push_str(&self.order_by.to_sql(self.style, params));
+        }
+        if let Some(n) = self.top {
+            if self.style == PlaceholderStyle::Dollar {
+                sql.push_str(&format!(" LIMIT {}", n));
+            }
+        }
+        if let (PlaceholderStyle::Dollar, Some(lock)) = (self.style, self.lock) {
+            sql.push_str(match lock {
+                LockMode::Update => " FOR UPDATE",
+                LockMode::Share => " FOR SHARE",
+            });
+            match self.lock_wait {
+                Some(LockWait::SkipLocked) => sql.push_str(" SKIP LOCKED"),
+                Some(LockWait::NoWait) => sql.push_str(" NOWAIT"),
+                None => {}
             }
-            PlaceholderStyle::Dollar => {
-                sql.push_str(&format!("SELECT * FROM {}", self.table));
+        }
+        sql
+    }
+
+    // SELECT ... FROM ... JOIN ... WHERE ..., without ordering or LIMIT.
+    fn select_sql(
+        &self,
+        projection: &str,
+        top: Option<i64>,
+        include_joins: &[IncludeJoin],
+        params: &mut Vec<SqlParam>,
+    ) -> String {
+        let mut sql = String::from("SELECT ");
+        if self.distinct {
+            sql.push_str("DISTINCT ");
+        }
+        if let (PlaceholderStyle::AtP, Some(n)) = (self.style, top) {
+            sql.push_str(&format!("TOP({}) ", n));
+        }
+        sql.push_str(&format!("{} FROM {}", projection, self.table));
+        if self.style == PlaceholderStyle::AtP {
+            let hints = self.table_hints();
+            if !hints.is_empty() {
+                sql.push_str(&format!(" WITH ({})", hints.join(", ")));
             }
         }
         for j in &self.joins {
@@ -578,112 +2434,275 @@ where
             sql.push(' ');
             sql.push_str(&j.table);
             sql.push_str(" ON ");
-            sql.push_str(&j.on.to_sql_with(self.style, &mut params));
-        }
-        if !self.filters.is_empty() {
-            let mut it = self.filters.iter();
-            if let Some(first) = it.next() {
-                sql.push_str(" WHERE ");
-                sql.push_str(&first.to_sql_with(self.style, &mut params));
-                for f in it {
-                    sql.push_str(" AND ");
-                    sql.push_str(&f.to_sql_with(self.style, &mut params));
-                }
+            sql.push_str(&j.on.to_sql_with(self.style, params));
+        }
+        for j in include_joins {
+            sql.push(' ');
+            sql.push_str(&j.clause);
+        }
+        let mut filters: Vec<String> = self
+            .filters
+            .iter()
+            .map(|f| f.to_sql_with(self.style, params))
+            .collect();
+        let mut implicit = Vec::new();
+        if !self.ignore_query_filters {
+            for (column, value) in &self.query_filters {
+                params.push(value.clone());
+                implicit.push(format!(
+                    "{}.{} = {}",
+                    self.parent(),
+                    column,
+                    self.style.placeholder(params.len())
+                ));
             }
         }
-        if let Some(ob) = &self.order_by {
-            sql.push_str(" ORDER BY ");
-            sql.push_str(ob);
+        implicit.extend(self.soft_delete_sql());
+        filters.extend(implicit);
+        if !filters.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&filters.join(" AND "));
         }
-        if let Some(n) = self.top {
-            if self.style == PlaceholderStyle::Dollar {
-                sql.push_str(&format!(" LIMIT {}", n));
+        sql
+    }
+
+    // One member of a CTE body. Columns are listed explicitly so that the
+    // anchor and recursive members line up even when the latter joins.
+    pub(crate) fn cte_member_sql(&self, params: &mut Vec<SqlParam>) -> String {
+        if !self.order_by.is_empty() || self.top.is_some() || !self.set_ops.is_empty() {
+            return self.operand_sql(params);
+        }
+        let parent = self.parent();
+        let columns = T::table().columns;
+        let mut cols: Vec<String> = if columns.is_empty() {
+            vec![format!("{}.*", parent)]
+        } else {
+            columns
+                .iter()
+                .map(|c| format!("{}.{}", parent, c.name))
+                .collect()
+        };
+        cols.extend(self.computed_sql(params));
+        self.select_sql(&cols.join(", "), None, &[], params)
+    }
+
+    // Right-hand side of a set operator. Operands that order, limit or
+    // combine on their own are wrapped in a derived table.
+    fn operand_sql(&self, params: &mut Vec<SqlParam>) -> String {
+        if self.order_by.is_empty() && self.top.is_none() && self.set_ops.is_empty() {
+            self.select_sql("*", None, &[], params)
+        } else {
+            format!(
+                "SELECT * FROM ({}) AS {}",
+                self.render(params),
+                self.parent()
+            )
+        }
+    }
+
+    // Fails when the builder was misused, e.g. NullsFirst before OrderBy,
+    // a CTE without a body or an ordered set operand without Top on SQL
+    // Server. to_sql and batches check this before rendering.
+    pub(crate) fn check(&self) -> Result<()> {
+        if let Some(error) = &self.error {
+            return Err(anyhow!("{}", error));
+        }
+        if !self.ignore_query_filters {
+            // a filter on a column the entity does not map makes the WHERE invalid
+            let table = T::table();
+            if let Some((column, _)) = self
+                .query_filters
+                .iter()
+                .find(|(c, _)| !table.columns.iter().any(|m| m.name == *c))
+            {
+                return Err(anyhow!(
+                    "query filter column {} is not mapped on {}",
+                    column,
+                    table.name
+                ));
+            }
+        }
+        for cte in &self.ctes {
+            cte.check()?;
+        }
+        for (_, q) in &self.set_ops {
+            // SQL Server only orders a derived table together with TOP
+            if q.style == PlaceholderStyle::AtP && !q.order_by.is_empty() && q.top.is_none() {
+                return Err(anyhow!(
+                    "an operand of a set operator that orders its rows needs Top on SQL Server"
+                ));
             }
+            q.check()?;
         }
-        (sql, params)
+        Ok(())
     }
 
-    pub async fn to_list_async(self) -> Result<Vec<T>> {
+    pub async fn to_list_async(self) -> Result<Vec<T>>
+    where
+        T: Send,
+    {
+        let (sql, params) = self.to_sql()?;
         let db = self.db.clone().expect("database reference not set");
-        let (sql, params) = self.to_sql();
-        match db.as_ref() {
-            DatabaseRef::Mssql(conn) => {
-                let mut guard = conn.lock().await;
-                let mut boxed: Vec<Box<dyn tiberius::ToSql + Send + Sync>> = Vec::new();
-                for p in &params {
-                    let b: Box<dyn tiberius::ToSql + Send + Sync> = match p {
-                        SqlParam::I32(v) => Box::new(*v),
-                        SqlParam::I64(v) => Box::new(*v),
-                        SqlParam::Bool(v) => Box::new(*v),
-                        SqlParam::Text(v) => Box::new(v.clone()),
-                        SqlParam::Uuid(v) => Box::new(*v),
-                        SqlParam::Decimal(v) => Box::new(v.to_string()),
-                        SqlParam::DateTime(v) => Box::new(*v),
-                        SqlParam::Bytes(v) => Box::new(v.clone()),
-                        SqlParam::Null => Box::new(Option::<i32>::None),
-                    };
-                    boxed.push(b);
-                }
-                let refs: Vec<&dyn tiberius::ToSql> =
-                    boxed.iter().map(|b| &**b as &dyn tiberius::ToSql).collect();
-                let mut stream = guard.query(&sql, &refs[..]).await?;
-                let mut out = Vec::new();
-                while let Some(item) = stream.try_next().await? {
-                    if let Some(row) = item.into_row() {
-                        out.push(T::from_row_ms(&row)?);
+        let rows = db
+            .query_rows_on(Some(T::table().name), &sql, &params)
+            .await?;
+        self.materialize(&db, rows).await
+    }
+
+    // Like to_list_async, but also maps each row into `X`, whose fields name
+    // the aliases given to Compute.
+    pub async fn to_list_with_computed_async<X>(self) -> Result<Vec<(T, X)>>
+    where
+        T: Send,
+        X: FromRowNamed,
+    {
+        let (sql, params) = self.to_sql()?;
+        let db = self.db.clone().expect("database reference not set");
+        let rows = db
+            .query_rows_on(Some(T::table().name), &sql, &params)
+            .await?;
+        let computed = match &rows {
+            DbRows::Mssql(rows) => rows
+                .iter()
+                .map(X::from_row_ms)
+                .collect::<Result<Vec<_>>>()?,
+            DbRows::Postgres(rows) => rows
+                .iter()
+                .map(X::from_row_pg)
+                .collect::<Result<Vec<_>>>()?,
+        };
+        let list = self.materialize(&db, rows).await?;
+        Ok(list.into_iter().zip(computed).collect())
+    }
+
+    // Maps rows fetched for this query, runs its follow-up includes and
+    // then the after_load hook.
+    pub(crate) async fn materialize(&self, db: &Arc<DatabaseRef>, rows: DbRows) -> Result<Vec<T>>
+    where
+        T: Send,
+    {
+        let mut out: Vec<T> = self
+            .map_rows(rows, None)?
+            .into_iter()
+            .map(|(e, _)| e)
+            .collect();
+        let scope = self.include_scope();
+        for inc in &self.includes {
+            inc.load(db, &scope, &mut out).await?;
+        }
+        if let Some(hooks) = &self.hooks {
+            for entity in &mut out {
+                hooks.after_load(entity, db).await?;
+            }
+        }
+        Ok(out)
+    }
+
+    pub(crate) fn with_hooks(mut self, hooks: Option<Arc<dyn EntityHooks<T>>>) -> Self {
+        self.hooks = hooks;
+        self
+    }
+
+    pub(crate) fn with_include(mut self, include: Box<dyn IncludeLoader<T>>) -> Self {
+        self.includes.push(include);
+        self
+    }
+
+    // Like to_list_async, but also returns the value of `tag` for each row.
+    // Used by loaders that select an extra owner column next to the entity.
+    pub(crate) async fn fetch_tagged_async(self, tag: &str) -> Result<Vec<(T, Option<SqlParam>)>>
+    where
+        T: Send,
+    {
+        let (sql, params) = self.to_sql()?;
+        let db = self.db.clone().expect("database reference not set");
+        let rows = db
+            .query_rows_on(Some(T::table().name), &sql, &params)
+            .await?;
+        self.map_rows(rows, Some(tag))
+    }
+
+    fn map_rows(&self, rows: DbRows, tag: Option<&str>) -> Result<Vec<(T, Option<SqlParam>)>> {
+        let mut out = Vec::new();
+        match rows {
+            DbRows::Mssql(rows) => {
+                for row in rows {
+                    let mut entity = T::from_row_ms(&row)?;
+                    for (i, inc) in self.includes.iter().enumerate() {
+                        inc.apply_ms(&row, &format!("i{}", i), &mut entity)?;
                     }
-                }
-                Ok(out)
-            }
-            DatabaseRef::Postgres(pg) => {
-                let mut boxed: Vec<Box<dyn tokio_postgres::types::ToSql + Send + Sync>> =
-                    Vec::new();
-                for p in &params {
-                    let b: Box<dyn tokio_postgres::types::ToSql + Send + Sync> = match p {
-                        SqlParam::I32(v) => Box::new(*v),
-                        SqlParam::I64(v) => Box::new(*v),
-                        SqlParam::Bool(v) => Box::new(*v),
-                        SqlParam::Text(v) => Box::new(v.clone()),
-                        SqlParam::Uuid(v) => Box::new(*v),
-                        SqlParam::Decimal(v) => Box::new(v.to_string()),
-                        SqlParam::DateTime(v) => Box::new(*v),
-                        SqlParam::Bytes(v) => Box::new(v.clone()),
-                        SqlParam::Null => Box::new(Option::<i32>::None),
+                    let tagged = match tag {
+                        Some(t) => ms_param(&row, t)?,
+                        None => None,
                     };
-                    boxed.push(b);
+                    out.push((entity, tagged));
                 }
-                let refs: Vec<&(dyn tokio_postgres::types::ToSql + Sync)> =
-                    boxed.iter().map(|b| &**b as _).collect();
-                let rows = pg.query(&sql, &refs[..]).await?;
-                let mut out = Vec::new();
+            }
+            DbRows::Postgres(rows) => {
                 for row in rows {
-                    out.push(T::from_row_pg(&row)?);
+                    let mut entity = T::from_row_pg(&row)?;
+                    for (i, inc) in self.includes.iter().enumerate() {
+                        inc.apply_pg(&row, &format!("i{}", i), &mut entity)?;
+                    }
+                    let tagged = match tag {
+                        Some(t) => pg_param(&row, t)?,
+                        None => None,
+                    };
+                    out.push((entity, tagged));
                 }
-                Ok(out)
             }
         }
+        Ok(out)
+    }
+
+    pub(crate) fn style(&self) -> PlaceholderStyle {
+        self.style
     }
 
-    pub async fn to_single_async(self) -> Result<Option<T>> {
+    pub async fn to_single_async(self) -> Result<Option<T>>
+    where
+        T: Send,
+    {
         let mut list = self.Top(1).to_list_async().await?;
         Ok(list.pop())
     }
 
-    pub async fn ToDictionaryKeyIntAsync(
-        self,
-    ) -> anyhow::Result<std::collections::HashMap<i32, T>> {
-        unimplemented!("execution not implemented");
+    pub async fn ToDictionaryKeyIntAsync(self) -> anyhow::Result<std::collections::HashMap<i32, T>>
+    where
+        T: KeyAsInt + Send,
+    {
+        let list = self.to_list_async().await?;
+        Ok(list.into_iter().map(|e| (e.key(), e)).collect())
     }
 
     pub async fn ToDictionaryKeyGuidAsync(
         self,
-    ) -> anyhow::Result<std::collections::HashMap<uuid::Uuid, T>> {
-        unimplemented!("execution not implemented");
+    ) -> anyhow::Result<std::collections::HashMap<uuid::Uuid, T>>
+    where
+        T: KeyAsGuid + Send,
+    {
+        let list = self.to_list_async().await?;
+        Ok(list.into_iter().map(|e| (e.key(), e)).collect())
     }
 
     pub async fn ToDictionaryKeyStringAsync(
         self,
-    ) -> anyhow::Result<std::collections::HashMap<String, T>> {
-        unimplemented!("execution not implemented");
+    ) -> anyhow::Result<std::collections::HashMap<String, T>>
+    where
+        T: KeyAsString + Send,
+    {
+        let list = self.to_list_async().await?;
+        Ok(list.into_iter().map(|e| (e.key(), e)).collect())
+    }
+
+    pub async fn ToDictionaryKeyTupleAsync(
+        self,
+    ) -> anyhow::Result<std::collections::HashMap<<T as KeyAsTuple>::Key, T>>
+    where
+        T: KeyAsTuple + Send,
+        <T as KeyAsTuple>::Key: Eq + std::hash::Hash,
+    {
+        let list = self.to_list_async().await?;
+        Ok(list.into_iter().map(|e| (e.key(), e)).collect())
     }
 }
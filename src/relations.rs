@@ -0,0 +1,377 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+
+use crate::db::DatabaseRef;
+use crate::mapping::{
+    ms_is_null, pg_is_null, Entity, FromRowNamed, FromRowWithPrefix, Persistable, RelationMeta,
+    TableMeta,
+};
+use crate::query::{Expr, PlaceholderStyle, Query, SqlParam};
+
+// Extra SELECT columns and JOIN clause contributed by an include that is
+// resolved in the same round trip as its parent query.
+pub struct IncludeJoin {
+    pub columns: Vec<String>,
+    pub clause: String,
+}
+
+// What includes take over from their parent query.
+#[derive(Clone, Debug)]
+pub struct IncludeScope {
+    pub style: PlaceholderStyle,
+    // set by WithDeleted/OnlyDeleted: related rows are loaded whether or
+    // not they were soft deleted
+    pub with_deleted: bool,
+    // the repository's query filters, applied to related tables that map
+    // the column; empty after IgnoreQueryFilters
+    pub query_filters: Vec<(&'static str, SqlParam)>,
+}
+
+// Loads a relation for a list of already materialized parents. Includes
+// either join into the parent query (`join` + `apply_*`) or run a batched
+// follow-up query once the parents are known (`load`).
+#[async_trait]
+pub trait IncludeLoader<T>: Send + Sync {
+    // Conditions of the join may bind values, numbered after `params`.
+    fn join(
+        &self,
+        _parent: &str,
+        _alias: &str,
+        _scope: &IncludeScope,
+        _params: &mut Vec<SqlParam>,
+    ) -> Option<IncludeJoin> {
+        None
+    }
+
+    fn apply_ms(&self, _row: &tiberius::Row, _alias: &str, _entity: &mut T) -> Result<()> {
+        Ok(())
+    }
+
+    fn apply_pg(&self, _row: &tokio_postgres::Row, _alias: &str, _entity: &mut T) -> Result<()> {
+        Ok(())
+    }
+
+    async fn load(
+        &self,
+        _db: &Arc<DatabaseRef>,
+        _scope: &IncludeScope,
+        _parents: &mut [T],
+    ) -> Result<()>
+    where
+        T: Send,
+    {
+        Ok(())
+    }
+}
+
+// One-to-many: `foreign_key` is a column of `U` pointing at the key of `T`.
+pub struct HasMany<T, U> {
+    pub meta: &'static RelationMeta,
+    set: fn(&mut T, Vec<U>),
+}
+
+impl<T, U> HasMany<T, U> {
+    pub fn new(meta: &'static RelationMeta, set: fn(&mut T, Vec<U>)) -> Self {
+        Self { meta, set }
+    }
+}
+
+// Many-to-many: rows of `through` link `local_key` (the key of `T`) to
+// `remote_key` (the key of `U`).
+pub struct ManyToMany<T, U> {
+    pub meta: &'static RelationMeta,
+    set: fn(&mut T, Vec<U>),
+}
+
+impl<T, U> ManyToMany<T, U> {
+    pub fn new(meta: &'static RelationMeta, set: fn(&mut T, Vec<U>)) -> Self {
+        Self { meta, set }
+    }
+}
+
+// Many-to-one: `foreign_key` is a column of `T` pointing at the key of `U`.
+//
+// A relation names a single column, so both ends need a single-column key;
+// an entity with a composite key makes loading the relation fail.
+pub struct BelongsTo<T, U> {
+    pub meta: &'static RelationMeta,
+    set: fn(&mut T, Option<U>),
+}
+
+impl<T, U> BelongsTo<T, U> {
+    pub fn new(meta: &'static RelationMeta, set: fn(&mut T, Option<U>)) -> Self {
+        Self { meta, set }
+    }
+}
+
+#[async_trait]
+impl<T, U> IncludeLoader<T> for HasMany<T, U>
+where
+    T: Entity + Persistable + Send + Sync,
+    U: Entity + FromRowNamed + Persistable + Send + Sync + 'static,
+{
+    async fn load(
+        &self,
+        db: &Arc<DatabaseRef>,
+        scope: &IncludeScope,
+        parents: &mut [T],
+    ) -> Result<()>
+    where
+        T: Send,
+    {
+        let pk = key_column::<T>(self.meta)?;
+        let keys = distinct_keys(parents, pk);
+        if keys.is_empty() {
+            return Ok(());
+        }
+
+        let child_table = U::table().name;
+        let fk = Expr::Col(format!("{}.{}", child_table, self.meta.foreign_key));
+        let children = scope
+            .query::<U>(child_table)
+            .with_db(db.clone())
+            .Where(fk.in_list(keys.into_iter().map(Expr::Param).collect()))
+            .to_list_async()
+            .await?;
+
+        let mut groups: HashMap<SqlParam, Vec<U>> = HashMap::new();
+        for c in children {
+            if let Some(k) = c.column_value(self.meta.foreign_key) {
+                groups.entry(k).or_default().push(c);
+            }
+        }
+        for p in parents.iter_mut() {
+            let items = p
+                .column_value(pk)
+                .and_then(|k| groups.remove(&k))
+                .unwrap_or_default();
+            (self.set)(p, items);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<T, U> IncludeLoader<T> for BelongsTo<T, U>
+where
+    T: Entity + Send + Sync,
+    U: Entity + FromRowWithPrefix + Send + Sync,
+{
+    // Without a single-column key on `U` nothing is joined; apply_* then
+    // report the key as an error. A soft deleted or filtered out target is
+    // left out in the ON clause, so the parent is still returned, without it.
+    fn join(
+        &self,
+        parent: &str,
+        alias: &str,
+        scope: &IncludeScope,
+        params: &mut Vec<SqlParam>,
+    ) -> Option<IncludeJoin> {
+        let table = U::table();
+        let pk = match table.keys {
+            [pk] => pk,
+            _ => return None,
+        };
+        let columns = table
+            .columns
+            .iter()
+            .map(|c| format!("{}.{} AS {}_{}", alias, c.name, alias, c.name))
+            .collect();
+        let mut clause = format!(
+            "LEFT JOIN {} {} ON {}.{} = {}.{}",
+            table.name, alias, parent, self.meta.foreign_key, alias, pk.column
+        );
+        for (column, value) in scope.filters_of(table) {
+            params.push(value);
+            clause.push_str(&format!(
+                " AND {}.{} = {}",
+                alias,
+                column,
+                scope.style.placeholder(params.len())
+            ));
+        }
+        if let (Some(meta), false) = (&table.soft_delete, scope.with_deleted) {
+            clause.push_str(" AND ");
+            clause.push_str(&meta.filter_sql(alias, scope.style, false));
+        }
+        Some(IncludeJoin { columns, clause })
+    }
+
+    fn apply_ms(&self, row: &tiberius::Row, alias: &str, entity: &mut T) -> Result<()> {
+        let related = if ms_is_null(row, &self.key_column(alias)?) {
+            None
+        } else {
+            Some(U::from_row_ms_with(row, alias)?)
+        };
+        (self.set)(entity, related);
+        Ok(())
+    }
+
+    fn apply_pg(&self, row: &tokio_postgres::Row, alias: &str, entity: &mut T) -> Result<()> {
+        let related = if pg_is_null(row, &self.key_column(alias)?) {
+            None
+        } else {
+            Some(U::from_row_pg_with(row, alias)?)
+        };
+        (self.set)(entity, related);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<T, U> IncludeLoader<T> for ManyToMany<T, U>
+where
+    T: Entity + Persistable + Send + Sync,
+    U: Entity + FromRowNamed + Send + Sync + 'static,
+{
+    async fn load(
+        &self,
+        db: &Arc<DatabaseRef>,
+        scope: &IncludeScope,
+        parents: &mut [T],
+    ) -> Result<()>
+    where
+        T: Send,
+    {
+        let pk = key_column::<T>(self.meta)?;
+        let keys = distinct_keys(parents, pk);
+        if keys.is_empty() {
+            return Ok(());
+        }
+
+        let link = LinkJoin::new::<U>(self.meta)?;
+        let owner = Expr::Col(format!("{}.{}", link.through, link.local_key));
+        let rows = scope
+            .query::<U>(U::table().name)
+            .with_db(db.clone())
+            .Where(owner.in_list(keys.into_iter().map(Expr::Param).collect()))
+            .with_include(Box::new(link))
+            .fetch_tagged_async(LINK_OWNER)
+            .await?;
+
+        let mut groups: HashMap<SqlParam, Vec<U>> = HashMap::new();
+        for (item, owner) in rows {
+            if let Some(k) = owner {
+                groups.entry(k).or_default().push(item);
+            }
+        }
+        for p in parents.iter_mut() {
+            let items = p
+                .column_value(pk)
+                .and_then(|k| groups.remove(&k))
+                .unwrap_or_default();
+            (self.set)(p, items);
+        }
+        Ok(())
+    }
+}
+
+const LINK_OWNER: &str = "rquery_owner";
+
+// Joins the link table into a query over the remote entity and exposes the
+// local key of each link row as LINK_OWNER.
+struct LinkJoin {
+    through: &'static str,
+    local_key: &'static str,
+    remote_key: &'static str,
+    target_key: &'static str,
+}
+
+impl LinkJoin {
+    fn new<U: Entity>(meta: &'static RelationMeta) -> Result<Self> {
+        let missing = |what: &str| anyhow!("relation {} is missing {}", meta.name, what);
+        Ok(Self {
+            through: meta.through.ok_or_else(|| missing("through"))?,
+            local_key: meta.local_key.ok_or_else(|| missing("local_key"))?,
+            remote_key: meta.remote_key.ok_or_else(|| missing("remote_key"))?,
+            target_key: key_column::<U>(meta)?,
+        })
+    }
+}
+
+impl<U> IncludeLoader<U> for LinkJoin {
+    fn join(
+        &self,
+        parent: &str,
+        _alias: &str,
+        _scope: &IncludeScope,
+        _params: &mut Vec<SqlParam>,
+    ) -> Option<IncludeJoin> {
+        Some(IncludeJoin {
+            columns: vec![format!(
+                "{}.{} AS {}",
+                self.through, self.local_key, LINK_OWNER
+            )],
+            clause: format!(
+                "INNER JOIN {} ON {}.{} = {}.{}",
+                self.through, self.through, self.remote_key, parent, self.target_key
+            ),
+        })
+    }
+}
+
+// Relations match on one column, so a composite key is an error rather
+// than a join on part of it.
+fn key_column<T: Entity>(meta: &RelationMeta) -> Result<&'static str> {
+    match T::table().keys {
+        [key] => Ok(key.column),
+        [] => Err(anyhow!(
+            "relation {} requires a key on {}",
+            meta.name,
+            T::table().name
+        )),
+        _ => Err(anyhow!(
+            "relation {} requires a single-column key on {}, which has a composite key",
+            meta.name,
+            T::table().name
+        )),
+    }
+}
+
+fn distinct_keys<T: Persistable>(parents: &[T], column: &str) -> Vec<SqlParam> {
+    let mut seen = HashSet::new();
+    let mut keys = Vec::new();
+    for p in parents {
+        match p.column_value(column) {
+            Some(SqlParam::Null) | None => {}
+            Some(k) => {
+                if seen.insert(k.clone()) {
+                    keys.push(k);
+                }
+            }
+        }
+    }
+    keys
+}
+
+impl IncludeScope {
+    // Query for the related rows of a follow-up include.
+    fn query<U: Entity + FromRowNamed>(&self, table: &str) -> Query<U> {
+        let query =
+            Query::<U>::new(table, self.style).with_query_filters(self.filters_of(U::table()));
+        if self.with_deleted {
+            query.WithDeleted()
+        } else {
+            query
+        }
+    }
+
+    // Query filters on columns that `table` maps.
+    fn filters_of(&self, table: &TableMeta) -> Vec<(&'static str, SqlParam)> {
+        self.query_filters
+            .iter()
+            .filter(|(column, _)| table.columns.iter().any(|c| c.name == *column))
+            .cloned()
+            .collect()
+    }
+}
+
+impl<T, U: Entity> BelongsTo<T, U> {
+    fn key_column(&self, alias: &str) -> Result<String> {
+        let pk = key_column::<U>(self.meta)?;
+        Ok(format!("{}_{}", alias, pk))
+    }
+}
@@ -1,7 +1,7 @@
 use async_trait::async_trait;
 
 use crate::mapping::{Entity, FromRowNamed, Persistable, Validatable};
-use crate::query::{Query, SqlParam};
+use crate::query::{Query, ToKey};
 
 #[allow(non_snake_case)]
 #[async_trait]
@@ -10,7 +10,7 @@ where
     T: Entity + FromRowNamed + Validatable + Persistable + Send + Sync,
 {
     fn Select(&self) -> Query<T>;
-    async fn get_by_key_async(&self, key: SqlParam) -> anyhow::Result<Option<T>>;
+    async fn get_by_key_async<K: ToKey + Send>(&self, key: K) -> anyhow::Result<Option<T>>;
 }
 
 #[async_trait]
@@ -20,8 +20,11 @@ where
 {
     async fn insert_async(&self, entity: &T) -> anyhow::Result<()>;
     async fn update_async(&self, entity: &T) -> anyhow::Result<()>;
+    // Like update_async, and stores the concurrency token written by the
+    // update in `entity`, so that the same instance can be updated again.
+    async fn update_and_refresh_async(&self, entity: &mut T) -> anyhow::Result<()>;
     async fn delete_by_entity_async(&self, entity: &T) -> anyhow::Result<()>;
-    async fn delete_by_key_async(&self, key: SqlParam) -> anyhow::Result<()>;
+    async fn delete_by_key_async<K: ToKey + Send>(&self, key: K) -> anyhow::Result<()>;
 }
 
 pub trait Repository<T>: QueryExecutor<T> + Crud<T>
@@ -0,0 +1,184 @@
+use std::time::Instant;
+
+use anyhow::Result;
+use tracing::field::Empty;
+
+use crate::db::{DatabaseRef, DbKind};
+use crate::interceptor::StatementKind;
+
+// Metric names recorded through the `metrics` facade. Counters and
+// histograms carry `db.system` and `db.operation` labels, plus
+// `db.sql.table` when known.
+pub const QUERIES_TOTAL: &str = "rquery_orm.queries";
+pub const QUERY_ERRORS_TOTAL: &str = "rquery_orm.query.errors";
+pub const QUERY_DURATION_SECONDS: &str = "rquery_orm.query.duration";
+pub const QUERY_ROWS: &str = "rquery_orm.query.rows";
+// Statements started and not yet finished, labelled with `db.system` only.
+// There is no connection pool, so this stands in for pool usage metrics.
+pub const STATEMENTS_IN_FLIGHT: &str = "rquery_orm.statements.in_flight";
+
+// Span and metrics of one round trip. Spans follow the OpenTelemetry
+// database conventions; `otel.*` fields are understood by
+// tracing-opentelemetry.
+pub(crate) struct Telemetry {
+    span: tracing::Span,
+    system: &'static str,
+    labels: Vec<(&'static str, String)>,
+    started: Instant,
+}
+
+impl Telemetry {
+    pub(crate) fn start(
+        db: &DatabaseRef,
+        kind: StatementKind,
+        table: Option<&str>,
+        sql: &str,
+    ) -> Self {
+        let system = match db.kind() {
+            DbKind::Mssql => "mssql",
+            DbKind::Postgres => "postgresql",
+        };
+        let operation = operation(kind, sql);
+        let name = match table {
+            Some(table) => format!("{} {}", operation, table),
+            None => operation.to_string(),
+        };
+        let span = tracing::info_span!(
+            target: "rquery_orm",
+            "db.query",
+            otel.name = %name,
+            otel.kind = "client",
+            otel.status_code = Empty,
+            otel.status_message = Empty,
+            db.system = system,
+            db.statement = %sanitize(sql),
+            db.operation = operation,
+            db.sql.table = Empty,
+            db.row_count = Empty,
+        );
+        let mut labels = vec![
+            ("db.system", system.to_string()),
+            ("db.operation", operation.to_string()),
+        ];
+        if let Some(table) = table {
+            span.record("db.sql.table", table);
+            labels.push(("db.sql.table", table.to_string()));
+        }
+        metrics::gauge!(STATEMENTS_IN_FLIGHT, "db.system" => system).increment(1.0);
+        Self {
+            span,
+            system,
+            labels,
+            started: Instant::now(),
+        }
+    }
+
+    pub(crate) fn span(&self) -> tracing::Span {
+        self.span.clone()
+    }
+
+    pub(crate) fn finish<R>(self, result: &Result<R>, rows: impl Fn(&R) -> u64) {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        metrics::counter!(QUERIES_TOTAL, &self.labels).increment(1);
+        metrics::histogram!(QUERY_DURATION_SECONDS, &self.labels).record(elapsed);
+        match result {
+            Ok(r) => {
+                let rows = rows(r);
+                self.span.record("db.row_count", rows);
+                metrics::histogram!(QUERY_ROWS, &self.labels).record(rows as f64);
+            }
+            Err(e) => {
+                self.span.record("otel.status_code", "ERROR");
+                self.span
+                    .record("otel.status_message", tracing::field::display(e));
+                metrics::counter!(QUERY_ERRORS_TOTAL, &self.labels).increment(1);
+            }
+        }
+    }
+}
+
+// Also runs when the future awaiting the statement is dropped before it
+// completes, so a cancelled statement leaves the in-flight gauge.
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        metrics::gauge!(STATEMENTS_IN_FLIGHT, "db.system" => self.system).decrement(1.0);
+    }
+}
+
+// `db.operation` of a statement: its leading keyword or, after a WITH
+// clause, the first keyword outside the CTE bodies.
+pub fn operation(kind: StatementKind, sql: &str) -> &'static str {
+    const VERBS: [&str; 5] = ["SELECT", "INSERT", "UPDATE", "DELETE", "MERGE"];
+    match kind {
+        StatementKind::Batch => return "BATCH",
+        StatementKind::Procedure => return "CALL",
+        _ => {}
+    }
+    let mut depth = 0usize;
+    let mut word = String::new();
+    let mut first = true;
+    for c in sql.chars().chain(std::iter::once(' ')) {
+        if c.is_ascii_alphabetic() {
+            word.push(c.to_ascii_uppercase());
+            continue;
+        }
+        if !word.is_empty() {
+            let verb = VERBS.iter().find(|v| **v == word).copied();
+            if first {
+                if word != "WITH" {
+                    return verb.unwrap_or("EXECUTE");
+                }
+                first = false;
+            } else if let (0, Some(verb)) = (depth, verb) {
+                return verb;
+            }
+            word.clear();
+        }
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    "EXECUTE"
+}
+
+// Replaces string and numeric literals with `?` so values written into the
+// SQL text (raw fragments, JSON paths, TOP counts) never reach telemetry.
+// Placeholders such as `@P1` and `$1` are kept.
+pub fn sanitize(sql: &str) -> String {
+    let mut out = String::with_capacity(sql.len());
+    let mut chars = sql.chars().peekable();
+    let mut prev: Option<char> = None;
+    while let Some(c) = chars.next() {
+        if c == '\'' {
+            // '' inside a literal is an escaped quote
+            loop {
+                match chars.next() {
+                    Some('\'') if chars.peek() == Some(&'\'') => {
+                        chars.next();
+                    }
+                    Some('\'') | None => break,
+                    Some(_) => {}
+                }
+            }
+            out.push('?');
+            prev = Some('\'');
+        } else if c.is_ascii_digit()
+            && !prev.is_some_and(|p| p.is_alphanumeric() || matches!(p, '_' | '$' | '@' | '#'))
+        {
+            while chars
+                .peek()
+                .is_some_and(|n| n.is_ascii_digit() || *n == '.')
+            {
+                chars.next();
+            }
+            out.push('?');
+            prev = Some('0');
+        } else {
+            out.push(c);
+            prev = Some(c);
+        }
+    }
+    out
+}
@@ -35,7 +35,7 @@ async fn it_mssql_select() -> anyhow::Result<()> {
             col!("Employees.CountryId").eq(col!("C.CountryId")),
         )
         .Where(condition!(Employee::country_id == "Mex"))
-        .OrderBy("Employees.HireDate DESC")
+        .OrderByDesc(Employee::cols().hire_date)
         .Top(10)
         .to_list_async()
         .await?;
@@ -131,3 +131,24 @@ async fn it_mssql_delete() -> anyhow::Result<()> {
     assert_eq!(none.len(), 0);
     Ok(())
 }
+
+#[tokio::test]
+#[ignore]
+async fn it_mssql_batch() -> anyhow::Result<()> {
+    let db = connect_mssql("localhost", 1433, "tempdb", "sa", "YourStrong!Passw0rd").await?;
+    let repo = GenericRepository::<Employee>::new(db.clone());
+    let c = Employee::cols();
+
+    let batch = db
+        .batch()
+        .add(repo.Select().Where(c.country_id.eq("Mex")))
+        .add(repo.Select().Where(c.country_id.eq("USA")).Top(1));
+    let statements = batch.to_sql()?;
+    assert!(statements[1].0.contains("@P2"));
+    assert_eq!(statements[1].1, vec![SqlParam::Text("USA".into())]);
+
+    let (mex, usa) = batch.execute_async().await?;
+    assert!(mex.iter().all(|e| e.country_id == "Mex"));
+    assert!(usa.len() <= 1);
+    Ok(())
+}
@@ -1,6 +1,7 @@
 use rquery_orm::{
-    col, condition, connect_postgres, DatabaseRef, Entity, GenericRepository, JoinType,
-    QueryExecutor,
+    col, condition, connect_postgres, ConcurrencyConflict, Crud, DatabaseRef, Entity, EntityHooks,
+    GenericRepository, Interceptor, JoinType, MappingError, Outcome, Procedure, QueryExecutor,
+    SqlType, Statement, StatementKind, StatementRewriter, ToParam,
 };
 
 #[derive(Entity, Debug)]
@@ -44,7 +45,7 @@ async fn it_pg_select_chain() -> anyhow::Result<()> {
             col!("Employees.CountryId").eq(col!("C.CountryId")),
         )
         .Where(condition!(Employee::country_id == "Mex"))
-        .OrderBy("Employees.HireDate DESC")
+        .OrderByDesc(Employee::cols().hire_date)
         .Top(1)
         .to_list_async()
         .await?;
@@ -54,3 +55,1149 @@ async fn it_pg_select_chain() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+#[ignore]
+async fn it_pg_raw_query_and_execute() -> anyhow::Result<()> {
+    let db = connect_postgres(
+        "localhost",
+        5432,
+        "tempdb",
+        "postgres",
+        "YourStrong!Passw0rd",
+    )
+    .await?;
+    if let DatabaseRef::Postgres(client) = &db {
+        client.batch_execute(include_str!("pg_setup.sql")).await?;
+    }
+
+    let list: Vec<Employee> = db
+        .raw_query(
+            "SELECT * FROM Employees WHERE CountryId = $1",
+            &["Mex".to_param()],
+        )
+        .await?;
+    assert!(list.iter().all(|e| e.country_id == "Mex"));
+
+    let changed = db
+        .raw_execute(
+            "UPDATE Employees SET FirstName = FirstName WHERE CountryId = $1",
+            &["Mex".to_param()],
+        )
+        .await?;
+    assert_eq!(changed, list.len() as u64);
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore]
+async fn it_pg_batch() -> anyhow::Result<()> {
+    let repo = repo().await?;
+    let db = connect_postgres(
+        "localhost",
+        5432,
+        "tempdb",
+        "postgres",
+        "YourStrong!Passw0rd",
+    )
+    .await?;
+    let c = Employee::cols();
+
+    let (mex, usa, all) = db
+        .batch()
+        .add(repo.Select().Where(c.country_id.eq("Mex")))
+        .add(repo.Select().Where(c.country_id.eq("USA")))
+        .add(repo.Select())
+        .execute_async()
+        .await?;
+    assert_eq!(mex.len() + usa.len(), all.len());
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore]
+async fn it_pg_in_list_uses_any() -> anyhow::Result<()> {
+    let repo = repo().await?;
+    let c = Employee::cols();
+
+    let all = repo.Select().to_list_async().await?;
+    let ids: Vec<i32> = all.iter().map(|e| e.employee_id).collect();
+    let found = repo
+        .Select()
+        .Where(c.employee_id.in_list(ids))
+        .to_list_async()
+        .await?;
+    assert_eq!(found.len(), all.len());
+
+    let none = repo
+        .Select()
+        .Where(c.employee_id.in_list((-5000..0).collect()))
+        .to_list_async()
+        .await?;
+    assert!(none.is_empty());
+    Ok(())
+}
+
+mod defaults {
+    use rquery_orm::Entity;
+
+    #[derive(Entity, Debug)]
+    #[table(name = "Employees")]
+    pub struct Hire {
+        #[key(name = "EmployeeId")]
+        pub employee_id: i32,
+        #[column(name = "CountryId", default_on_null)]
+        pub country_id: String,
+    }
+}
+
+#[tokio::test]
+#[ignore]
+async fn it_pg_mapping_errors_name_the_column() -> anyhow::Result<()> {
+    repo().await?;
+    let db = connect_postgres(
+        "localhost",
+        5432,
+        "tempdb",
+        "postgres",
+        "YourStrong!Passw0rd",
+    )
+    .await?;
+
+    let err = db
+        .raw_query::<Employee>(
+            "SELECT EmployeeId, NULL::varchar AS FirstName, CountryId, HireDate FROM Employees",
+            &[],
+        )
+        .await
+        .unwrap_err();
+    match err.downcast_ref::<MappingError>() {
+        Some(MappingError::UnexpectedNull {
+            field,
+            column,
+            sql_type,
+            ..
+        }) => {
+            assert_eq!(
+                (*field, column.as_str(), sql_type.as_str()),
+                ("first_name", "FirstName", "varchar")
+            );
+        }
+        other => panic!("expected UnexpectedNull, got {:?}", other),
+    }
+
+    let err = db
+        .raw_query::<Employee>(
+            "SELECT EmployeeId, FirstName, CountryId FROM Employees",
+            &[],
+        )
+        .await
+        .unwrap_err();
+    match err.downcast_ref::<MappingError>() {
+        Some(MappingError::MissingColumn { column, .. }) => assert_eq!(column, "HireDate"),
+        other => panic!("expected MissingColumn, got {:?}", other),
+    }
+
+    let hires = db
+        .raw_query::<defaults::Hire>(
+            "SELECT EmployeeId, NULL::varchar AS CountryId FROM Employees",
+            &[],
+        )
+        .await?;
+    assert_eq!(hires.len(), 3);
+    assert!(hires.iter().all(|h| h.country_id.is_empty()));
+    Ok(())
+}
+
+mod rel_countries {
+    use rquery_orm::Entity;
+
+    #[derive(Entity, Debug)]
+    #[table(name = "Countries")]
+    pub struct Country {
+        #[key(name = "CountryId")]
+        pub country_id: String,
+        #[column(name = "Name")]
+        pub name: String,
+        #[relation(foreign_key = "CountryId", table = "Employees")]
+        pub employees: Vec<super::rel_employees::Staff>,
+    }
+}
+
+mod rel_employees {
+    use rquery_orm::Entity;
+
+    #[derive(Entity, Debug)]
+    #[table(name = "Employees")]
+    pub struct Staff {
+        #[key(name = "EmployeeId")]
+        pub employee_id: i32,
+        #[column(name = "FirstName")]
+        pub first_name: String,
+        #[column(name = "CountryId")]
+        pub country_id: String,
+        #[relation(foreign_key = "CountryId", table = "Countries")]
+        pub country: Option<super::rel_countries::Country>,
+    }
+}
+
+#[tokio::test]
+#[ignore]
+async fn it_pg_includes_load_has_many_and_belongs_to() -> anyhow::Result<()> {
+    repo().await?;
+    let db = connect_postgres(
+        "localhost",
+        5432,
+        "tempdb",
+        "postgres",
+        "YourStrong!Passw0rd",
+    )
+    .await?;
+    let countries = GenericRepository::<rel_countries::Country>::new(db.clone());
+    let staff = GenericRepository::<rel_employees::Staff>::new(db);
+
+    let list = countries
+        .Select()
+        .Include(|r| r.employees)
+        .OrderBy(rel_countries::Country::cols().country_id)
+        .to_list_async()
+        .await?;
+    let names: Vec<(&str, Vec<&str>)> = list
+        .iter()
+        .map(|c| {
+            let mut names: Vec<&str> = c.employees.iter().map(|e| e.first_name.as_str()).collect();
+            names.sort();
+            (c.country_id.as_str(), names)
+        })
+        .collect();
+    assert_eq!(
+        names,
+        vec![("Mex", vec!["Ana", "Luis"]), ("USA", vec!["John"])]
+    );
+
+    let list = staff
+        .Select()
+        .Include(|r| r.country)
+        .OrderBy(rel_employees::Staff::cols().employee_id)
+        .to_list_async()
+        .await?;
+    let countries: Vec<Option<&str>> = list
+        .iter()
+        .map(|e| e.country.as_ref().map(|c| c.name.as_str()))
+        .collect();
+    assert_eq!(
+        countries,
+        vec![Some("Mexico"), Some("Mexico"), Some("United States")]
+    );
+    Ok(())
+}
+
+mod rel_learners {
+    use rquery_orm::Entity;
+
+    #[derive(Entity, Debug)]
+    #[table(name = "Employees")]
+    pub struct Learner {
+        #[key(name = "EmployeeId")]
+        pub employee_id: i32,
+        #[column(name = "FirstName")]
+        pub first_name: String,
+        #[relation(
+            table = "Skills",
+            through = "EmployeeSkills",
+            local_key = "EmployeeId",
+            remote_key = "SkillId"
+        )]
+        pub skills: Vec<super::rel_skills::Skill>,
+    }
+}
+
+mod rel_skills {
+    use rquery_orm::Entity;
+
+    #[derive(Entity, Debug)]
+    #[table(name = "Skills")]
+    pub struct Skill {
+        #[key(name = "SkillId")]
+        pub skill_id: i32,
+        #[column(name = "Name")]
+        pub name: String,
+    }
+}
+
+#[tokio::test]
+#[ignore]
+async fn it_pg_many_to_many_attach_detach_and_load() -> anyhow::Result<()> {
+    use rel_learners::Learner;
+    use rel_skills::Skill;
+
+    repo().await?;
+    let recorder = std::sync::Arc::new(Recorder::default());
+    let db = connect_postgres(
+        "localhost",
+        5432,
+        "tempdb",
+        "postgres",
+        "YourStrong!Passw0rd",
+    )
+    .await?
+    .with_interceptor(recorder.clone());
+    let learners = GenericRepository::<Learner>::new(db);
+
+    let skills = |list: &[Learner]| -> Vec<(String, Vec<String>)> {
+        list.iter()
+            .map(|l| {
+                let mut names: Vec<String> = l.skills.iter().map(|s| s.name.clone()).collect();
+                names.sort();
+                (l.first_name.clone(), names)
+            })
+            .collect()
+    };
+    let load = || {
+        learners
+            .Select()
+            .Include(|r| r.skills)
+            .OrderBy(Learner::cols().employee_id)
+            .to_list_async()
+    };
+
+    assert_eq!(
+        skills(&load().await?),
+        vec![
+            (
+                "Luis".to_string(),
+                vec!["Rust".to_string(), "SQL".to_string()]
+            ),
+            ("Ana".to_string(), vec!["SQL".to_string()]),
+            ("John".to_string(), vec![]),
+        ]
+    );
+
+    let john = Learner {
+        employee_id: 3,
+        first_name: "John".into(),
+        skills: Vec::new(),
+    };
+    let luis = Learner {
+        employee_id: 1,
+        first_name: "Luis".into(),
+        skills: Vec::new(),
+    };
+    let rust = Skill {
+        skill_id: 1,
+        name: "Rust".into(),
+    };
+    let sql = Skill {
+        skill_id: 2,
+        name: "SQL".into(),
+    };
+    recorder.seen.lock().unwrap().clear();
+    learners.attach(&john, &rust).await?;
+    learners.detach(&luis, &sql).await?;
+    {
+        let seen = recorder.seen.lock().unwrap();
+        let statements: Vec<(&str, Option<u64>)> =
+            seen.iter().map(|s| (s.1.as_str(), s.2)).collect();
+        assert_eq!(
+            statements,
+            vec![
+                (
+                    "INSERT INTO EmployeeSkills (EmployeeId, SkillId) VALUES ($1, $2)",
+                    Some(1)
+                ),
+                (
+                    "DELETE FROM EmployeeSkills WHERE EmployeeId = $1 AND SkillId = $2",
+                    Some(1)
+                ),
+            ]
+        );
+    }
+
+    assert_eq!(
+        skills(&load().await?),
+        vec![
+            ("Luis".to_string(), vec!["Rust".to_string()]),
+            ("Ana".to_string(), vec!["SQL".to_string()]),
+            ("John".to_string(), vec!["Rust".to_string()]),
+        ]
+    );
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore]
+async fn it_pg_unreadable_output_type_is_an_error() -> anyhow::Result<()> {
+    repo().await?;
+    let db = connect_postgres(
+        "localhost",
+        5432,
+        "tempdb",
+        "postgres",
+        "YourStrong!Passw0rd",
+    )
+    .await?;
+    db.raw_execute(
+        "CREATE OR REPLACE FUNCTION it_total(OUT total NUMERIC) AS 'SELECT 1.5' LANGUAGE sql",
+        &[],
+    )
+    .await?;
+
+    let call = Procedure::new("it_total").output("total", SqlType::Decimal(10, 2));
+    let err = match db.execute_procedure(&call).await {
+        Ok(_) => panic!("NUMERIC output should not be read as NULL"),
+        Err(e) => e,
+    };
+    assert!(err.to_string().contains("total"), "{}", err);
+    Ok(())
+}
+
+mod computed {
+    use rquery_orm::Entity;
+
+    #[derive(Entity, Debug)]
+    pub struct Seniority {
+        #[column(name = "Seniority")]
+        pub seniority: i64,
+        #[column(name = "PrevName")]
+        pub prev_name: Option<String>,
+    }
+}
+
+#[tokio::test]
+#[ignore]
+async fn it_pg_computed_columns_are_read_back() -> anyhow::Result<()> {
+    use rquery_orm::{Expr, Window};
+
+    let repo = repo().await?;
+    let c = Employee::cols();
+    let by_hire = || Window::new().order_by(c.hire_date);
+    let rows = repo
+        .Select()
+        .Compute(Expr::row_number().over(by_hire()), "Seniority")
+        .Compute(Expr::lag(c.first_name, 1).over(by_hire()), "PrevName")
+        .OrderBy(c.hire_date)
+        .to_list_with_computed_async::<computed::Seniority>()
+        .await?;
+    let seen: Vec<(&str, i64, Option<&str>)> = rows
+        .iter()
+        .map(|(e, x)| (e.first_name.as_str(), x.seniority, x.prev_name.as_deref()))
+        .collect();
+    assert_eq!(
+        seen,
+        vec![
+            ("John", 1, None),
+            ("Luis", 2, Some("John")),
+            ("Ana", 3, Some("Luis"))
+        ]
+    );
+    Ok(())
+}
+
+mod orders {
+    use rquery_orm::Entity;
+
+    #[derive(Entity, Debug)]
+    #[table(name = "Orders")]
+    pub struct Order {
+        #[key(name = "OrderId")]
+        pub order_id: i32,
+        #[column(name = "Total")]
+        pub total: i32,
+        #[column(name = "Version", concurrency_token)]
+        pub version: i32,
+    }
+}
+use orders::Order;
+
+#[tokio::test]
+#[ignore]
+async fn it_pg_concurrency_token_detects_stale_update() -> anyhow::Result<()> {
+    repo().await?;
+    let db = connect_postgres(
+        "localhost",
+        5432,
+        "tempdb",
+        "postgres",
+        "YourStrong!Passw0rd",
+    )
+    .await?;
+    let orders = GenericRepository::<Order>::new(db);
+
+    let mut first = orders.get_by_key_async(1).await?.expect("order 1");
+    let mut second = orders.get_by_key_async(1).await?.expect("order 1");
+    first.total = 150;
+    orders.update_async(&first).await?;
+
+    second.total = 175;
+    let err = orders.update_async(&second).await.unwrap_err();
+    assert!(err.downcast_ref::<ConcurrencyConflict>().is_some());
+
+    let fresh = orders.get_by_key_async(1).await?.expect("order 1");
+    assert_eq!((fresh.total, fresh.version), (150, 2));
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore]
+async fn it_pg_same_instance_can_be_updated_twice() -> anyhow::Result<()> {
+    repo().await?;
+    let db = connect_postgres(
+        "localhost",
+        5432,
+        "tempdb",
+        "postgres",
+        "YourStrong!Passw0rd",
+    )
+    .await?;
+    let orders = GenericRepository::<Order>::new(db);
+
+    let mut order = orders.get_by_key_async(1).await?.expect("order 1");
+    order.total = 150;
+    orders.update_and_refresh_async(&mut order).await?;
+    assert_eq!(order.version, 2);
+    order.total = 175;
+    orders.update_and_refresh_async(&mut order).await?;
+
+    let fresh = orders.get_by_key_async(1).await?.expect("order 1");
+    assert_eq!((fresh.total, fresh.version), (175, 3));
+    assert_eq!(order.version, 3);
+    Ok(())
+}
+
+mod customers {
+    use rquery_orm::Entity;
+
+    #[derive(Entity, Debug)]
+    #[table(name = "Customers", soft_delete = "IsDeleted")]
+    pub struct Customer {
+        #[key(name = "CustomerId")]
+        pub customer_id: i32,
+        #[column(name = "Name")]
+        pub name: String,
+    }
+}
+use customers::Customer;
+
+#[tokio::test]
+#[ignore]
+async fn it_pg_soft_delete_and_restore() -> anyhow::Result<()> {
+    repo().await?;
+    let db = connect_postgres(
+        "localhost",
+        5432,
+        "tempdb",
+        "postgres",
+        "YourStrong!Passw0rd",
+    )
+    .await?;
+    let customers = GenericRepository::<Customer>::new(db);
+
+    customers.delete_by_key_async(1).await?;
+    assert!(customers.get_by_key_async(1).await?.is_none());
+    assert_eq!(customers.Select().to_list_async().await?.len(), 1);
+    assert_eq!(
+        customers
+            .Select()
+            .OnlyDeleted()
+            .to_list_async()
+            .await?
+            .len(),
+        1
+    );
+    assert_eq!(
+        customers
+            .Select()
+            .WithDeleted()
+            .to_list_async()
+            .await?
+            .len(),
+        2
+    );
+
+    customers.restore_async(1).await?;
+    assert!(customers.get_by_key_async(1).await?.is_some());
+    Ok(())
+}
+
+mod invoices {
+    use rquery_orm::Entity;
+
+    #[derive(Entity, Debug)]
+    #[table(name = "Invoices")]
+    pub struct Invoice {
+        #[key(name = "InvoiceId")]
+        pub invoice_id: i32,
+        #[column(name = "Total")]
+        pub total: i32,
+        #[column(name = "DeletedAt", soft_delete)]
+        pub deleted_at: Option<chrono::NaiveDateTime>,
+    }
+}
+use invoices::Invoice;
+
+#[tokio::test]
+#[ignore]
+async fn it_pg_soft_delete_stamps_the_repository_clock() -> anyhow::Result<()> {
+    repo().await?;
+    let db = connect_postgres(
+        "localhost",
+        5432,
+        "tempdb",
+        "postgres",
+        "YourStrong!Passw0rd",
+    )
+    .await?;
+    let at = chrono::NaiveDate::from_ymd_opt(2020, 2, 29)
+        .unwrap()
+        .and_hms_opt(12, 0, 0)
+        .unwrap();
+    let invoices = GenericRepository::<Invoice>::new(db).with_clock(move || at);
+
+    invoices.delete_by_key_async(1).await?;
+    let deleted = invoices
+        .Select()
+        .OnlyDeleted()
+        .to_list_async()
+        .await?
+        .pop()
+        .expect("invoice 1");
+    assert_eq!(deleted.deleted_at, Some(at));
+
+    invoices.restore_async(1).await?;
+    let restored = invoices.get_by_key_async(1).await?.expect("invoice 1");
+    assert_eq!(restored.deleted_at, None);
+    Ok(())
+}
+
+mod projects {
+    use rquery_orm::Entity;
+
+    #[derive(Entity, Debug)]
+    #[table(name = "Projects")]
+    pub struct Project {
+        #[key(name = "ProjectId")]
+        pub project_id: i32,
+        #[column(name = "TenantId")]
+        pub tenant_id: i32,
+        #[column(name = "Name")]
+        pub name: String,
+    }
+}
+use projects::Project;
+
+#[tokio::test]
+#[ignore]
+async fn it_pg_query_filters_scope_rows_to_tenant() -> anyhow::Result<()> {
+    repo().await?;
+    let db = connect_postgres(
+        "localhost",
+        5432,
+        "tempdb",
+        "postgres",
+        "YourStrong!Passw0rd",
+    )
+    .await?;
+    let projects =
+        GenericRepository::<Project>::new(db).with_query_filter("TenantId", || 1.to_param());
+
+    assert_eq!(projects.Select().to_list_async().await?.len(), 1);
+    assert!(projects.get_by_key_async(2).await?.is_none());
+    assert_eq!(
+        projects
+            .Select()
+            .IgnoreQueryFilters()
+            .to_list_async()
+            .await?
+            .len(),
+        2
+    );
+
+    // the tenant column is stamped regardless of the entity's value
+    let p = Project {
+        project_id: 3,
+        tenant_id: 2,
+        name: "Gamma".into(),
+    };
+    projects.insert_async(&p).await?;
+    assert_eq!(projects.get_by_key_async(3).await?.unwrap().tenant_id, 1);
+
+    // rows of other tenants are left alone
+    projects.delete_by_key_async(2).await?;
+    assert_eq!(
+        projects
+            .Select()
+            .IgnoreQueryFilters()
+            .to_list_async()
+            .await?
+            .len(),
+        3
+    );
+
+    // a filter on a column the entity does not map is an error, not bad SQL
+    let employees = repo().await?.with_query_filter("TenantId", || 1.to_param());
+    let err = employees.Select().to_list_async().await.unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "query filter column TenantId is not mapped on Employees"
+    );
+    Ok(())
+}
+
+mod tenant_projects {
+    use rquery_orm::Entity;
+
+    #[derive(Entity, Debug)]
+    #[table(name = "Projects")]
+    pub struct TenantProject {
+        #[key(name = "ProjectId")]
+        pub project_id: i32,
+        #[column(name = "TenantId")]
+        pub tenant_id: i32,
+        #[column(name = "Name")]
+        pub name: String,
+        #[relation(foreign_key = "ProjectId", table = "Tasks")]
+        pub tasks: Vec<super::tasks::Task>,
+    }
+}
+
+mod tasks {
+    use rquery_orm::Entity;
+
+    // task 3 belongs to tenant 2 but points at a project of tenant 1
+    #[derive(Entity, Debug)]
+    #[table(name = "Tasks")]
+    pub struct Task {
+        #[key(name = "TaskId")]
+        pub task_id: i32,
+        #[column(name = "ProjectId")]
+        pub project_id: i32,
+        #[column(name = "TenantId")]
+        pub tenant_id: i32,
+        #[column(name = "Title")]
+        pub title: String,
+        #[relation(foreign_key = "ProjectId", table = "Projects")]
+        pub project: Option<super::tenant_projects::TenantProject>,
+    }
+}
+
+#[tokio::test]
+#[ignore]
+async fn it_pg_query_filters_scope_joins_and_includes() -> anyhow::Result<()> {
+    use tasks::Task;
+    use tenant_projects::TenantProject;
+
+    repo().await?;
+    let db = connect_postgres(
+        "localhost",
+        5432,
+        "tempdb",
+        "postgres",
+        "YourStrong!Passw0rd",
+    )
+    .await?;
+
+    // every slot that maps TenantId is scoped, joined ones in their ON clause
+    let joined = GenericRepository::<(TenantProject, Option<Task>)>::new(db.clone())
+        .with_query_filter("TenantId", || 1.to_param());
+    let rows = joined
+        .Select()
+        .Join(
+            JoinType::Left,
+            rquery_orm::on!(TenantProject::project_id == Task::project_id),
+        )
+        .to_list_async()
+        .await?;
+    let pairs: Vec<_> = rows
+        .iter()
+        .map(|(p, t)| (p.project_id, t.as_ref().map(|t| t.task_id)))
+        .collect();
+    assert_eq!(pairs, vec![(1, Some(1))]);
+    let all = joined
+        .Select()
+        .Join(
+            JoinType::Left,
+            rquery_orm::on!(TenantProject::project_id == Task::project_id),
+        )
+        .IgnoreQueryFilters()
+        .to_list_async()
+        .await?;
+    assert_eq!(all.len(), 3);
+
+    // follow-up includes only load the tenant's rows
+    let projects = GenericRepository::<TenantProject>::new(db.clone())
+        .with_query_filter("TenantId", || 1.to_param());
+    let loaded = projects
+        .Select()
+        .Include(|r| r.tasks)
+        .to_list_async()
+        .await?;
+    assert_eq!(loaded.len(), 1);
+    let ids: Vec<_> = loaded[0].tasks.iter().map(|t| t.task_id).collect();
+    assert_eq!(ids, vec![1]);
+
+    // a joined include of another tenant's row comes back empty
+    let tasks = GenericRepository::<Task>::new(db).with_query_filter("TenantId", || 2.to_param());
+    let mut loaded = tasks
+        .Select()
+        .Include(|r| r.project)
+        .to_list_async()
+        .await?;
+    loaded.sort_by_key(|t| t.task_id);
+    let projects: Vec<_> = loaded
+        .iter()
+        .map(|t| (t.task_id, t.project.as_ref().map(|p| p.project_id)))
+        .collect();
+    assert_eq!(projects, vec![(2, Some(2)), (3, None)]);
+    Ok(())
+}
+
+struct ProjectHooks {
+    loaded: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+}
+
+#[async_trait::async_trait]
+impl EntityHooks<Project> for ProjectHooks {
+    async fn before_insert(
+        &self,
+        p: &Project,
+        _db: &DatabaseRef,
+    ) -> anyhow::Result<Option<Project>> {
+        Ok(Some(Project {
+            project_id: p.project_id,
+            tenant_id: p.tenant_id,
+            name: p.name.trim().to_uppercase(),
+        }))
+    }
+
+    // the row is written by then, so this does not fail the insert
+    async fn after_insert(&self, _p: &Project, _db: &DatabaseRef) -> anyhow::Result<()> {
+        anyhow::bail!("audit log unavailable")
+    }
+
+    async fn before_delete(&self, p: &Project, _db: &DatabaseRef) -> anyhow::Result<()> {
+        if p.project_id == 1 {
+            anyhow::bail!("project 1 is archived");
+        }
+        Ok(())
+    }
+
+    async fn after_load(&self, _p: &mut Project, _db: &DatabaseRef) -> anyhow::Result<()> {
+        self.loaded
+            .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+#[tokio::test]
+#[ignore]
+async fn it_pg_hooks_normalize_and_abort() -> anyhow::Result<()> {
+    repo().await?;
+    let db = connect_postgres(
+        "localhost",
+        5432,
+        "tempdb",
+        "postgres",
+        "YourStrong!Passw0rd",
+    )
+    .await?;
+    let loaded = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let projects = GenericRepository::<Project>::new(db).with_hooks(ProjectHooks {
+        loaded: loaded.clone(),
+    });
+
+    let p = Project {
+        project_id: 3,
+        tenant_id: 1,
+        name: "  gamma ".into(),
+    };
+    projects.insert_async(&p).await?;
+    assert_eq!(projects.get_by_key_async(3).await?.unwrap().name, "GAMMA");
+
+    assert!(projects.delete_by_key_async(1).await.is_err());
+    assert!(projects.get_by_key_async(1).await?.is_some());
+
+    assert_eq!(projects.Select().to_list_async().await?.len(), 3);
+    // three single loads (one by the delete) plus the list
+    assert_eq!(loaded.load(std::sync::atomic::Ordering::SeqCst), 6);
+    Ok(())
+}
+
+// kind, sql, rows, failed
+type Seen = (StatementKind, String, Option<u64>, bool);
+
+#[derive(Default)]
+struct Recorder {
+    seen: std::sync::Mutex<Vec<Seen>>,
+}
+
+impl Interceptor for Recorder {
+    fn after(&self, statement: &Statement, outcome: &Outcome<'_>) {
+        self.seen.lock().unwrap().push((
+            statement.kind,
+            statement.sql.clone(),
+            outcome.rows,
+            outcome.error.is_some(),
+        ));
+    }
+}
+
+#[tokio::test]
+#[ignore]
+async fn it_pg_interceptors_see_every_statement() -> anyhow::Result<()> {
+    repo().await?;
+    let recorder = std::sync::Arc::new(Recorder::default());
+    let db = connect_postgres(
+        "localhost",
+        5432,
+        "tempdb",
+        "postgres",
+        "YourStrong!Passw0rd",
+    )
+    .await?
+    .with_interceptor(StatementRewriter::new(|s: &mut Statement| {
+        s.sql = format!("/* it */ {}", s.sql);
+    }))
+    .with_interceptor(recorder.clone());
+    let employees = GenericRepository::<Employee>::new(db.clone());
+
+    assert_eq!(employees.Select().to_list_async().await?.len(), 3);
+    db.raw_execute("UPDATE Employees SET FirstName = FirstName", &[])
+        .await?;
+    assert!(db.raw_execute("SELECT * FROM Missing", &[]).await.is_err());
+
+    let seen = recorder.seen.lock().unwrap();
+    assert_eq!(seen.len(), 3);
+    assert_eq!((seen[0].0, seen[0].2), (StatementKind::Query, Some(3)));
+    assert!(seen[0].1.starts_with("/* it */ SELECT"));
+    assert_eq!((seen[1].0, seen[1].2), (StatementKind::Execute, Some(3)));
+    assert!(seen[2].3);
+    Ok(())
+}
+
+mod profiles {
+    use rquery_orm::Entity;
+    use std::collections::BTreeMap;
+
+    #[derive(Entity, Debug)]
+    #[table(name = "Profiles")]
+    pub struct Profile {
+        #[key(name = "ProfileId")]
+        pub profile_id: i32,
+        #[column(name = "Settings", json)]
+        pub settings: BTreeMap<String, String>,
+        #[column(name = "Metadata", json, allow_null)]
+        pub metadata: Option<serde_json::Value>,
+    }
+}
+
+mod grid_profiles {
+    use rquery_orm::Entity;
+    use std::collections::BTreeMap;
+
+    // JSON object keys must be strings, so this cannot be serialized
+    #[derive(Entity, Debug)]
+    #[table(name = "Profiles")]
+    pub struct GridProfile {
+        #[key(name = "ProfileId")]
+        pub profile_id: i32,
+        #[column(name = "Settings", json)]
+        pub settings: BTreeMap<(i32, i32), String>,
+    }
+}
+
+#[tokio::test]
+#[ignore]
+async fn it_pg_json_columns_round_trip_and_filter() -> anyhow::Result<()> {
+    use grid_profiles::GridProfile;
+    use profiles::Profile;
+
+    repo().await?;
+    let db = connect_postgres(
+        "localhost",
+        5432,
+        "tempdb",
+        "postgres",
+        "YourStrong!Passw0rd",
+    )
+    .await?;
+    let profiles = GenericRepository::<Profile>::new(db.clone());
+    profiles
+        .insert_async(&Profile {
+            profile_id: 1,
+            settings: [("theme".to_string(), "dark".to_string())].into(),
+            metadata: Some(serde_json::json!({"plan": "pro", "tags": ["a", "b"]})),
+        })
+        .await?;
+    profiles
+        .insert_async(&Profile {
+            profile_id: 2,
+            settings: [("theme".to_string(), "light".to_string())].into(),
+            metadata: None,
+        })
+        .await?;
+
+    let back = profiles.get_by_key_async(1).await?.expect("profile 1");
+    assert_eq!(back.settings["theme"], "dark");
+    assert_eq!(back.metadata.as_ref().unwrap()["tags"][1], "b");
+    assert!(profiles
+        .get_by_key_async(2)
+        .await?
+        .unwrap()
+        .metadata
+        .is_none());
+
+    let ids = |rows: Vec<Profile>| rows.iter().map(|p| p.profile_id).collect::<Vec<_>>();
+    let c = Profile::cols();
+    let dark = profiles
+        .Select()
+        .Where(
+            c.settings
+                .json_text("theme")
+                .eq(rquery_orm::Expr::Param("dark".to_param())),
+        )
+        .to_list_async()
+        .await?;
+    assert_eq!(ids(dark), vec![1]);
+    let tagged = profiles
+        .Select()
+        .Where(c.metadata.json_has_key("tags"))
+        .to_list_async()
+        .await?;
+    assert_eq!(ids(tagged), vec![1]);
+    let pro = profiles
+        .Select()
+        .Where(c.metadata.json_contains(serde_json::json!({"tags": ["b"]})))
+        .to_list_async()
+        .await?;
+    assert_eq!(ids(pro), vec![1]);
+
+    // an unserializable document fails the insert instead of panicking
+    let grid = GenericRepository::<GridProfile>::new(db);
+    let err = grid
+        .insert_async(&GridProfile {
+            profile_id: 3,
+            settings: [((0, 0), "origin".to_string())].into(),
+        })
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("could not be serialized as JSON"));
+    assert!(profiles.get_by_key_async(3).await?.is_none());
+    Ok(())
+}
+
+// Fields of every `db.query` span, as recorded at creation and later.
+#[derive(Clone, Default)]
+struct SpanCapture {
+    spans: std::sync::Arc<std::sync::Mutex<Vec<std::collections::HashMap<String, String>>>>,
+}
+
+struct FieldVisitor<'a>(&'a mut std::collections::HashMap<String, String>);
+
+impl tracing::field::Visit for FieldVisitor<'_> {
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{:?}", value));
+    }
+
+    fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.to_string());
+    }
+}
+
+impl<S> tracing_subscriber::Layer<S> for SpanCapture
+where
+    S: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+{
+    fn on_new_span(
+        &self,
+        attrs: &tracing::span::Attributes<'_>,
+        id: &tracing::span::Id,
+        ctx: tracing_subscriber::layer::Context<'_, S>,
+    ) {
+        if attrs.metadata().name() != "db.query" {
+            return;
+        }
+        let mut fields = std::collections::HashMap::new();
+        attrs.record(&mut FieldVisitor(&mut fields));
+        let mut spans = self.spans.lock().unwrap();
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(spans.len());
+        }
+        spans.push(fields);
+    }
+
+    fn on_record(
+        &self,
+        id: &tracing::span::Id,
+        values: &tracing::span::Record<'_>,
+        ctx: tracing_subscriber::layer::Context<'_, S>,
+    ) {
+        let Some(index) = ctx
+            .span(id)
+            .and_then(|s| s.extensions().get::<usize>().copied())
+        else {
+            return;
+        };
+        values.record(&mut FieldVisitor(&mut self.spans.lock().unwrap()[index]));
+    }
+}
+
+#[tokio::test]
+#[ignore]
+async fn it_pg_telemetry_emits_span_fields_and_metrics() -> anyhow::Result<()> {
+    use futures::FutureExt;
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    let recorder = DebuggingRecorder::new();
+    let snapshotter = recorder.snapshotter();
+    recorder.install()?;
+    let capture = SpanCapture::default();
+    let _guard =
+        tracing::subscriber::set_default(tracing_subscriber::registry().with(capture.clone()));
+
+    let repo = repo().await?;
+    repo.Select()
+        .Where(condition!(Employee::employee_id == 1))
+        .to_list_async()
+        .await?;
+    // a statement whose future is dropped while it waits on the server
+    assert!(repo.Select().to_list_async().now_or_never().is_none());
+
+    let spans = capture.spans.lock().unwrap().clone();
+    let select = spans
+        .iter()
+        .find(|s| s.get("db.row_count").map(String::as_str) == Some("1"))
+        .expect("span of the finished select");
+    assert_eq!(select["db.system"], "postgresql");
+    assert_eq!(select["db.operation"], "SELECT");
+    assert_eq!(select["db.sql.table"], "Employees");
+    assert_eq!(select["otel.name"], "SELECT Employees");
+    assert!(select["db.statement"].contains("Employees.EmployeeId = $1"));
+
+    let metrics: std::collections::HashMap<String, DebugValue> = snapshotter
+        .snapshot()
+        .into_vec()
+        .into_iter()
+        .filter(|(key, ..)| {
+            key.key()
+                .labels()
+                .all(|l| l.key() != "db.sql.table" || l.value() == "Employees")
+        })
+        .map(|(key, _, _, value)| (key.key().name().to_string(), value))
+        .collect();
+    assert!(matches!(
+        metrics.get(rquery_orm::telemetry::QUERIES_TOTAL),
+        Some(DebugValue::Counter(n)) if *n >= 1
+    ));
+    assert!(matches!(
+        metrics.get(rquery_orm::telemetry::QUERY_DURATION_SECONDS),
+        Some(DebugValue::Histogram(v)) if !v.is_empty()
+    ));
+    // the dropped statement gave back its in-flight slot
+    assert!(matches!(
+        metrics.get(rquery_orm::telemetry::STATEMENTS_IN_FLIGHT),
+        Some(DebugValue::Gauge(v)) if v.into_inner() == 0.0
+    ));
+    Ok(())
+}
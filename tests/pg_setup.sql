@@ -1,3 +1,11 @@
+DROP TABLE IF EXISTS Invoices;
+DROP TABLE IF EXISTS Tasks;
+DROP TABLE IF EXISTS Profiles;
+DROP TABLE IF EXISTS Customers;
+DROP TABLE IF EXISTS Projects;
+DROP TABLE IF EXISTS Orders;
+DROP TABLE IF EXISTS EmployeeSkills;
+DROP TABLE IF EXISTS Skills;
 DROP TABLE IF EXISTS Employees;
 DROP TABLE IF EXISTS Countries;
 CREATE TABLE Countries (
@@ -15,3 +23,51 @@ INSERT INTO Employees (EmployeeId, FirstName, CountryId, HireDate) VALUES
     (1,'Luis','Mex','2023-01-01 00:00:00'),
     (2,'Ana','Mex','2024-01-01 00:00:00'),
     (3,'John','USA','2022-01-01 00:00:00');
+CREATE TABLE Skills (
+    SkillId INT PRIMARY KEY,
+    Name VARCHAR(50) NOT NULL
+);
+CREATE TABLE EmployeeSkills (
+    EmployeeId INT REFERENCES Employees(EmployeeId),
+    SkillId INT REFERENCES Skills(SkillId),
+    PRIMARY KEY (EmployeeId, SkillId)
+);
+INSERT INTO Skills (SkillId, Name) VALUES (1, 'Rust'), (2, 'SQL');
+INSERT INTO EmployeeSkills (EmployeeId, SkillId) VALUES (1, 1), (1, 2), (2, 2);
+CREATE TABLE Orders (
+    OrderId INT PRIMARY KEY,
+    Total INT NOT NULL,
+    Version INT NOT NULL
+);
+INSERT INTO Orders (OrderId, Total, Version) VALUES (1, 100, 1);
+CREATE TABLE Customers (
+    CustomerId INT PRIMARY KEY,
+    Name VARCHAR(50) NOT NULL,
+    IsDeleted BOOLEAN NOT NULL DEFAULT FALSE
+);
+INSERT INTO Customers (CustomerId, Name) VALUES (1, 'Acme'), (2, 'Globex');
+CREATE TABLE Projects (
+    ProjectId INT PRIMARY KEY,
+    TenantId INT NOT NULL,
+    Name VARCHAR(50) NOT NULL
+);
+INSERT INTO Projects (ProjectId, TenantId, Name) VALUES (1, 1, 'Alpha'), (2, 2, 'Beta');
+CREATE TABLE Profiles (
+    ProfileId INT PRIMARY KEY,
+    Settings JSONB NOT NULL,
+    Metadata JSONB
+);
+CREATE TABLE Tasks (
+    TaskId INT PRIMARY KEY,
+    ProjectId INT NOT NULL,
+    TenantId INT NOT NULL,
+    Title VARCHAR(50) NOT NULL
+);
+INSERT INTO Tasks (TaskId, ProjectId, TenantId, Title) VALUES
+    (1, 1, 1, 'Design'), (2, 2, 2, 'Build'), (3, 1, 2, 'Review');
+CREATE TABLE Invoices (
+    InvoiceId INT PRIMARY KEY,
+    Total INT NOT NULL,
+    DeletedAt TIMESTAMP
+);
+INSERT INTO Invoices (InvoiceId, Total) VALUES (1, 250);
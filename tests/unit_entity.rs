@@ -1,5 +1,7 @@
 use chrono::{NaiveDate, NaiveDateTime};
-use rquery_orm::{Entity, Persistable, PlaceholderStyle};
+use rquery_orm::{
+    Entity, KeyAsTuple, MappingError, Persistable, PlaceholderStyle, SqlParam, ToKey,
+};
 
 #[derive(Entity, Debug, Clone)]
 #[table(name = "Employees")]
@@ -37,7 +39,7 @@ fn insert_sql_builds() {
             .and_hms_opt(0, 0, 0)
             .unwrap(),
     };
-    let (sql, params, has_id) = emp.build_insert(PlaceholderStyle::Dollar);
+    let (sql, params, has_id) = emp.build_insert(PlaceholderStyle::Dollar).unwrap();
     assert_eq!(
         sql,
         "INSERT INTO Employees (first_name, last_name, age, hire_date) VALUES ($1, $2, $3, $4)"
@@ -45,3 +47,404 @@ fn insert_sql_builds() {
     assert_eq!(params.len(), 4);
     assert!(has_id);
 }
+
+#[test]
+fn default_on_null_is_recorded() {
+    mod nullable {
+        use rquery_orm::Entity;
+
+        #[derive(Entity, Debug)]
+        #[table(name = "Products")]
+        pub struct Product {
+            #[key(is_identity = true)]
+            pub product_id: i32,
+            #[column(default_on_null)]
+            pub stock: i32,
+            #[column]
+            pub name: String,
+        }
+    }
+    let t = nullable::Product::table();
+    assert!(t.columns[1].default_on_null);
+    assert!(!t.columns[2].default_on_null);
+}
+
+#[test]
+fn mapping_error_names_entity_field_and_column() {
+    let err = MappingError::UnexpectedNull {
+        entity: "Employees",
+        field: "age",
+        column: "Age".to_string(),
+        sql_type: "int4".to_string(),
+    };
+    assert_eq!(
+        err.to_string(),
+        "Employees.age: unexpected NULL in column Age (int4)"
+    );
+}
+
+mod link {
+    use rquery_orm::Entity;
+
+    #[derive(Entity, Debug, Clone)]
+    #[table(name = "EmployeeSkills")]
+    pub struct EmployeeSkill {
+        #[key(name = "EmployeeId")]
+        pub employee_id: i32,
+        #[key(name = "SkillId")]
+        pub skill_id: String,
+        #[column(name = "Level")]
+        pub level: i32,
+    }
+}
+
+#[test]
+fn composite_key_delete_uses_all_keys() {
+    let (sql, params) =
+        link::EmployeeSkill::build_delete_by_key((7, "rust").to_key(), PlaceholderStyle::AtP);
+    assert_eq!(
+        sql,
+        "DELETE FROM EmployeeSkills WHERE EmployeeId = @P1 AND SkillId = @P2"
+    );
+    assert_eq!(
+        params,
+        vec![SqlParam::I32(7), SqlParam::Text("rust".into())]
+    );
+}
+
+#[test]
+fn composite_key_as_tuple() {
+    let link = link::EmployeeSkill {
+        employee_id: 7,
+        skill_id: "rust".into(),
+        level: 3,
+    };
+    assert_eq!(link.key(), (7, "rust".to_string()));
+    let (sql, _) = link.build_update(PlaceholderStyle::Dollar).unwrap();
+    assert_eq!(
+        sql,
+        "UPDATE EmployeeSkills SET Level = $1 WHERE EmployeeId = $2 AND SkillId = $3"
+    );
+}
+
+mod versioned {
+    use rquery_orm::Entity;
+
+    #[derive(Entity, Debug)]
+    #[table(name = "Orders")]
+    pub struct Order {
+        #[key(name = "OrderId")]
+        pub order_id: i32,
+        #[column(name = "Total")]
+        pub total: i32,
+        #[column(name = "Version", concurrency_token)]
+        pub version: i32,
+    }
+}
+
+mod rowversion {
+    use rquery_orm::Entity;
+
+    #[derive(Entity, Debug)]
+    #[table(name = "Invoices")]
+    pub struct Invoice {
+        #[key(name = "InvoiceId")]
+        pub invoice_id: i32,
+        #[column(name = "Amount")]
+        pub amount: i32,
+        #[column(name = "RowVersion", concurrency_token)]
+        pub row_version: Vec<u8>,
+    }
+}
+
+#[test]
+fn integer_concurrency_token_is_checked_and_bumped() {
+    let order = versioned::Order {
+        order_id: 7,
+        total: 100,
+        version: 3,
+    };
+    let (sql, params) = order.build_update(PlaceholderStyle::AtP).unwrap();
+    assert_eq!(
+        sql,
+        "UPDATE Orders SET Total = @P1, Version = Version + 1 WHERE OrderId = @P2 AND Version = @P3"
+    );
+    assert_eq!(
+        params,
+        vec![SqlParam::I32(100), SqlParam::I32(7), SqlParam::I32(3)]
+    );
+
+    let (sql, params) = order.build_delete(PlaceholderStyle::Dollar);
+    assert_eq!(
+        sql,
+        "DELETE FROM Orders WHERE OrderId = $1 AND Version = $2"
+    );
+    assert_eq!(params, vec![SqlParam::I32(7), SqlParam::I32(3)]);
+    assert!(versioned::Order::table().columns[2].concurrency_token);
+}
+
+#[test]
+fn rowversion_token_is_only_compared() {
+    let invoice = rowversion::Invoice {
+        invoice_id: 1,
+        amount: 5,
+        row_version: vec![0, 0, 0, 0, 0, 0, 7, 209],
+    };
+    let (sql, _, _) = invoice.build_insert(PlaceholderStyle::AtP).unwrap();
+    assert_eq!(
+        sql,
+        "INSERT INTO Invoices (InvoiceId, Amount) VALUES (@P1, @P2)"
+    );
+    let (sql, params) = invoice.build_update(PlaceholderStyle::AtP).unwrap();
+    assert_eq!(
+        sql,
+        "UPDATE Invoices SET Amount = @P1 WHERE InvoiceId = @P2 AND RowVersion = @P3"
+    );
+    assert_eq!(params[2], SqlParam::Bytes(vec![0, 0, 0, 0, 0, 0, 7, 209]));
+}
+
+#[test]
+fn updates_can_return_the_new_token() {
+    let order = versioned::Order {
+        order_id: 7,
+        total: 100,
+        version: 3,
+    };
+    let filters = [("TenantId", SqlParam::I32(2))];
+    let (sql, params) = order
+        .build_update_returning(PlaceholderStyle::Dollar, None, &filters)
+        .unwrap();
+    assert_eq!(
+        sql,
+        "UPDATE Orders SET Total = $1, Version = Version + 1 WHERE OrderId = $2 AND Version = $3 AND TenantId = $4 RETURNING Version"
+    );
+    assert_eq!(params[3], SqlParam::I32(2));
+
+    let invoice = rowversion::Invoice {
+        invoice_id: 1,
+        amount: 5,
+        row_version: vec![0, 0, 0, 0, 0, 0, 7, 209],
+    };
+    let (sql, _) = invoice
+        .build_update_returning(PlaceholderStyle::AtP, None, &[])
+        .unwrap();
+    assert_eq!(
+        sql,
+        "DECLARE @token TABLE (RowVersion BINARY(8)); \
+         UPDATE Invoices SET Amount = @P1 OUTPUT inserted.RowVersion INTO @token WHERE InvoiceId = @P2 AND RowVersion = @P3; \
+         SELECT RowVersion FROM @token"
+    );
+    // without a token there is nothing to read back
+    let link = link::EmployeeSkill {
+        employee_id: 7,
+        skill_id: "rust".into(),
+        level: 3,
+    };
+    assert!(link
+        .build_update_returning(PlaceholderStyle::AtP, None, &[])
+        .is_err());
+}
+
+mod nullable_rowversion {
+    use rquery_orm::Entity;
+
+    #[derive(Entity, Debug)]
+    #[table(name = "Invoices")]
+    pub struct Invoice {
+        #[key(name = "InvoiceId")]
+        pub invoice_id: i32,
+        #[column(name = "Amount")]
+        pub amount: i32,
+        #[column(name = "RowVersion", concurrency_token, allow_null)]
+        pub row_version: Option<Vec<u8>>,
+    }
+}
+
+#[test]
+fn optional_rowversion_token_is_only_compared() {
+    let invoice = nullable_rowversion::Invoice {
+        invoice_id: 1,
+        amount: 5,
+        row_version: Some(vec![0, 0, 0, 0, 0, 0, 7, 209]),
+    };
+    let (sql, _, _) = invoice.build_insert(PlaceholderStyle::AtP).unwrap();
+    assert_eq!(
+        sql,
+        "INSERT INTO Invoices (InvoiceId, Amount) VALUES (@P1, @P2)"
+    );
+    let (sql, _) = invoice.build_update(PlaceholderStyle::AtP).unwrap();
+    assert_eq!(
+        sql,
+        "UPDATE Invoices SET Amount = @P1 WHERE InvoiceId = @P2 AND RowVersion = @P3"
+    );
+}
+
+#[test]
+fn concurrency_conflict_is_a_distinct_error() {
+    let err: anyhow::Error = rquery_orm::ConcurrencyConflict { table: "Orders" }.into();
+    let conflict = err.downcast_ref::<rquery_orm::ConcurrencyConflict>();
+    assert_eq!(conflict.map(|c| c.table), Some("Orders"));
+}
+
+mod audited {
+    use rquery_orm::Entity;
+
+    #[derive(Entity, Debug)]
+    #[table(name = "Documents")]
+    pub struct Document {
+        #[key(name = "DocumentId")]
+        pub document_id: i32,
+        #[column(name = "Title")]
+        pub title: String,
+        #[column(name = "CreatedAt", created_at)]
+        pub created_at: chrono::NaiveDateTime,
+        #[column(name = "CreatedBy", created_by)]
+        pub created_by: Option<String>,
+        #[column(name = "UpdatedAt", updated_at)]
+        pub updated_at: Option<chrono::NaiveDateTime>,
+        #[column(name = "UpdatedBy", updated_by)]
+        pub updated_by: Option<String>,
+    }
+}
+
+#[test]
+fn audit_columns_are_stamped() {
+    use rquery_orm::{AuditColumn, AuditValues};
+
+    let epoch = NaiveDate::from_ymd_opt(1970, 1, 1)
+        .unwrap()
+        .and_hms_opt(0, 0, 0)
+        .unwrap();
+    let now = NaiveDate::from_ymd_opt(2024, 5, 1)
+        .unwrap()
+        .and_hms_opt(12, 0, 0)
+        .unwrap();
+    let doc = audited::Document {
+        document_id: 1,
+        title: "Plan".into(),
+        created_at: epoch,
+        created_by: None,
+        updated_at: None,
+        updated_by: None,
+    };
+    let audit = AuditValues {
+        now,
+        user: Some(SqlParam::Text("ana".into())),
+        columns: Vec::new(),
+    };
+
+    let (_, params, _) = doc
+        .build_insert_audited(PlaceholderStyle::Dollar, Some(&audit))
+        .unwrap();
+    assert_eq!(
+        params[2..],
+        [
+            SqlParam::DateTime(now),
+            SqlParam::Text("ana".into()),
+            SqlParam::DateTime(now),
+            SqlParam::Text("ana".into())
+        ]
+    );
+
+    let (sql, params) = doc
+        .build_update_audited(PlaceholderStyle::Dollar, Some(&audit))
+        .unwrap();
+    assert_eq!(
+        sql,
+        "UPDATE Documents SET Title = $1, UpdatedAt = $2, UpdatedBy = $3 WHERE DocumentId = $4"
+    );
+    assert_eq!(params[1], SqlParam::DateTime(now));
+
+    // without a repository the entity's own values are written
+    let (_, params, _) = doc.build_insert(PlaceholderStyle::Dollar).unwrap();
+    assert_eq!(params[2], SqlParam::DateTime(epoch));
+    assert_eq!(params[3], SqlParam::Null);
+
+    let t = audited::Document::table();
+    assert_eq!(t.columns[2].audit, Some(AuditColumn::CreatedAt));
+    assert!(t.columns[2].ignore_in_update);
+}
+
+mod append_only {
+    // the generated build methods must not leave their AuditValues unused
+    #![deny(unused_variables)]
+    use rquery_orm::Entity;
+
+    #[derive(Entity, Debug)]
+    #[table(name = "Events")]
+    pub struct Event {
+        #[key(name = "EventId")]
+        pub event_id: i32,
+        #[column(name = "Payload", ignore_in_update)]
+        pub payload: String,
+    }
+}
+
+#[test]
+fn audit_values_only_reach_stamped_statements() {
+    use rquery_orm::AuditValues;
+
+    let event = append_only::Event {
+        event_id: 4,
+        payload: "created".into(),
+    };
+    let audit = AuditValues {
+        now: NaiveDate::from_ymd_opt(2024, 5, 1)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap(),
+        user: None,
+        columns: vec![
+            ("EventId", SqlParam::I32(9)),
+            ("Payload", SqlParam::Text("pinned".into())),
+        ],
+    };
+
+    let (_, params, _) = event
+        .build_insert_audited(PlaceholderStyle::AtP, Some(&audit))
+        .unwrap();
+    assert_eq!(
+        params,
+        vec![SqlParam::I32(9), SqlParam::Text("pinned".into())]
+    );
+    // the update sets nothing, so its key is never taken from AuditValues
+    let (_, params) = event
+        .build_update_audited(PlaceholderStyle::AtP, Some(&audit))
+        .unwrap();
+    assert_eq!(params, vec![SqlParam::I32(4)]);
+}
+
+#[test]
+fn pinned_columns_override_entity_values() {
+    use rquery_orm::AuditValues;
+
+    let e = Employees {
+        employee_id: 1,
+        first_name: "Ana".into(),
+        last_name: "Lopez".into(),
+        age: 30,
+        hire_date: NaiveDate::from_ymd_opt(2024, 1, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap(),
+    };
+    let audit = AuditValues {
+        now: e.hire_date,
+        user: None,
+        columns: vec![("age", SqlParam::I32(41))],
+    };
+
+    let (_, params, _) = e
+        .build_insert_audited(PlaceholderStyle::AtP, Some(&audit))
+        .unwrap();
+    assert_eq!(params[2], SqlParam::I32(41));
+    let (sql, params) = e
+        .build_update_audited(PlaceholderStyle::AtP, Some(&audit))
+        .unwrap();
+    assert_eq!(
+        sql,
+        "UPDATE Employees SET first_name = @P1, last_name = @P2, age = @P3, hire_date = @P4 WHERE employee_id = @P5"
+    );
+    assert_eq!(params[2], SqlParam::I32(41));
+    assert_eq!(params[4], SqlParam::I32(1));
+}
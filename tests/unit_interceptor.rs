@@ -0,0 +1,33 @@
+use rquery_orm::{
+    Interceptor, SqlParam, Statement, StatementKind, StatementRewriter, TracingInterceptor,
+};
+
+#[test]
+fn rewriter_changes_statement_before_it_runs() {
+    let rewriter = StatementRewriter::new(|s: &mut Statement| {
+        s.sql = format!("/* billing */ {}", s.sql);
+    });
+    let mut statement = Statement::new(
+        StatementKind::Query,
+        "SELECT * FROM Employees WHERE EmployeeId = $1",
+        &[SqlParam::I32(1)],
+    );
+    rewriter.before(&mut statement).unwrap();
+    assert_eq!(
+        statement.sql,
+        "/* billing */ SELECT * FROM Employees WHERE EmployeeId = $1"
+    );
+    assert_eq!(statement.params, vec![SqlParam::I32(1)]);
+}
+
+#[test]
+fn tracing_redacts_params_by_default() {
+    let params = [SqlParam::Text("secret".into()), SqlParam::I32(7)];
+    assert_eq!(TracingInterceptor::new().render_params(&params), "[?, ?]");
+    assert_eq!(
+        TracingInterceptor::new()
+            .with_params()
+            .render_params(&params),
+        "[Text(\"secret\"), I32(7)]"
+    );
+}
@@ -0,0 +1,48 @@
+use rquery_orm::{PlaceholderStyle, Procedure, SqlParam, SqlType};
+
+#[test]
+fn mssql_call_captures_return_value_and_outputs() {
+    let call = Procedure::new("dbo.GetOrders")
+        .arg(42)
+        .arg("open")
+        .output("Total", SqlType::Int)
+        .output("LastCode", SqlType::Text);
+    let (sql, params) = call.to_sql(PlaceholderStyle::AtP).unwrap();
+    assert_eq!(
+        sql,
+        "DECLARE @rquery_return INT, @rquery_Total INT, @rquery_LastCode NVARCHAR(MAX); \
+         EXEC @rquery_return = dbo.GetOrders @P1, @P2, @Total = @rquery_Total OUTPUT, @LastCode = @rquery_LastCode OUTPUT; \
+         SELECT @rquery_return AS rquery_return, @rquery_Total AS Total, @rquery_LastCode AS LastCode;"
+    );
+    assert_eq!(
+        params,
+        vec![SqlParam::I32(42), SqlParam::Text("open".into())]
+    );
+}
+
+#[test]
+fn pg_call_selects_from_function() {
+    let call = Procedure::new("public.orders_for").args(&[SqlParam::I32(1), SqlParam::Bool(true)]);
+    let (sql, params) = call.to_sql(PlaceholderStyle::Dollar).unwrap();
+    assert_eq!(sql, "SELECT * FROM public.orders_for($1, $2)");
+    assert_eq!(params.len(), 2);
+
+    let (sql, _) = Procedure::new("dbo.Ping")
+        .to_sql(PlaceholderStyle::AtP)
+        .unwrap();
+    assert!(sql.contains("EXEC @rquery_return = dbo.Ping;"));
+}
+
+#[test]
+fn procedure_names_are_validated() {
+    let err = Procedure::new("dbo.X; DROP TABLE Orders")
+        .to_sql(PlaceholderStyle::AtP)
+        .unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("invalid procedure or parameter name"));
+    assert!(Procedure::new("p")
+        .output("bad name", SqlType::Int)
+        .to_sql(PlaceholderStyle::AtP)
+        .is_err());
+}
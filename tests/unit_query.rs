@@ -1,6 +1,6 @@
 use rquery_orm::{
-    col, condition, val, Entity, FromRowNamed, FromRowWithPrefix, JoinType, Persistable,
-    PlaceholderStyle, Query, SqlParam, TableMeta, Validatable,
+    col, condition, val, Entity, Expr, FromRowNamed, FromRowWithPrefix, JoinType, Persistable,
+    PlaceholderStyle, Query, SqlParam, TableMeta, ToParam, Validatable,
 };
 
 struct Dummy;
@@ -13,6 +13,7 @@ impl Entity for Dummy {
             columns: &[],
             keys: &[],
             relations: &[],
+            soft_delete: None,
         }
     }
 }
@@ -42,16 +43,22 @@ impl Validatable for Dummy {
 }
 
 impl Persistable for Dummy {
-    fn build_insert(&self, _style: PlaceholderStyle) -> (String, Vec<SqlParam>, bool) {
+    fn build_insert(
+        &self,
+        _style: PlaceholderStyle,
+    ) -> anyhow::Result<(String, Vec<SqlParam>, bool)> {
         unimplemented!()
     }
-    fn build_update(&self, _style: PlaceholderStyle) -> (String, Vec<SqlParam>) {
+    fn build_update(&self, _style: PlaceholderStyle) -> anyhow::Result<(String, Vec<SqlParam>)> {
         unimplemented!()
     }
     fn build_delete(&self, _style: PlaceholderStyle) -> (String, Vec<SqlParam>) {
         unimplemented!()
     }
-    fn build_delete_by_key(_key: SqlParam, _style: PlaceholderStyle) -> (String, Vec<SqlParam>) {
+    fn build_delete_by_key(
+        _key: Vec<SqlParam>,
+        _style: PlaceholderStyle,
+    ) -> (String, Vec<SqlParam>) {
         unimplemented!()
     }
 }
@@ -76,9 +83,9 @@ fn join_and_where_build_for_pg() {
             col!("E.CountryId").eq(col!("C.CountryId")),
         )
         .Where(condition!("E.CountryId" == "Mex"))
-        .OrderBy("E.Id")
+        .OrderBy(col!("E.Id"))
         .Top(5);
-    let (sql, params) = q.to_sql();
+    let (sql, params) = q.to_sql().unwrap();
     assert!(sql.contains("LEFT JOIN Countries C ON (E.CountryId = C.CountryId)"));
     assert!(sql.contains("WHERE (E.CountryId = $1)"));
     assert!(sql.ends_with("ORDER BY E.Id LIMIT 5"));
@@ -94,9 +101,9 @@ fn full_query_chain_pg() {
             col!("Employees.CountryId").eq(col!("C.CountryId")),
         )
         .Where(condition!("Employees.CountryId" == "Mex"))
-        .OrderBy("Employees.HireDate DESC")
+        .OrderByDesc(col!("Employees.HireDate"))
         .Top(10);
-    let (sql, params) = q.to_sql();
+    let (sql, params) = q.to_sql().unwrap();
     assert_eq!(
         sql,
         "SELECT * FROM Employees LEFT JOIN Countries C ON (Employees.CountryId = C.CountryId) WHERE (Employees.CountryId = $1) ORDER BY Employees.HireDate DESC LIMIT 10",
@@ -110,7 +117,1172 @@ fn dual_query_builds_sql() {
     let q = rquery_orm::DualQuery::<Dummy, Dummy>::new(PlaceholderStyle::Dollar)
         .Join(JoinType::Left, col!("A").eq(col!("B")))
         .Top(10);
-    let (sql, _params) = q.to_sql();
+    let (sql, _params) = q.to_sql().unwrap();
     assert!(sql.contains("SELECT "));
     assert!(sql.contains(" LEFT JOIN "));
 }
+
+mod org {
+    pub mod employees {
+        use rquery_orm::Entity;
+
+        #[derive(Entity, Debug)]
+        #[table(name = "Employees")]
+        pub struct Employee {
+            #[key(name = "EmployeeId")]
+            pub employee_id: i32,
+            #[column(name = "DepartmentId")]
+            pub department_id: i32,
+        }
+    }
+
+    pub mod departments {
+        use rquery_orm::Entity;
+
+        #[derive(Entity, Debug)]
+        #[table(name = "Departments")]
+        pub struct Department {
+            #[key(name = "DepartmentId")]
+            pub department_id: i32,
+            #[column(name = "SiteId")]
+            pub site_id: i32,
+        }
+    }
+
+    pub mod sites {
+        use rquery_orm::Entity;
+
+        #[derive(Entity, Debug)]
+        #[table(name = "Sites")]
+        pub struct Site {
+            #[key(name = "SiteId")]
+            pub site_id: i32,
+        }
+    }
+}
+
+#[test]
+fn join_query_builds_three_way_sql() {
+    use org::departments::Department;
+    use org::employees::Employee;
+    use org::sites::Site;
+
+    let q =
+        rquery_orm::JoinQuery::<(Employee, Department, Option<Site>)>::new(PlaceholderStyle::AtP)
+            .Join(
+                JoinType::Inner,
+                rquery_orm::on!(Employee::department_id == Department::department_id),
+            )
+            .Join(
+                JoinType::Left,
+                rquery_orm::on!(Department::site_id == Site::site_id),
+            )
+            .Where(condition!(Employee::employee_id == 7))
+            .Top(3);
+    let (sql, params) = q.to_sql().unwrap();
+    assert_eq!(
+        sql,
+        "SELECT TOP(3) Employees.EmployeeId AS t_EmployeeId, Employees.DepartmentId AS t_DepartmentId, \
+         Departments.DepartmentId AS u_DepartmentId, Departments.SiteId AS u_SiteId, \
+         Sites.SiteId AS v_SiteId FROM Employees \
+         INNER JOIN Departments ON (Employees.DepartmentId = Departments.DepartmentId) \
+         LEFT JOIN Sites ON (Departments.SiteId = Sites.SiteId) WHERE (Employees.EmployeeId = @P1)"
+    );
+    assert_eq!(params, vec![SqlParam::I32(7)]);
+}
+
+#[test]
+fn join_query_requires_a_join_per_tuple_element() {
+    use org::departments::Department;
+    use org::employees::Employee;
+    use org::sites::Site;
+
+    let q =
+        rquery_orm::JoinQuery::<(Employee, Department, Option<Site>)>::new(PlaceholderStyle::AtP)
+            .Join(
+                JoinType::Inner,
+                rquery_orm::on!(Employee::department_id == Department::department_id),
+            );
+    let err = q.to_sql().unwrap_err();
+    assert!(err.to_string().contains("needs 2 Join calls, found 1"));
+}
+
+mod staff {
+    use rquery_orm::Entity;
+
+    #[derive(Entity, Debug)]
+    #[table(name = "Employees")]
+    pub struct Employee {
+        #[key(name = "EmployeeId")]
+        pub employee_id: i32,
+        #[column(name = "Name")]
+        pub name: String,
+        #[column(name = "ManagerId")]
+        pub manager_id: Option<i32>,
+    }
+}
+
+#[test]
+fn self_join_renders_against_aliases() {
+    use rquery_orm::alias;
+    use staff::Employee;
+
+    let e = alias::<Employee>("e");
+    let m = alias::<Employee>("m");
+    let q = Query::<Employee>::new(Employee::TABLE, PlaceholderStyle::Dollar)
+        .As(&e)
+        .JoinAs(
+            JoinType::Left,
+            &m,
+            rquery_orm::on!(e.manager_id == m.employee_id),
+        )
+        .Where(condition!(m.name == "Ana"));
+    let (sql, params) = q.to_sql().unwrap();
+    assert_eq!(
+        sql,
+        "SELECT * FROM Employees e LEFT JOIN Employees m ON (e.ManagerId = m.EmployeeId) WHERE (m.Name = $1)"
+    );
+    assert_eq!(params, vec![SqlParam::Text("Ana".into())]);
+}
+
+#[test]
+fn join_query_prefixes_aliased_slots() {
+    use rquery_orm::alias;
+    use staff::Employee;
+
+    let e = alias::<Employee>("e");
+    let m = alias::<Employee>("m");
+    let q = rquery_orm::JoinQuery::<(Employee, Option<Employee>)>::new(PlaceholderStyle::AtP)
+        .As(&e)
+        .JoinAs(
+            JoinType::Left,
+            &m,
+            rquery_orm::on!(e.manager_id == m.employee_id),
+        )
+        .Where(condition!(e.employee_id == 3));
+    let (sql, params) = q.to_sql().unwrap();
+    assert_eq!(
+        sql,
+        "SELECT e.EmployeeId AS t_EmployeeId, e.Name AS t_Name, e.ManagerId AS t_ManagerId, \
+         m.EmployeeId AS u_EmployeeId, m.Name AS u_Name, m.ManagerId AS u_ManagerId \
+         FROM Employees e LEFT JOIN Employees m ON (e.ManagerId = m.EmployeeId) \
+         WHERE (e.EmployeeId = @P1)"
+    );
+    assert_eq!(params, vec![SqlParam::I32(3)]);
+}
+
+#[test]
+fn join_query_rejects_alias_of_another_table() {
+    use org::departments::Department;
+    use rquery_orm::alias;
+    use staff::Employee;
+
+    let d = alias::<Department>("d");
+    let q = rquery_orm::JoinQuery::<(Employee, Option<Employee>)>::new(PlaceholderStyle::AtP)
+        .JoinAs(
+            JoinType::Left,
+            &d,
+            rquery_orm::on!(Employee::manager_id == d.department_id),
+        );
+    let err = q.to_sql().unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("alias d of Departments does not match join slot 1"));
+}
+
+#[test]
+fn typed_columns_build_comparisons() {
+    use staff::Employee;
+
+    let c = Employee::cols();
+    assert_eq!(c.name.reference().to_string(), "Employees.Name");
+    let q = Query::<Employee>::new(Employee::TABLE, PlaceholderStyle::AtP)
+        .Where(c.name.eq("Ana"))
+        .Where(c.manager_id.eq(4).or(c.manager_id.eq(None::<i32>)))
+        .Where(c.employee_id.in_list(vec![1, 2]))
+        .Where(c.employee_id.ne(c.employee_id))
+        .Where(
+            c.manager_id
+                .ne(None::<i32>)
+                .and(c.name.eq("Bo").or(c.name.eq("Cy"))),
+        );
+    let (sql, params) = q.to_sql().unwrap();
+    assert_eq!(
+        sql,
+        "SELECT * FROM Employees WHERE (Employees.Name = @P1) AND ((Employees.ManagerId = @P2) OR (Employees.ManagerId IS NULL)) \
+         AND Employees.EmployeeId IN (@P3, @P4) AND (Employees.EmployeeId <> Employees.EmployeeId) \
+         AND (Employees.ManagerId IS NOT NULL) AND ((Employees.Name = @P5) OR (Employees.Name = @P6))"
+    );
+    assert_eq!(
+        params,
+        vec![
+            SqlParam::Text("Ana".into()),
+            SqlParam::I32(4),
+            SqlParam::I32(1),
+            SqlParam::I32(2),
+            SqlParam::Text("Bo".into()),
+            SqlParam::Text("Cy".into())
+        ]
+    );
+}
+
+#[test]
+fn typed_columns_follow_aliases() {
+    use rquery_orm::alias;
+    use staff::Employee;
+
+    let m = alias::<Employee>("m");
+    let mut params = Vec::new();
+    assert_eq!(
+        m.name
+            .like("A%")
+            .to_sql_with(PlaceholderStyle::Dollar, &mut params),
+        "(m.Name LIKE $1)"
+    );
+    assert_eq!(params, vec![SqlParam::Text("A%".into())]);
+    assert_eq!(
+        Expr::from(m.manager_id).to_sql_with(PlaceholderStyle::Dollar, &mut Vec::new()),
+        "m.ManagerId"
+    );
+}
+
+#[test]
+fn order_by_chains_typed_columns() {
+    use staff::Employee;
+
+    let c = Employee::cols();
+    let q = Query::<Employee>::new(Employee::TABLE, PlaceholderStyle::Dollar)
+        .OrderBy(c.employee_id)
+        .OrderByDesc(c.name)
+        .ThenBy(c.manager_id)
+        .NullsLast()
+        .ThenByDesc(c.employee_id);
+    let (sql, _) = q.to_sql().unwrap();
+    assert_eq!(
+        sql,
+        "SELECT * FROM Employees ORDER BY Employees.Name DESC, Employees.ManagerId NULLS LAST, Employees.EmployeeId DESC"
+    );
+}
+
+#[test]
+fn nulls_ordering_is_emulated_on_mssql() {
+    use staff::Employee;
+
+    let q = Query::<Employee>::new(Employee::TABLE, PlaceholderStyle::AtP)
+        .OrderByDesc(Employee::cols().manager_id)
+        .NullsFirst();
+    let (sql, _) = q.to_sql().unwrap();
+    assert_eq!(
+        sql,
+        "SELECT * FROM Employees ORDER BY CASE WHEN Employees.ManagerId IS NULL THEN 0 ELSE 1 END, Employees.ManagerId DESC"
+    );
+}
+
+#[test]
+fn nulls_ordering_without_a_key_is_an_error() {
+    use rquery_orm::alias;
+    use staff::Employee;
+
+    let q = Query::<Employee>::new(Employee::TABLE, PlaceholderStyle::Dollar).NullsLast();
+    let err = q.to_sql().unwrap_err();
+    assert!(err.to_string().contains("must follow OrderBy or ThenBy"));
+    assert!(
+        Query::<Employee>::new(Employee::TABLE, PlaceholderStyle::Dollar)
+            .Union(Query::new(Employee::TABLE, PlaceholderStyle::Dollar).NullsFirst())
+            .to_sql()
+            .is_err()
+    );
+
+    let m = alias::<Employee>("m");
+    let q = rquery_orm::JoinQuery::<(Employee, Option<Employee>)>::new(PlaceholderStyle::Dollar)
+        .JoinAs(
+            JoinType::Left,
+            &m,
+            rquery_orm::on!(Employee::manager_id == m.employee_id),
+        )
+        .NullsFirst();
+    assert!(q.to_sql().is_err());
+}
+
+#[test]
+fn order_by_name_only_accepts_known_columns() {
+    use rquery_orm::alias;
+    use staff::Employee;
+
+    let q = Query::<Employee>::new("Employees E", PlaceholderStyle::Dollar)
+        .OrderByName("managerid", true)
+        .unwrap()
+        .ThenByName("Name", false)
+        .unwrap();
+    let (sql, _) = q.to_sql().unwrap();
+    assert_eq!(
+        sql,
+        "SELECT * FROM Employees E ORDER BY E.ManagerId DESC, E.Name"
+    );
+
+    let err = Query::<Employee>::new(Employee::TABLE, PlaceholderStyle::Dollar)
+        .OrderByName("Name; DROP TABLE Employees", false)
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("not a sortable column"));
+
+    let m = alias::<Employee>("m");
+    let q = rquery_orm::JoinQuery::<(Employee, Option<Employee>)>::new(PlaceholderStyle::Dollar)
+        .JoinAs(
+            JoinType::Left,
+            &m,
+            rquery_orm::on!(Employee::manager_id == m.employee_id),
+        )
+        .OrderByName("Name", false)
+        .unwrap();
+    assert!(q.to_sql().unwrap().0.ends_with("ORDER BY Employees.Name"));
+}
+
+#[test]
+fn distinct_precedes_top() {
+    use staff::Employee;
+
+    let q = Query::<Employee>::new(Employee::TABLE, PlaceholderStyle::AtP)
+        .Distinct()
+        .Top(5);
+    assert_eq!(
+        q.to_sql().unwrap().0,
+        "SELECT DISTINCT TOP(5) * FROM Employees"
+    );
+
+    let m = rquery_orm::alias::<Employee>("m");
+    let q = rquery_orm::JoinQuery::<(Employee, Option<Employee>)>::new(PlaceholderStyle::Dollar)
+        .JoinAs(
+            JoinType::Left,
+            &m,
+            rquery_orm::on!(Employee::manager_id == m.employee_id),
+        )
+        .Distinct();
+    assert!(q
+        .to_sql()
+        .unwrap()
+        .0
+        .starts_with("SELECT DISTINCT Employees.EmployeeId AS t_EmployeeId"));
+}
+
+#[test]
+fn set_operators_renumber_placeholders() {
+    use staff::Employee;
+
+    let c = Employee::cols();
+    let q = |style| {
+        Query::<Employee>::new(Employee::TABLE, style)
+            .Where(c.manager_id.eq(1))
+            .Union(Query::new(Employee::TABLE, style).Where(c.manager_id.eq(2)))
+            .Except(Query::new(Employee::TABLE, style).Where(c.name.eq("Ana")))
+    };
+    let (sql, params) = q(PlaceholderStyle::Dollar).to_sql().unwrap();
+    assert_eq!(
+        sql,
+        "SELECT * FROM ((SELECT * FROM Employees WHERE (Employees.ManagerId = $1) \
+         UNION SELECT * FROM Employees WHERE (Employees.ManagerId = $2)) \
+         EXCEPT SELECT * FROM Employees WHERE (Employees.Name = $3)) AS Employees"
+    );
+    assert_eq!(
+        params,
+        vec![
+            SqlParam::I32(1),
+            SqlParam::I32(2),
+            SqlParam::Text("Ana".into())
+        ]
+    );
+    let (sql, _) = q(PlaceholderStyle::AtP).to_sql().unwrap();
+    assert!(sql.contains("(Employees.ManagerId = @P2)) EXCEPT"));
+    assert!(sql.ends_with("(Employees.Name = @P3)) AS Employees"));
+}
+
+#[test]
+fn set_operators_order_and_limit_the_combined_rows() {
+    use staff::Employee;
+
+    let c = Employee::cols();
+    let top = Query::<Employee>::new(Employee::TABLE, PlaceholderStyle::AtP)
+        .Where(c.employee_id.gt(10))
+        .OrderBy(c.employee_id)
+        .Top(1);
+    let q = Query::<Employee>::new(Employee::TABLE, PlaceholderStyle::AtP)
+        .Where(c.employee_id.lt(3))
+        .UnionAll(top)
+        .Intersect(Query::new(Employee::TABLE, PlaceholderStyle::AtP))
+        .OrderByDesc(c.name)
+        .Top(2);
+    let (sql, params) = q.to_sql().unwrap();
+    assert_eq!(
+        sql,
+        "SELECT TOP(2) * FROM ((SELECT * FROM Employees WHERE (Employees.EmployeeId < @P1) \
+         UNION ALL SELECT * FROM (SELECT TOP(1) * FROM Employees WHERE (Employees.EmployeeId > @P2) ORDER BY Employees.EmployeeId) AS Employees) \
+         INTERSECT SELECT * FROM Employees) AS Employees ORDER BY Employees.Name DESC"
+    );
+    assert_eq!(params, vec![SqlParam::I32(3), SqlParam::I32(10)]);
+    assert!(q.to_sql().is_ok());
+
+    let unbounded = Query::<Employee>::new(Employee::TABLE, PlaceholderStyle::AtP)
+        .Union(Query::new(Employee::TABLE, PlaceholderStyle::AtP).OrderBy(c.name));
+    assert!(unbounded.to_sql().is_err());
+    let unbounded = Query::<Employee>::new(Employee::TABLE, PlaceholderStyle::Dollar)
+        .Union(Query::new(Employee::TABLE, PlaceholderStyle::Dollar).OrderBy(c.name));
+    assert!(unbounded.to_sql().is_ok());
+}
+
+#[test]
+fn cte_without_a_body_is_an_error() {
+    use rquery_orm::Cte;
+    use staff::Employee;
+
+    let empty = Cte::<Employee>::new("Empty");
+    let q = Query::<Employee>::new(Employee::TABLE, PlaceholderStyle::Dollar).With(empty);
+    let err = q.to_sql().unwrap_err();
+    assert!(err.to_string().contains("CTE Empty has no query"));
+}
+
+#[test]
+fn recursive_cte_walks_manager_chain() {
+    use rquery_orm::{alias, Cte};
+    use staff::Employee;
+
+    let build = |style| {
+        let chain = Cte::<Employee>::new("ManagerChain");
+        let c = chain.alias("c");
+        let e = alias::<Employee>("e");
+        let chain = chain.Recursive(
+            Query::new(Employee::TABLE, style).Where(Employee::cols().employee_id.eq(42)),
+            Query::new(Employee::TABLE, style).As(&e).JoinAs(
+                JoinType::Inner,
+                &c,
+                rquery_orm::on!(e.employee_id == c.manager_id),
+            ),
+        );
+        let out = chain.alias("o");
+        Query::<Employee>::new(Employee::TABLE, style)
+            .With(chain)
+            .As(&out)
+            .Where(out.name.ne("Root"))
+    };
+
+    let (sql, params) = build(PlaceholderStyle::Dollar).to_sql().unwrap();
+    assert_eq!(
+        sql,
+        "WITH RECURSIVE ManagerChain AS (\
+         SELECT Employees.EmployeeId, Employees.Name, Employees.ManagerId FROM Employees WHERE (Employees.EmployeeId = $1) \
+         UNION ALL SELECT e.EmployeeId, e.Name, e.ManagerId FROM Employees e INNER JOIN ManagerChain c ON (e.EmployeeId = c.ManagerId)) \
+         SELECT * FROM ManagerChain o WHERE (o.Name <> $2)"
+    );
+    assert_eq!(
+        params,
+        vec![SqlParam::I32(42), SqlParam::Text("Root".into())]
+    );
+
+    let (sql, _) = build(PlaceholderStyle::AtP).to_sql().unwrap();
+    assert!(sql.starts_with("WITH ManagerChain AS (SELECT"));
+    assert!(sql.ends_with("SELECT * FROM ManagerChain o WHERE (o.Name <> @P2)"));
+}
+
+#[test]
+fn plain_cte_can_be_joined() {
+    use rquery_orm::Cte;
+    use staff::Employee;
+
+    let bosses = Cte::<Employee>::new("Bosses");
+    let b = bosses.alias("b");
+    let bosses = bosses.Select(
+        Query::new(Employee::TABLE, PlaceholderStyle::Dollar)
+            .Where(Employee::cols().employee_id.lt(100)),
+    );
+    let q = Query::<Employee>::new(Employee::TABLE, PlaceholderStyle::Dollar)
+        .With(bosses)
+        .JoinAs(
+            JoinType::Inner,
+            &b,
+            rquery_orm::on!(Employee::manager_id == b.employee_id),
+        );
+    let (sql, params) = q.to_sql().unwrap();
+    assert_eq!(
+        sql,
+        "WITH Bosses AS (SELECT Employees.EmployeeId, Employees.Name, Employees.ManagerId FROM Employees WHERE (Employees.EmployeeId < $1)) \
+         SELECT * FROM Employees INNER JOIN Bosses b ON (Employees.ManagerId = b.EmployeeId)"
+    );
+    assert_eq!(params, vec![SqlParam::I32(100)]);
+}
+
+#[test]
+fn computed_expressions_render_per_dialect() {
+    use rquery_orm::SqlType;
+    use staff::Employee;
+
+    let c = Employee::cols();
+    let bonus = (Expr::from(c.employee_id) * val!(10) + val!(5)).cast(SqlType::Decimal(10, 2));
+    let label = Expr::case_when(c.manager_id.eq(None::<i32>), val!("boss"))
+        .when(c.manager_id.eq(1), val!("direct"))
+        .otherwise(Expr::coalesce(vec![c.name.into(), val!("-")]));
+    let q = |style| {
+        Query::<Employee>::new(Employee::TABLE, style)
+            .Compute(bonus.clone(), "Bonus")
+            .Compute(label.clone(), "Label")
+            .Where(Expr::func("LEN", vec![c.name.into()]).gt(val!(2)))
+    };
+    let (sql, params) = q(PlaceholderStyle::AtP).to_sql().unwrap();
+    assert_eq!(
+        sql,
+        "SELECT *, CAST(((Employees.EmployeeId * @P1) + @P2) AS DECIMAL(10, 2)) AS Bonus, \
+         CASE WHEN (Employees.ManagerId IS NULL) THEN @P3 WHEN (Employees.ManagerId = @P4) THEN @P5 \
+         ELSE COALESCE(Employees.Name, @P6) END AS Label FROM Employees WHERE (LEN(Employees.Name) > @P7)"
+    );
+    assert_eq!(params.len(), 7);
+    assert_eq!(params[6], SqlParam::I32(2));
+    let (sql, _) = q(PlaceholderStyle::Dollar).to_sql().unwrap();
+    assert!(sql.starts_with(
+        "SELECT *, CAST(((Employees.EmployeeId * $1) + $2) AS NUMERIC(10, 2)) AS Bonus"
+    ));
+}
+
+#[test]
+fn window_functions_pick_latest_row_per_group() {
+    use rquery_orm::{Cte, Window};
+    use staff::Employee;
+
+    let c = Employee::cols();
+    let ranked = Cte::<Employee>::new("Ranked");
+    let r = ranked.alias("r");
+    let ranked = ranked.Select(
+        Query::new(Employee::TABLE, PlaceholderStyle::Dollar).Compute(
+            Expr::row_number().over(
+                Window::new()
+                    .partition_by(c.manager_id)
+                    .order_by_desc(c.employee_id),
+            ),
+            "rn",
+        ),
+    );
+    let q = Query::<Employee>::new(Employee::TABLE, PlaceholderStyle::Dollar)
+        .With(ranked)
+        .As(&r)
+        .Where(col!("r.rn").eq(val!(1)))
+        .Compute(
+            Expr::lag(r.name, 1).over(Window::new().order_by(r.employee_id)),
+            "PrevName",
+        )
+        .OrderBy(Expr::rank().over(Window::new().order_by(r.name)));
+    let (sql, params) = q.to_sql().unwrap();
+    assert_eq!(
+        sql,
+        "WITH Ranked AS (SELECT Employees.EmployeeId, Employees.Name, Employees.ManagerId, \
+         ROW_NUMBER() OVER (PARTITION BY Employees.ManagerId ORDER BY Employees.EmployeeId DESC) AS rn FROM Employees) \
+         SELECT *, LAG(r.Name, 1) OVER (ORDER BY r.EmployeeId) AS PrevName FROM Ranked r WHERE (r.rn = $1) \
+         ORDER BY RANK() OVER (ORDER BY r.Name)"
+    );
+    assert_eq!(params, vec![SqlParam::I32(1)]);
+}
+
+#[test]
+fn raw_fragments_are_renumbered() {
+    use staff::Employee;
+
+    let q = |style| {
+        Query::<Employee>::new(Employee::TABLE, style)
+            .Where(Employee::cols().name.eq("Ana"))
+            .Where(Expr::raw(
+                "DATEDIFF(day, @P1, GETDATE()) > $2 AND $1 IS NOT NULL AND email LIKE '%@x'",
+                vec![SqlParam::I32(7), SqlParam::I32(30)],
+            ))
+    };
+    let (sql, params) = q(PlaceholderStyle::AtP).to_sql().unwrap();
+    assert_eq!(
+        sql,
+        "SELECT * FROM Employees WHERE (Employees.Name = @P1) AND (DATEDIFF(day, @P2, GETDATE()) > @P3 AND @P2 IS NOT NULL AND email LIKE '%@x')"
+    );
+    assert_eq!(
+        params,
+        vec![
+            SqlParam::Text("Ana".into()),
+            SqlParam::I32(7),
+            SqlParam::I32(30)
+        ]
+    );
+    let (sql, _) = q(PlaceholderStyle::Dollar).to_sql().unwrap();
+    assert!(sql
+        .ends_with("(DATEDIFF(day, $2, GETDATE()) > $3 AND $2 IS NOT NULL AND email LIKE '%@x')"));
+}
+
+#[test]
+fn quoted_text_in_raw_fragments_is_not_renumbered() {
+    use staff::Employee;
+
+    let q = |style, fragment: &str| {
+        Query::<Employee>::new(Employee::TABLE, style)
+            .Where(Employee::cols().name.eq("Ana"))
+            .Where(Expr::raw(fragment, vec![SqlParam::I32(5)]))
+    };
+    let (sql, _) = q(
+        PlaceholderStyle::Dollar,
+        "note = 'costs $1, it''s @P1' AND \"$1\" = $1",
+    )
+    .to_sql()
+    .unwrap();
+    assert!(sql.ends_with("(note = 'costs $1, it''s @P1' AND \"$1\" = $2)"));
+    let (sql, _) = q(PlaceholderStyle::AtP, "note <> '@P1' AND cost > @P1")
+        .to_sql()
+        .unwrap();
+    assert!(sql.ends_with("(note <> '@P1' AND cost > @P2)"));
+    let (sql, _) = q(
+        PlaceholderStyle::Dollar,
+        "body = $body$ costs $1 $body$ AND $$@P1$$ <> $1",
+    )
+    .to_sql()
+    .unwrap();
+    assert!(sql.ends_with("(body = $body$ costs $1 $body$ AND $$@P1$$ <> $2)"));
+}
+
+#[test]
+fn row_locks_render_per_dialect() {
+    use rquery_orm::TableHint;
+    use staff::Employee;
+
+    let q = |style| {
+        Query::<Employee>::new(Employee::TABLE, style)
+            .Where(Employee::cols().manager_id.eq(3))
+            .Top(10)
+            .ForUpdate()
+            .SkipLocked()
+    };
+    let (sql, _) = q(PlaceholderStyle::Dollar).to_sql().unwrap();
+    assert_eq!(
+        sql,
+        "SELECT * FROM Employees WHERE (Employees.ManagerId = $1) LIMIT 10 FOR UPDATE SKIP LOCKED"
+    );
+    let (sql, _) = q(PlaceholderStyle::AtP).to_sql().unwrap();
+    assert_eq!(
+        sql,
+        "SELECT TOP(10) * FROM Employees WITH (UPDLOCK, ROWLOCK, READPAST) WHERE (Employees.ManagerId = @P1)"
+    );
+
+    let (sql, _) = Query::<Employee>::new("Employees E", PlaceholderStyle::AtP)
+        .ForShare()
+        .NoWait()
+        .to_sql()
+        .unwrap();
+    assert_eq!(
+        sql,
+        "SELECT * FROM Employees E WITH (HOLDLOCK, ROWLOCK, NOWAIT)"
+    );
+
+    let q = |style| Query::<Employee>::new(Employee::TABLE, style).WithHint(TableHint::NoLock);
+    assert_eq!(
+        q(PlaceholderStyle::AtP).to_sql().unwrap().0,
+        "SELECT * FROM Employees WITH (NOLOCK)"
+    );
+    assert_eq!(
+        q(PlaceholderStyle::Dollar).to_sql().unwrap().0,
+        "SELECT * FROM Employees"
+    );
+}
+
+mod posts {
+    use rquery_orm::Entity;
+
+    #[derive(Entity, Debug)]
+    #[table(name = "Posts")]
+    pub struct Post {
+        #[key(name = "PostId")]
+        pub post_id: i32,
+        #[column(name = "Tags")]
+        pub tags: Vec<String>,
+        #[column(name = "Scores")]
+        pub scores: Option<Vec<i32>>,
+    }
+}
+
+#[test]
+fn in_list_binds_arrays_and_json() {
+    use staff::Employee;
+
+    let q = |style, ids: Vec<i32>| {
+        Query::<Employee>::new(Employee::TABLE, style)
+            .Where(Employee::cols().employee_id.in_list(ids))
+    };
+    let (sql, params) = q(PlaceholderStyle::Dollar, vec![1, 2, 3]).to_sql().unwrap();
+    assert_eq!(
+        sql,
+        "SELECT * FROM Employees WHERE Employees.EmployeeId = ANY($1)"
+    );
+    assert_eq!(
+        params,
+        vec![SqlParam::Array(vec![
+            SqlParam::I32(1),
+            SqlParam::I32(2),
+            SqlParam::I32(3)
+        ])]
+    );
+
+    let (sql, params) = q(PlaceholderStyle::AtP, (1..=3000).collect())
+        .to_sql()
+        .unwrap();
+    assert_eq!(
+        sql,
+        "SELECT * FROM Employees WHERE Employees.EmployeeId IN (SELECT value FROM OPENJSON(@P1) WITH (value INT '$'))"
+    );
+    match &params[..] {
+        [SqlParam::Text(json)] => assert!(json.starts_with("[1,2,3,") && json.ends_with(",3000]")),
+        other => panic!("unexpected params {:?}", other),
+    }
+
+    let (sql, params) = q(PlaceholderStyle::AtP, vec![1, 2]).to_sql().unwrap();
+    assert!(sql.ends_with("Employees.EmployeeId IN (@P1, @P2)"));
+    assert_eq!(params.len(), 2);
+}
+
+#[test]
+fn empty_in_list_matches_nothing() {
+    use staff::Employee;
+
+    for style in [PlaceholderStyle::AtP, PlaceholderStyle::Dollar] {
+        let (sql, params) = Query::<Employee>::new(Employee::TABLE, style)
+            .Where(Employee::cols().employee_id.in_list(Vec::<i32>::new()))
+            .to_sql()
+            .unwrap();
+        assert_eq!(sql, "SELECT * FROM Employees WHERE (1 = 0)");
+        assert!(params.is_empty());
+    }
+}
+
+#[test]
+fn mssql_in_lists_stay_under_the_parameter_limit() {
+    use staff::Employee;
+
+    // short lists are expanded
+    let (sql, params) = Query::<Employee>::new(Employee::TABLE, PlaceholderStyle::AtP)
+        .Where(Employee::cols().employee_id.in_list(vec![1, 2]))
+        .to_sql()
+        .unwrap();
+    assert_eq!(params.len(), 2);
+    assert!(sql.contains("EmployeeId IN (@P1, @P2)"));
+
+    // two lists that only pass 1000 params together both go to OPENJSON
+    let ids = |n: i32| (1..=n).collect::<Vec<i32>>();
+    let (sql, params) = Query::<Employee>::new(Employee::TABLE, PlaceholderStyle::AtP)
+        .Where(Employee::cols().employee_id.in_list(ids(600)))
+        .Where(Employee::cols().manager_id.in_list(ids(600)))
+        .to_sql()
+        .unwrap();
+    assert_eq!(params.len(), 2);
+    assert!(sql.contains("EmployeeId IN (SELECT value FROM OPENJSON(@P1) WITH (value INT '$'))"));
+    assert!(sql.ends_with("ManagerId IN (SELECT value FROM OPENJSON(@P2) WITH (value INT '$'))"));
+
+    // parameters bound after a list count as well
+    let (sql, params) = Query::<Employee>::new(Employee::TABLE, PlaceholderStyle::AtP)
+        .Where(Employee::cols().employee_id.in_list(ids(1000)))
+        .Where(Employee::cols().name.eq("Ana"))
+        .to_sql()
+        .unwrap();
+    assert_eq!(params.len(), 2);
+    assert!(sql.ends_with("(Employees.Name = @P2)"));
+
+    // mixed types are read as text
+    let mut mixed: Vec<SqlParam> = (1..=1000).map(SqlParam::I32).collect();
+    mixed.push(SqlParam::Text("1001".into()));
+    let (sql, params) = Query::<Employee>::new(Employee::TABLE, PlaceholderStyle::AtP)
+        .Where(Expr::InList {
+            left: Box::new(Expr::Col("Employees.EmployeeId".into())),
+            list: mixed,
+        })
+        .to_sql()
+        .unwrap();
+    assert_eq!(params.len(), 1);
+    assert!(sql.ends_with("WITH (value NVARCHAR(4000) '$'))"));
+
+    // binary items are sent as hex strings
+    let bytes: Vec<SqlParam> = (0..1001u32)
+        .map(|i| SqlParam::Bytes(i.to_be_bytes().to_vec()))
+        .collect();
+    let (sql, params) = Query::<Employee>::new(Employee::TABLE, PlaceholderStyle::AtP)
+        .Where(Expr::InList {
+            left: Box::new(Expr::Col("Employees.EmployeeId".into())),
+            list: bytes,
+        })
+        .to_sql()
+        .unwrap();
+    assert!(sql.ends_with(
+        "IN (SELECT CONVERT(VARBINARY(MAX), value, 1) FROM OPENJSON(@P1) WITH (value VARCHAR(MAX) '$'))"
+    ));
+    match &params[..] {
+        [SqlParam::Text(json)] => assert!(json.starts_with(r#"["0x00000000","0x00000001","#)),
+        other => panic!("unexpected params {:?}", other),
+    }
+}
+
+#[test]
+fn array_columns_map_to_array_params() {
+    use posts::Post;
+
+    let post = Post {
+        post_id: 1,
+        tags: vec!["rust".into(), "sql".into()],
+        scores: None,
+    };
+    assert_eq!(
+        post.column_value("Tags"),
+        Some(SqlParam::Array(vec![
+            SqlParam::Text("rust".into()),
+            SqlParam::Text("sql".into())
+        ]))
+    );
+    assert_eq!(post.column_value("Scores"), Some(SqlParam::Null));
+    let (_, params, _) = post.build_insert(PlaceholderStyle::Dollar).unwrap();
+    assert!(params.contains(&SqlParam::Array(vec![
+        SqlParam::Text("rust".into()),
+        SqlParam::Text("sql".into())
+    ])));
+}
+
+mod profiles {
+    use rquery_orm::Entity;
+    use std::collections::BTreeMap;
+
+    #[derive(Entity, Debug)]
+    #[table(name = "Profiles")]
+    pub struct Profile {
+        #[key(name = "ProfileId")]
+        pub profile_id: i32,
+        #[column(name = "Settings", json)]
+        pub settings: BTreeMap<String, String>,
+        #[column(name = "Metadata", json)]
+        pub metadata: Option<serde_json::Value>,
+    }
+}
+
+#[test]
+fn json_columns_bind_serialized_documents() {
+    use profiles::Profile;
+
+    let profile = Profile {
+        profile_id: 1,
+        settings: [("theme".to_string(), "dark".to_string())].into(),
+        metadata: None,
+    };
+    assert_eq!(
+        profile.column_value("Settings"),
+        Some(SqlParam::Json(r#"{"theme":"dark"}"#.into()))
+    );
+    assert_eq!(profile.column_value("Metadata"), Some(SqlParam::Null));
+    let (sql, params) = profile.build_update(PlaceholderStyle::Dollar).unwrap();
+    assert!(sql.starts_with("UPDATE Profiles SET Settings = $1, Metadata = $2"));
+    assert_eq!(params[0], SqlParam::Json(r#"{"theme":"dark"}"#.into()));
+}
+
+mod grid_profiles {
+    use rquery_orm::Entity;
+    use std::collections::BTreeMap;
+
+    #[derive(Entity, Debug)]
+    #[table(name = "Profiles")]
+    pub struct GridProfile {
+        #[key(name = "ProfileId")]
+        pub profile_id: i32,
+        #[column(name = "Settings", json)]
+        pub settings: BTreeMap<(i32, i32), String>,
+    }
+}
+
+#[test]
+fn unserializable_json_columns_fail_the_build() {
+    let grid = grid_profiles::GridProfile {
+        profile_id: 1,
+        settings: [((0, 0), "origin".to_string())].into(),
+    };
+    let err = grid.build_insert(PlaceholderStyle::Dollar).unwrap_err();
+    assert!(err
+        .to_string()
+        .starts_with("Settings: value could not be serialized as JSON"));
+    assert!(grid.build_update(PlaceholderStyle::AtP).is_err());
+    assert_eq!(grid.column_value("Settings"), None);
+}
+
+#[test]
+fn json_predicates_render_per_dialect() {
+    use profiles::Profile;
+
+    let q = |style| {
+        let c = Profile::cols();
+        Query::<Profile>::new(Profile::TABLE, style)
+            .Where(
+                c.settings
+                    .json_text("theme")
+                    .eq(Expr::Param("dark".to_param())),
+            )
+            .Where(
+                c.metadata
+                    .json_text("owner.name")
+                    .eq(Expr::Param("Ana".to_param())),
+            )
+            .Where(c.metadata.json_has_key("tags"))
+            .Where(
+                c.metadata
+                    .json_contains(serde_json::json!({"plan": "pro", "tags": ["a"]})),
+            )
+    };
+    let (sql, params) = q(PlaceholderStyle::Dollar).to_sql().unwrap();
+    assert_eq!(
+        sql,
+        "SELECT * FROM Profiles WHERE ((Profiles.Settings ->> 'theme') = $1) \
+         AND ((Profiles.Metadata #>> '{owner,name}') = $2) AND (Profiles.Metadata ? $3) \
+         AND (Profiles.Metadata @> $4)"
+    );
+    assert_eq!(params[2], SqlParam::Text("tags".into()));
+    assert_eq!(
+        params[3],
+        SqlParam::Json(r#"{"plan":"pro","tags":["a"]}"#.into())
+    );
+
+    let (sql, params) = q(PlaceholderStyle::AtP).to_sql().unwrap();
+    assert_eq!(
+        sql,
+        "SELECT * FROM Profiles WHERE (JSON_VALUE(Profiles.Settings, '$.theme') = @P1) \
+         AND (JSON_VALUE(Profiles.Metadata, '$.owner.name') = @P2) \
+         AND (JSON_VALUE(Profiles.Metadata, '$.tags') IS NOT NULL OR JSON_QUERY(Profiles.Metadata, '$.tags') IS NOT NULL) \
+         AND (JSON_VALUE(Profiles.Metadata, '$.plan') = @P3 \
+         AND EXISTS (SELECT 1 FROM OPENJSON(Profiles.Metadata, '$.tags') WHERE value = @P4))"
+    );
+    assert_eq!(
+        params[2..],
+        [SqlParam::Text("pro".into()), SqlParam::Text("a".into())]
+    );
+}
+
+mod articles {
+    use rquery_orm::Entity;
+
+    #[derive(Entity, Debug)]
+    #[table(name = "Articles")]
+    pub struct Article {
+        #[key(name = "ArticleId")]
+        pub article_id: i32,
+        #[column(name = "Title", full_text = "english")]
+        pub title: String,
+        #[column(name = "Body", full_text)]
+        pub body: String,
+        #[column(name = "Author")]
+        pub author: String,
+    }
+}
+
+#[test]
+fn full_text_search_renders_per_dialect() {
+    use articles::Article;
+
+    let indexed: Vec<_> = Article::table()
+        .columns
+        .iter()
+        .filter(|c| c.full_text)
+        .map(|c| (c.name, c.full_text_config))
+        .collect();
+    assert_eq!(indexed, vec![("Title", Some("english")), ("Body", None)]);
+
+    let q = |style| {
+        Query::<Article>::new("Articles a", style)
+            .Search("rust orm")
+            .OrderBySearchRank("rust orm")
+    };
+    let (sql, params) = q(PlaceholderStyle::Dollar).to_sql().unwrap();
+    assert_eq!(
+        sql,
+        "SELECT * FROM Articles a WHERE (to_tsvector('english', concat_ws(' ', a.Title, a.Body)) \
+         @@ plainto_tsquery('english', $1)) \
+         ORDER BY ts_rank(to_tsvector('english', concat_ws(' ', a.Title, a.Body)), plainto_tsquery('english', $2)) DESC"
+    );
+    assert_eq!(params.len(), 2);
+
+    let (sql, _) = q(PlaceholderStyle::AtP).to_sql().unwrap();
+    assert_eq!(
+        sql,
+        "SELECT * FROM Articles a WHERE FREETEXT((a.Title, a.Body), @P1) \
+         ORDER BY (SELECT ft.[RANK] FROM FREETEXTTABLE(Articles, (Title, Body), @P2) AS ft WHERE ft.[KEY] = a.ArticleId) DESC"
+    );
+
+    let c = Article::cols();
+    let (sql, _) = Query::<Article>::new(Article::TABLE, PlaceholderStyle::AtP)
+        .Where(Expr::full_text_contains(vec![c.author], "\"smith*\"", None))
+        .to_sql()
+        .unwrap();
+    assert_eq!(
+        sql,
+        "SELECT * FROM Articles WHERE CONTAINS(Articles.Author, @P1)"
+    );
+    let (sql, _) = Query::<Article>::new(Article::TABLE, PlaceholderStyle::Dollar)
+        .Where(Expr::full_text_contains(vec![c.author], "smith:*", None))
+        .to_sql()
+        .unwrap();
+    assert_eq!(
+        sql,
+        "SELECT * FROM Articles WHERE (to_tsvector(Articles.Author) @@ to_tsquery($1))"
+    );
+    let (sql, _) = Query::<Article>::new(Article::TABLE, PlaceholderStyle::Dollar)
+        .Where(Expr::full_text(vec![c.author], "smith", Some("simple")))
+        .to_sql()
+        .unwrap();
+    assert_eq!(
+        sql,
+        "SELECT * FROM Articles WHERE (to_tsvector('simple', Articles.Author) @@ plainto_tsquery('simple', $1))"
+    );
+}
+
+mod notes {
+    use rquery_orm::Entity;
+
+    #[derive(Entity, Debug)]
+    #[table(name = "Notes")]
+    pub struct Note {
+        #[column(name = "Text", full_text)]
+        pub text: String,
+    }
+}
+
+#[test]
+fn full_text_search_without_columns_or_key_is_an_error() {
+    use articles::Article;
+    use staff::Employee;
+
+    let err = Query::<Employee>::new(Employee::TABLE, PlaceholderStyle::Dollar)
+        .Search("rust")
+        .to_sql()
+        .unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("declares no #[column(full_text)] columns"));
+    let err = Query::<notes::Note>::new(notes::Note::TABLE, PlaceholderStyle::AtP)
+        .Search("rust")
+        .OrderBySearchRank("rust")
+        .to_sql()
+        .unwrap_err();
+    assert!(err.to_string().contains("Notes needs a key"));
+    assert!(Query::<Article>::new(Article::TABLE, PlaceholderStyle::AtP)
+        .Search("rust")
+        .OrderBySearchRank("rust")
+        .to_sql()
+        .is_ok());
+}
+
+mod customers {
+    use rquery_orm::Entity;
+
+    #[derive(Entity, Debug)]
+    #[table(name = "Customers", soft_delete = "IsDeleted")]
+    pub struct Customer {
+        #[key(name = "CustomerId")]
+        pub customer_id: i32,
+        #[column(name = "Name")]
+        pub name: String,
+    }
+}
+
+mod invoices {
+    use rquery_orm::Entity;
+
+    #[derive(Entity, Debug)]
+    #[table(name = "Invoices")]
+    pub struct Invoice {
+        #[key(name = "InvoiceId")]
+        pub invoice_id: i32,
+        #[column(name = "DeletedAt", soft_delete)]
+        pub deleted_at: Option<chrono::NaiveDateTime>,
+    }
+}
+
+mod bills {
+    use rquery_orm::Entity;
+
+    #[derive(Entity, Debug)]
+    #[table(name = "Bills")]
+    pub struct Bill {
+        #[key(name = "BillId")]
+        pub bill_id: i32,
+        #[column(name = "CustomerId")]
+        pub customer_id: i32,
+        #[column(name = "DeletedAt", soft_delete)]
+        pub deleted_at: Option<chrono::NaiveDateTime>,
+        #[relation(foreign_key = "CustomerId", table = "Customers")]
+        pub customer: Option<super::customers::Customer>,
+    }
+}
+
+#[test]
+fn soft_delete_applies_to_joined_and_included_tables() {
+    use bills::Bill;
+    use customers::Customer;
+
+    let q = || {
+        rquery_orm::JoinQuery::<(Bill, Option<Customer>)>::new(PlaceholderStyle::Dollar).Join(
+            JoinType::Left,
+            rquery_orm::on!(Bill::customer_id == Customer::customer_id),
+        )
+    };
+    let (sql, _) = q().to_sql().unwrap();
+    assert!(sql.ends_with(
+        "FROM Bills LEFT JOIN Customers ON (Bills.CustomerId = Customers.CustomerId) \
+         AND Customers.IsDeleted = FALSE WHERE Bills.DeletedAt IS NULL"
+    ));
+    let (sql, _) = q().OnlyDeleted().to_sql().unwrap();
+    assert!(sql.ends_with(
+        "ON (Bills.CustomerId = Customers.CustomerId) WHERE Bills.DeletedAt IS NOT NULL"
+    ));
+    let (sql, _) = q().WithDeleted().to_sql().unwrap();
+    assert!(sql.ends_with("ON (Bills.CustomerId = Customers.CustomerId)"));
+
+    let q = || Query::<Bill>::new(Bill::TABLE, PlaceholderStyle::AtP).Include(|r| r.customer);
+    let (sql, _) = q().to_sql().unwrap();
+    assert!(sql.ends_with(
+        "LEFT JOIN Customers i0 ON Bills.CustomerId = i0.CustomerId AND i0.IsDeleted = 0 \
+         WHERE Bills.DeletedAt IS NULL"
+    ));
+    let (sql, _) = q().WithDeleted().to_sql().unwrap();
+    assert!(sql.ends_with("LEFT JOIN Customers i0 ON Bills.CustomerId = i0.CustomerId"));
+}
+
+#[test]
+fn soft_deleted_rows_are_filtered() {
+    use customers::Customer;
+    use invoices::Invoice;
+    use rquery_orm::SoftDeleteKind;
+
+    let meta = Customer::table().soft_delete.as_ref().unwrap();
+    assert_eq!(
+        (meta.column, meta.kind),
+        ("IsDeleted", SoftDeleteKind::Flag)
+    );
+    let meta = Invoice::table().soft_delete.as_ref().unwrap();
+    assert_eq!(
+        (meta.column, meta.kind),
+        ("DeletedAt", SoftDeleteKind::Timestamp)
+    );
+
+    let c = Customer::cols();
+    let (sql, _) = Query::<Customer>::new(Customer::TABLE, PlaceholderStyle::Dollar)
+        .Where(c.name.eq("Ana").or(c.name.eq("Luis")))
+        .to_sql()
+        .unwrap();
+    assert_eq!(
+        sql,
+        "SELECT * FROM Customers WHERE ((Customers.Name = $1) OR (Customers.Name = $2)) AND Customers.IsDeleted = FALSE"
+    );
+    let (sql, _) = Query::<Customer>::new("Customers c", PlaceholderStyle::AtP)
+        .OnlyDeleted()
+        .to_sql()
+        .unwrap();
+    assert_eq!(sql, "SELECT * FROM Customers c WHERE c.IsDeleted = 1");
+    let (sql, _) = Query::<Customer>::new(Customer::TABLE, PlaceholderStyle::AtP)
+        .WithDeleted()
+        .to_sql()
+        .unwrap();
+    assert_eq!(sql, "SELECT * FROM Customers");
+
+    let (sql, _) = Query::<Invoice>::new(Invoice::TABLE, PlaceholderStyle::AtP)
+        .to_sql()
+        .unwrap();
+    assert_eq!(
+        sql,
+        "SELECT * FROM Invoices WHERE Invoices.DeletedAt IS NULL"
+    );
+}
@@ -0,0 +1,173 @@
+use rquery_orm::{Entity, HasRelations, PlaceholderStyle, Query};
+
+mod countries {
+    use rquery_orm::Entity;
+
+    #[derive(Entity, Debug, Clone)]
+    #[table(name = "Countries")]
+    pub struct Country {
+        #[key(name = "CountryId")]
+        pub country_id: String,
+        #[column(name = "Name")]
+        pub name: String,
+        #[relation(foreign_key = "CountryId", table = "Employees")]
+        pub employees: Vec<super::employees::Employee>,
+    }
+}
+
+mod employees {
+    use rquery_orm::Entity;
+
+    #[derive(Entity, Debug, Clone)]
+    #[table(name = "Employees")]
+    pub struct Employee {
+        #[key(name = "EmployeeId")]
+        pub employee_id: i32,
+        #[column(name = "FirstName")]
+        pub first_name: String,
+        #[column(name = "CountryId")]
+        pub country_id: String,
+        #[relation(foreign_key = "CountryId", table = "Countries")]
+        pub country: Option<super::countries::Country>,
+        #[relation(
+            table = "Skills",
+            through = "EmployeeSkills",
+            local_key = "EmployeeId",
+            remote_key = "SkillId"
+        )]
+        pub skills: Vec<super::skills::Skill>,
+    }
+}
+
+mod skills {
+    use rquery_orm::Entity;
+
+    #[derive(Entity, Debug, Clone)]
+    #[table(name = "Skills")]
+    pub struct Skill {
+        #[key(name = "SkillId")]
+        pub skill_id: i32,
+        #[column(name = "Name")]
+        pub name: String,
+    }
+}
+
+use countries::Country;
+use employees::Employee;
+
+#[test]
+fn relation_descriptors_point_at_metadata() {
+    let rels = Employee::relations();
+    assert_eq!(rels.country.meta.name, "country");
+    assert_eq!(rels.country.meta.foreign_key, "CountryId");
+    assert_eq!(Country::relations().employees.meta.table, "Employees");
+    assert_eq!(Employee::table().columns.len(), 3);
+}
+
+#[test]
+fn belongs_to_include_joins_related_table() {
+    let q = Query::<Employee>::new(Employee::TABLE, PlaceholderStyle::Dollar)
+        .Include(|r| r.country)
+        .Top(5);
+    let (sql, params) = q.to_sql().unwrap();
+    assert_eq!(
+        sql,
+        "SELECT Employees.*, i0.CountryId AS i0_CountryId, i0.Name AS i0_Name FROM Employees LEFT JOIN Countries i0 ON Employees.CountryId = i0.CountryId LIMIT 5"
+    );
+    assert!(params.is_empty());
+}
+
+#[test]
+fn belongs_to_include_uses_table_alias() {
+    let q = Query::<Employee>::new("Employees E", PlaceholderStyle::AtP).Include(|r| r.country);
+    let (sql, _) = q.to_sql().unwrap();
+    assert!(sql.starts_with("SELECT E.*, i0.CountryId AS i0_CountryId"));
+    assert!(sql.ends_with("LEFT JOIN Countries i0 ON E.CountryId = i0.CountryId"));
+}
+
+#[test]
+fn has_many_include_does_not_change_parent_query() {
+    let q =
+        Query::<Country>::new(Country::TABLE, PlaceholderStyle::Dollar).Include(|r| r.employees);
+    let (sql, _) = q.to_sql().unwrap();
+    assert_eq!(sql, "SELECT * FROM Countries");
+}
+
+#[test]
+fn many_to_many_relation_records_join_table() {
+    let skills = Employee::relations().skills;
+    assert_eq!(skills.meta.through, Some("EmployeeSkills"));
+    assert_eq!(skills.meta.local_key, Some("EmployeeId"));
+    assert_eq!(skills.meta.remote_key, Some("SkillId"));
+
+    // the join table is only read by the follow-up query
+    let q = Query::<Employee>::new(Employee::TABLE, PlaceholderStyle::Dollar).Include(|r| r.skills);
+    let (sql, _) = q.to_sql().unwrap();
+    assert_eq!(sql, "SELECT * FROM Employees");
+}
+
+mod keyless {
+    use rquery_orm::Entity;
+
+    #[derive(Entity, Debug, Clone)]
+    #[table(name = "Regions")]
+    pub struct Region {
+        #[column(name = "Name")]
+        pub name: String,
+    }
+}
+
+mod offices {
+    use rquery_orm::Entity;
+
+    #[derive(Entity, Debug, Clone)]
+    #[table(name = "Offices")]
+    pub struct Office {
+        #[key(name = "OfficeId")]
+        pub office_id: i32,
+        #[relation(foreign_key = "RegionName", table = "Regions")]
+        pub region: Option<super::keyless::Region>,
+    }
+}
+
+#[test]
+fn belongs_to_without_target_key_is_not_joined() {
+    let q = Query::<offices::Office>::new(offices::Office::TABLE, PlaceholderStyle::Dollar)
+        .Include(|r| r.region);
+    let (sql, _) = q.to_sql().unwrap();
+    assert_eq!(sql, "SELECT * FROM Offices");
+}
+
+mod sites {
+    use rquery_orm::Entity;
+
+    #[derive(Entity, Debug, Clone)]
+    #[table(name = "Sites")]
+    pub struct Site {
+        #[key(name = "RegionName")]
+        pub region_name: String,
+        #[key(name = "SiteNo")]
+        pub site_no: i32,
+    }
+}
+
+mod desks {
+    use rquery_orm::Entity;
+
+    #[derive(Entity, Debug, Clone)]
+    #[table(name = "Desks")]
+    pub struct Desk {
+        #[key(name = "DeskId")]
+        pub desk_id: i32,
+        #[relation(foreign_key = "RegionName", table = "Sites")]
+        pub site: Option<super::sites::Site>,
+    }
+}
+
+#[test]
+fn belongs_to_composite_key_is_not_joined_on_part_of_it() {
+    let q =
+        Query::<desks::Desk>::new(desks::Desk::TABLE, PlaceholderStyle::Dollar).Include(|r| r.site);
+    let (sql, _) = q.to_sql().unwrap();
+    assert_eq!(sql, "SELECT * FROM Desks");
+}
@@ -0,0 +1,41 @@
+use rquery_orm::telemetry::{operation, sanitize};
+use rquery_orm::StatementKind;
+
+#[test]
+fn statements_are_sanitized_but_keep_placeholders() {
+    assert_eq!(
+        sanitize(
+            "SELECT TOP 10 * FROM Employees WHERE Name = 'O''Brien' AND Age > @P1 AND t2.Id = $12"
+        ),
+        "SELECT TOP ? * FROM Employees WHERE Name = ? AND Age > @P1 AND t2.Id = $12"
+    );
+    assert_eq!(
+        sanitize("SELECT JSON_VALUE(Data, '$.a') FROM T LIMIT 5 OFFSET 2.5"),
+        "SELECT JSON_VALUE(Data, ?) FROM T LIMIT ? OFFSET ?"
+    );
+}
+
+#[test]
+fn operation_follows_leading_keyword() {
+    assert_eq!(operation(StatementKind::Query, "select * from T"), "SELECT");
+    assert_eq!(
+        operation(StatementKind::Execute, "  UPDATE T SET A = @P1"),
+        "UPDATE"
+    );
+    assert_eq!(
+        operation(
+            StatementKind::Query,
+            "WITH x AS (SELECT 1) DELETE FROM T WHERE Id IN (SELECT * FROM x)"
+        ),
+        "DELETE"
+    );
+    assert_eq!(
+        operation(StatementKind::Execute, "TRUNCATE TABLE T"),
+        "EXECUTE"
+    );
+    assert_eq!(
+        operation(StatementKind::Batch, "SELECT 1; SELECT 2"),
+        "BATCH"
+    );
+    assert_eq!(operation(StatementKind::Procedure, "EXEC dbo.Get"), "CALL");
+}